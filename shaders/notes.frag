@@ -1,5 +1,7 @@
 #version 450
 
+#include <srgb.glsl>
+
 layout(location = 0) in vec3 frag_color;
 layout(location = 1) in vec2 frag_tex_coord;
 layout(location = 2) in vec2 v_note_size;
@@ -7,32 +9,73 @@ layout(location = 3) in vec2 win_size;
 
 layout(location = 0) out vec4 out_color;
 
-const float border = 1.0;
+layout(set = 0, binding = 1) uniform NoteStyle {
+    float border_width;
+    float border_darken;
+    float gradient;
+    float corner_radius;
+    float shading;
+    float glow;
+    uint use_texture;
+    float alpha;
+    // See srgb.glsl
+    uint encode_srgb;
+} style;
+
+layout(set = 0, binding = 2) uniform sampler2D note_texture;
 
 void main() {
     vec2 v_uv = frag_tex_coord;
-    vec3 color = frag_color;
-    float aspect = win_size.y / win_size.x;
+    // The note colors are sRGB, shading and blending is done on linear colors
+    vec3 base_color = srgb_to_linear(frag_color);
+    vec3 color = base_color;
+
+    if(style.use_texture != 0)
+    {
+        color *= texture(note_texture, v_uv).rgb;
+    }
 
     float lighten = cos(v_uv.x + 1) + 3 / 4;
-    color += vec3(lighten, lighten, lighten) * 0.40;
+    color += vec3(lighten, lighten, lighten) * style.shading;
 
-    float horiz_width_pixels = v_note_size.x / 2 * win_size.x;
-    float vert_width_pixels = v_note_size.y / 2 * win_size.y;
+    // Darken towards the end of the note
+    color *= 1 - style.gradient * v_uv.y;
 
-    float horiz_margin = 1 / horiz_width_pixels * border;
-    float vert_margin = 1 / vert_width_pixels * border;
+    vec2 size_pixels = v_note_size / 2 * win_size;
+    vec2 pos_pixels = v_uv * size_pixels;
+
+    // Discard the pixels outside of the rounded corners
+    float radius = min(style.corner_radius, min(size_pixels.x, size_pixels.y) / 2);
+    vec2 corner_dist = max(vec2(radius) - min(pos_pixels, size_pixels - pos_pixels), vec2(0));
+    float corner = length(corner_dist);
+    if(corner > radius)
+    {
+        discard;
+    }
+
+    if(style.glow > 0)
+    {
+        // Brighten the center of the note
+        float center = 1 - abs(v_uv.x * 2 - 1);
+        color += base_color * style.glow * center * center;
+    }
+
+    vec2 margin = style.border_width / size_pixels;
 
     bool border =
-        v_uv.x < horiz_margin ||
-        v_uv.x > 1 - horiz_margin ||
-        v_uv.y < vert_margin ||
-        v_uv.y > 1 - vert_margin;
+        v_uv.x < margin.x ||
+        v_uv.x > 1 - margin.x ||
+        v_uv.y < margin.y ||
+        v_uv.y > 1 - margin.y ||
+        (corner > 0 && corner > radius - style.border_width);
 
     if(border)
     {
-        color = vec3(frag_color * 0.40); //note edge color
+        color = vec3(base_color * style.border_darken); //note edge color
     }
 
-    out_color = vec4(color, 1.0);
-}
\ No newline at end of file
+    color = encode_output(color, style.encode_srgb);
+
+    // Premultiplied, to blend correctly in the transparent overlap mode
+    out_color = vec4(color * style.alpha, style.alpha);
+}
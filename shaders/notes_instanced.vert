@@ -0,0 +1,164 @@
+#version 450 core
+
+// Expands one note instance into a quad, drawn as a 4 vertex triangle strip.
+// This mirrors notes.geom for devices without geometry shader support.
+
+layout(location = 0) in vec2 start_length;
+layout(location = 1) in uint key_color;
+layout(location = 2) in float start_low;
+layout(location = 3) in uint track_chan;
+
+layout(location = 0) out vec3 frag_color;
+layout(location = 1) out vec2 frag_tex_coord;
+layout(location = 2) out vec2 v_note_size;
+layout(location = 3) out vec2 win_size;
+
+layout(push_constant) uniform PushConstants {
+    float height_time;
+    float win_width;
+    float win_height;
+    uint orientation;
+    // Notes uploaded with absolute times are displaced by the view start,
+    // split into a high and low part to keep precision in long files
+    float view_start_high;
+    float view_start_low;
+    int key_shift;
+    uint overlap_mode;
+    uint black_over_white;
+    // Set to draw the notes at their pitch offsets, between the key columns
+    uint tuned_pitch;
+} consts;
+
+const uint ORIENTATION_DOWN = 0;
+const uint ORIENTATION_UP = 1;
+const uint ORIENTATION_HORIZONTAL = 2;
+
+const uint OVERLAP_HIGHEST_TRACK = 2;
+const uint OVERLAP_LOWEST_TRACK = 3;
+
+// The amount of track/channels that get their own depth, later ones share one
+const float MAX_TRACK_DEPTH = 32000.0;
+
+struct KeyPosition {
+    float left;
+    float right;
+    uint black;
+};
+
+layout(set = 0, binding = 0) uniform Keys {
+    KeyPosition key_positions[256];
+};
+
+// The pitch offsets of the notes in semitones, only bound while they are
+// drawn at their tuned pitch. Notes without one get a single 0 placeholder.
+layout(set = 0, binding = 3) readonly buffer PitchOffsets {
+    float pitch_offsets[];
+};
+
+// The pitch offset of the note at that index in the bound note buffer
+float pitch_offset(uint note_index)
+{
+    return note_index < uint(pitch_offsets.length()) ? pitch_offsets[note_index] : 0.0;
+}
+
+// Maps a position on the key axis and the time axis (both -1 to 1, with -1
+// being the keyboard side) to the screen
+vec4 to_screen(float key_pos, float time_pos, float depth)
+{
+    if(consts.orientation == ORIENTATION_UP)
+    {
+        return vec4(key_pos, time_pos, depth, 1);
+    }
+    else if(consts.orientation == ORIENTATION_HORIZONTAL)
+    {
+        return vec4(time_pos, -key_pos, depth, 1);
+    }
+    else
+    {
+        return vec4(key_pos, -time_pos, depth, 1);
+    }
+}
+
+// The left and right of a note at a fractional key, between the columns of
+// the keys around it
+vec2 tuned_position(float key)
+{
+    float low = clamp(floor(key), 0.0, 255.0);
+    float high = min(low + 1.0, 255.0);
+    float t = clamp(key - low, 0.0, 1.0);
+
+    KeyPosition low_position = key_positions[uint(low)];
+    KeyPosition high_position = key_positions[uint(high)];
+    return vec2(
+        mix(low_position.left, high_position.left, t),
+        mix(low_position.right, high_position.right, t)
+    );
+}
+
+// Notes with a lower depth are drawn on top. The black and white key notes
+// are split into two layers, and the track modes order the notes in a layer.
+// Notes with the same depth are ordered by the draw order.
+float note_depth(KeyPosition key_position, uint track_chan)
+{
+    bool on_top = (key_position.black != 0) == (consts.black_over_white != 0);
+    float layer = on_top ? 0.0 : 0.5;
+    float track = min(float(track_chan), MAX_TRACK_DEPTH);
+
+    if(consts.overlap_mode == OVERLAP_HIGHEST_TRACK)
+    {
+        return layer + (MAX_TRACK_DEPTH - track) / 65535.0;
+    }
+    else if(consts.overlap_mode == OVERLAP_LOWEST_TRACK)
+    {
+        return layer + track / 65535.0;
+    }
+    else
+    {
+        return layer;
+    }
+}
+
+void main()
+{
+    float start_time = (start_length.x - consts.view_start_high) + (start_low - consts.view_start_low);
+    float start = start_time / consts.height_time;
+    float end = start + start_length.y / consts.height_time;
+    start = start * 2 - 1;
+    end = end * 2 - 1;
+
+    uint key = uint(int(key_color & 0xFF) + consts.key_shift);
+    uint col_int = key_color >> 8;
+
+    float col_r = float((col_int >> 16) & 0xFF) / 255.0;
+    float col_g = float((col_int >> 8) & 0xFF) / 255.0;
+    float col_b = float((col_int >> 0) & 0xFF) / 255.0;
+
+    KeyPosition key_position = key_positions[key];
+
+    float left = key_position.left * 2 - 1;
+    float right = key_position.right * 2 - 1;
+
+    if(consts.tuned_pitch != 0)
+    {
+        vec2 tuned = tuned_position(float(key) + pitch_offset(uint(gl_InstanceIndex)));
+        left = tuned.x * 2 - 1;
+        right = tuned.y * 2 - 1;
+    }
+
+    float depth = note_depth(key_position, track_chan);
+
+    // Same corner order as the geometry shader emits them
+    vec2 corner = vec2(gl_VertexIndex & 1, gl_VertexIndex >> 1);
+
+    vec2 win_size_out = vec2(consts.win_width, consts.win_height);
+    if(consts.orientation == ORIENTATION_HORIZONTAL)
+    {
+        win_size_out = win_size_out.yx;
+    }
+
+    gl_Position = to_screen(mix(left, right, corner.x), mix(start, end, corner.y), depth);
+    frag_color = vec3(col_r, col_g, col_b);
+    frag_tex_coord = corner;
+    v_note_size = vec2(right - left, end - start);
+    win_size = win_size_out;
+}
@@ -0,0 +1,30 @@
+// Shared by the shaders writing to the window. Colors are shaded and blended
+// as linear values. sRGB and float targets take them as they are, other
+// targets store the values as they are written, so the shaders have to encode
+// them to sRGB themselves. `encode_srgb` is set from `needs_srgb_encoding` for
+// the target format.
+
+// The input colors are sRGB
+vec3 srgb_to_linear(vec3 color)
+{
+    vec3 low = color / 12.92;
+    vec3 high = pow((color + 0.055) / 1.055, vec3(2.4));
+    return mix(high, low, lessThanEqual(color, vec3(0.04045)));
+}
+
+vec3 linear_to_srgb(vec3 color)
+{
+    vec3 low = color * 12.92;
+    vec3 high = 1.055 * pow(color, vec3(1 / 2.4)) - 0.055;
+    return mix(high, low, lessThanEqual(color, vec3(0.0031308)));
+}
+
+// The linear color as it has to be written to the target
+vec3 encode_output(vec3 color, uint encode_srgb)
+{
+    if(encode_srgb != 0)
+    {
+        return linear_to_srgb(clamp(color, 0, 1));
+    }
+    return color;
+}
@@ -0,0 +1,36 @@
+use std::env;
+
+/// The command line options
+#[derive(Debug, Default)]
+pub struct Args {
+    /// The GPU to use, either its index or a part of its name
+    pub gpu: Option<String>,
+    /// Print the Vulkan devices and exit
+    pub vulkan_info: bool,
+}
+
+impl Args {
+    pub fn parse() -> Self {
+        let mut args = Args::default();
+        let mut iter = env::args().skip(1);
+
+        while let Some(arg) = iter.next() {
+            match arg.as_str() {
+                "--gpu" => match iter.next() {
+                    Some(gpu) => args.gpu = Some(gpu),
+                    None => println!("--gpu needs a device index or name"),
+                },
+                "--vulkan-info" => args.vulkan_info = true,
+                _ => {
+                    if let Some(gpu) = arg.strip_prefix("--gpu=") {
+                        args.gpu = Some(gpu.to_string());
+                    } else {
+                        println!("Unknown argument: {}", arg);
+                    }
+                }
+            }
+        }
+
+        args
+    }
+}
@@ -0,0 +1,134 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+/// The fraction of the notes per second budget that can be left before the
+/// limiter considers the receiver overloaded
+const OVERLOAD_FRACTION: f64 = 0.5;
+
+/// The synth limiter shared between the GUI and the playback thread
+pub type SharedNoteLimiter = Arc<Mutex<NoteLimiter>>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct LimiterSettings {
+    /// The maximum amount of NoteOn events passed on per second
+    pub max_notes_per_second: Option<u32>,
+    /// NoteOns below this velocity are dropped while overloaded. Only has an
+    /// effect when the notes per second are limited.
+    pub overload_velocity_threshold: u8,
+    /// The maximum amount of notes held on a single key
+    pub max_voices_per_key: Option<u16>,
+}
+
+/// Decides which NoteOn events get passed on to a receiver. NoteOffs that
+/// belong to a dropped NoteOn are dropped as well, so the receiver never gets a
+/// NoteOff for a note it didn't play.
+pub struct NoteLimiter {
+    pub settings: LimiterSettings,
+    tokens: f64,
+    last_refill: Instant,
+    dropped_notes: Vec<u16>,
+    active_notes: Vec<u16>,
+    dropped_count: u64,
+}
+
+impl Default for NoteLimiter {
+    fn default() -> Self {
+        NoteLimiter::new(Default::default())
+    }
+}
+
+impl NoteLimiter {
+    pub fn new(settings: LimiterSettings) -> Self {
+        NoteLimiter {
+            settings,
+            tokens: settings.max_notes_per_second.unwrap_or(0) as f64,
+            last_refill: Instant::now(),
+            dropped_notes: vec![0; 16 * 128],
+            active_notes: vec![0; 128],
+            dropped_count: 0,
+        }
+    }
+
+    /// The amount of NoteOns that were dropped since the limiter was created
+    /// or the count was last reset
+    pub fn dropped_count(&self) -> u64 {
+        self.dropped_count
+    }
+
+    pub fn reset_dropped_count(&mut self) {
+        self.dropped_count = 0;
+    }
+
+    fn refill(&mut self, max: u32) {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.last_refill = now;
+        self.tokens = (self.tokens + elapsed * max as f64).min(max as f64);
+    }
+
+    fn allow_note_on(&mut self, key: usize, velocity: u8) -> bool {
+        if let Some(max_voices) = self.settings.max_voices_per_key {
+            if self.active_notes[key] >= max_voices {
+                return false;
+            }
+        }
+
+        if let Some(max) = self.settings.max_notes_per_second {
+            self.refill(max);
+
+            let overloaded = self.tokens < max as f64 * OVERLOAD_FRACTION;
+            if self.tokens < 1.0
+                || (overloaded && velocity < self.settings.overload_velocity_threshold)
+            {
+                return false;
+            }
+
+            self.tokens -= 1.0;
+        }
+
+        true
+    }
+
+    /// Returns true if the packed short message should be passed on
+    pub fn allow(&mut self, data: u32) -> bool {
+        let head = data as u8 & 0xF0;
+        let key = ((data >> 8) & 0x7F) as usize;
+        let index = ((data & 0x0F) << 7) as usize | key;
+        let velocity = (data >> 16) as u8;
+
+        match head {
+            0x90 if velocity > 0 => {
+                if self.allow_note_on(key, velocity) {
+                    self.active_notes[key] = self.active_notes[key].saturating_add(1);
+                    true
+                } else {
+                    self.dropped_notes[index] = self.dropped_notes[index].saturating_add(1);
+                    self.dropped_count += 1;
+                    false
+                }
+            }
+            0x80 | 0x90 => {
+                if self.dropped_notes[index] > 0 {
+                    self.dropped_notes[index] -= 1;
+                    false
+                } else {
+                    self.active_notes[key] = self.active_notes[key].saturating_sub(1);
+                    true
+                }
+            }
+            _ => true,
+        }
+    }
+
+    /// Forgets all held and dropped notes, used when the receiver gets reset
+    pub fn reset(&mut self) {
+        for dropped in self.dropped_notes.iter_mut() {
+            *dropped = 0;
+        }
+        for active in self.active_notes.iter_mut() {
+            *active = 0;
+        }
+    }
+}
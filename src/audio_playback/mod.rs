@@ -9,13 +9,31 @@ use xsynth_realtime::{config::XSynthRealtimeConfig, RealtimeEventSender, Realtim
 
 use native_dialog::{FileDialog};
 
+use self::{limiter::SharedNoteLimiter, thru::SharedMIDIThru, transform::SharedAudioTransform};
+
+pub mod limiter;
+pub mod thru;
+pub mod transform;
+
+/// Playback state that the GUI can change while a file is playing
+#[derive(Clone, Default)]
+pub struct SharedPlayerState {
+    pub thru: SharedMIDIThru,
+    pub limiter: SharedNoteLimiter,
+    pub transform: SharedAudioTransform,
+}
+
 pub struct SimpleTemporaryPlayer {
     //kdmapi: KDMAPIStream,
     sender: RealtimeEventSender,
+    shared: SharedPlayerState,
 }
 
 impl SimpleTemporaryPlayer {
-    pub fn new() -> Self {
+    pub fn new(shared: SharedPlayerState) -> Self {
+        // A player is made for every opened file, the count is per file
+        shared.limiter.lock().unwrap().reset_dropped_count();
+
         let config = XSynthRealtimeConfig {
             render_window_ms: 1000.0 / 60.0,
             use_threadpool: true,
@@ -56,22 +74,50 @@ impl SimpleTemporaryPlayer {
         Box::leak(Box::new(synth));
 
         //let kdmapi = KDMAPI.open_stream();
-        SimpleTemporaryPlayer { sender }//{ kdmapi, sender }
+        SimpleTemporaryPlayer { sender, shared }//{ kdmapi, sender }
     }
 
-    pub fn push_events(&mut self, data: impl Iterator<Item = u32>) {
-        for e in data {
-            self.push_event(e);
+    fn send_events(
+        sender: &mut RealtimeEventSender,
+        shared: &SharedPlayerState,
+        events: impl Iterator<Item = u32>,
+    ) {
+        let mut limiter = shared.limiter.lock().unwrap();
+        let mut thru = shared.thru.lock().unwrap();
+        for e in events {
+            if limiter.allow(e) {
+                sender.send_event_u32(e);
+            }
+            if let Some(thru) = thru.as_mut() {
+                thru.send_event(e);
+            }
         }
     }
 
+    pub fn push_events(&mut self, data: impl Iterator<Item = u32>) {
+        let mut transform = self.shared.transform.lock().unwrap();
+        let changes = transform.take_changes(false);
+        let events = changes
+            .into_iter()
+            .chain(data.filter_map(|e| transform.apply(e)));
+        Self::send_events(&mut self.sender, &self.shared, events);
+    }
+
     pub fn push_event(&mut self, data: u32) {
-        self.sender.send_event_u32(data);
+        self.push_events(std::iter::once(data));
         // self.kdmapi.send_direct_data(data);
     }
 
     pub fn reset(&mut self) {
         self.sender.reset_synth();
+        self.shared.limiter.lock().unwrap().reset();
+        if let Some(thru) = self.shared.thru.lock().unwrap().as_mut() {
+            thru.reset();
+        }
+
+        // Forced programs have to be re-applied after the synth forgot them
+        let changes = self.shared.transform.lock().unwrap().take_changes(true);
+        Self::send_events(&mut self.sender, &self.shared, changes.into_iter());
         // self.kdmapi.reset();
     }
 }
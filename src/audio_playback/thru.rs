@@ -0,0 +1,117 @@
+use std::sync::{Arc, Mutex};
+
+use midir::{MidiOutput, MidiOutputConnection};
+
+use super::limiter::NoteLimiter;
+
+const CLIENT_NAME: &str = "Wasabi";
+const PORT_NAME: &str = "Wasabi Thru";
+
+const CC_RESET_ALL_CONTROLLERS: u8 = 121;
+const CC_ALL_NOTES_OFF: u8 = 123;
+
+/// The MIDI thru output shared between the GUI and the playback thread
+pub type SharedMIDIThru = Arc<Mutex<Option<MIDIThru>>>;
+
+/// Forwards the played events to an external MIDI output port
+pub struct MIDIThru {
+    connection: MidiOutputConnection,
+    port_name: String,
+    /// The output channel of each input channel
+    channel_map: [u8; 16],
+    pub limiter: NoteLimiter,
+}
+
+impl MIDIThru {
+    /// Lists the names of the MIDI output ports that can be opened with
+    /// [`MIDIThru::open_port`]
+    pub fn list_ports() -> Vec<String> {
+        let output = match MidiOutput::new(CLIENT_NAME) {
+            Ok(output) => output,
+            Err(_) => return Vec::new(),
+        };
+
+        output
+            .ports()
+            .iter()
+            .map(|port| output.port_name(port).unwrap_or_else(|_| "Unknown".to_string()))
+            .collect()
+    }
+
+    pub fn open_port(index: usize) -> Result<Self, String> {
+        let output = MidiOutput::new(CLIENT_NAME).map_err(|e| e.to_string())?;
+
+        let ports = output.ports();
+        let port = ports.get(index).ok_or("MIDI output port no longer exists")?;
+        let port_name = output.port_name(port).map_err(|e| e.to_string())?;
+
+        let connection = output
+            .connect(port, PORT_NAME)
+            .map_err(|e| e.to_string())?;
+
+        Ok(MIDIThru {
+            connection,
+            port_name,
+            channel_map: [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15],
+            limiter: NoteLimiter::default(),
+        })
+    }
+
+    pub fn port_name(&self) -> &str {
+        &self.port_name
+    }
+
+    pub fn channel_map(&self) -> &[u8; 16] {
+        &self.channel_map
+    }
+
+    /// Sends a channel to another output channel. The notes held on the old
+    /// output channel are released there, their NoteOffs will go to the new one.
+    pub fn map_channel(&mut self, channel: usize, target: u8) {
+        let old = self.channel_map[channel];
+        if old == target {
+            return;
+        }
+
+        self.channel_map[channel] = target;
+        self.connection.send(&[0xB0 | old, CC_ALL_NOTES_OFF, 0]).ok();
+    }
+
+    /// Sends a packed short message, in the same format as the synth receives
+    pub fn send_event(&mut self, data: u32) {
+        if !self.limiter.allow(data) {
+            return;
+        }
+
+        let mut head = data as u8;
+        // System messages carry no channel in their low bits
+        if (0x80..0xF0).contains(&head) {
+            let channel = self.channel_map[(head & 0x0F) as usize] & 0x0F;
+            head = (head & 0xF0) | channel;
+        }
+        let message = [head, (data >> 8) as u8, (data >> 16) as u8];
+
+        let length = match head {
+            0xC0..=0xDF | 0xF1 | 0xF3 => 2,
+            0xF6..=0xFF => 1,
+            _ => 3,
+        };
+
+        if let Err(e) = self.connection.send(&message[..length]) {
+            println!("Failed to send MIDI thru event: {}", e);
+        }
+    }
+
+    /// Silences the output port, mirroring a synth reset on seek
+    pub fn reset(&mut self) {
+        self.limiter.reset();
+
+        for channel in 0..16 {
+            let head = 0xB0 | channel;
+            self.connection
+                .send(&[head, CC_ALL_NOTES_OFF, 0])
+                .and_then(|_| self.connection.send(&[head, CC_RESET_ALL_CONTROLLERS, 0]))
+                .ok();
+        }
+    }
+}
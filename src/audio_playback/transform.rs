@@ -0,0 +1,138 @@
+use std::sync::{Arc, Mutex};
+
+const CC_BANK_SELECT: u8 = 0;
+const CC_BANK_SELECT_LSB: u8 = 32;
+const CC_ALL_NOTES_OFF: u8 = 123;
+
+/// The audio transform shared between the GUI and the playback thread
+pub type SharedAudioTransform = Arc<Mutex<AudioTransform>>;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ForcedProgram {
+    pub bank: Option<u8>,
+    pub program: u8,
+}
+
+/// Transforms applied to the events between the parsed file and the synth
+#[derive(Debug, Clone, PartialEq)]
+pub struct AudioTransform {
+    /// Transpose in semitones
+    pub transpose: i8,
+    /// Exponent applied to the normalized velocity before scaling
+    pub velocity_curve: f32,
+    pub velocity_scale: f32,
+    /// Notes outside of this range (after transposing) are not played
+    pub low_key: u8,
+    pub high_key: u8,
+    pub forced_programs: [Option<ForcedProgram>; 16],
+    /// Whether the piano roll should be shifted by the transpose as well
+    pub show_transpose: bool,
+
+    changed: bool,
+}
+
+impl Default for AudioTransform {
+    fn default() -> Self {
+        AudioTransform {
+            transpose: 0,
+            velocity_curve: 1.0,
+            velocity_scale: 1.0,
+            low_key: 0,
+            high_key: 127,
+            forced_programs: [None; 16],
+            show_transpose: false,
+            changed: false,
+        }
+    }
+}
+
+impl AudioTransform {
+    /// Marks the transform as changed, so that held notes get released and the
+    /// forced programs get sent with the next events
+    pub fn mark_changed(&mut self) {
+        self.changed = true;
+    }
+
+    /// Returns the events that need to be sent after the transform was changed
+    /// or the synth was reset, and clears the changed flag
+    pub fn take_changes(&mut self, after_reset: bool) -> Vec<u32> {
+        let mut events = Vec::new();
+
+        if self.changed && !after_reset {
+            // Held notes would otherwise get stuck on their old keys
+            for channel in 0..16 {
+                events.push(pack(0xB0 | channel, CC_ALL_NOTES_OFF, 0));
+            }
+        }
+
+        if self.changed || after_reset {
+            for (channel, forced) in self.forced_programs.iter().enumerate() {
+                if let Some(forced) = forced {
+                    events.extend(forced_program_events(channel as u8, forced));
+                }
+            }
+        }
+
+        self.changed = false;
+        events
+    }
+
+    fn transform_velocity(&self, velocity: u8) -> u8 {
+        let normalized = velocity as f32 / 127.0;
+        let scaled = normalized.powf(self.velocity_curve) * self.velocity_scale * 127.0;
+        scaled.round().clamp(1.0, 127.0) as u8
+    }
+
+    /// Transforms a packed short message, returning None if it should be dropped
+    pub fn apply(&self, data: u32) -> Option<u32> {
+        let head = data as u8;
+        let channel = (head & 0x0F) as usize;
+        let data1 = (data >> 8) as u8;
+        let data2 = (data >> 16) as u8;
+
+        match head & 0xF0 {
+            0x80 | 0x90 | 0xA0 => {
+                let key = data1 as i16 + self.transpose as i16;
+                if key < self.low_key as i16 || key > self.high_key as i16 {
+                    return None;
+                }
+
+                let data2 = if head & 0xF0 == 0x90 && data2 > 0 {
+                    self.transform_velocity(data2)
+                } else {
+                    data2
+                };
+
+                Some(pack(head, key as u8, data2))
+            }
+            // Only a forced bank replaces the file's bank selects
+            0xB0 if data1 == CC_BANK_SELECT || data1 == CC_BANK_SELECT_LSB => {
+                match self.forced_programs[channel] {
+                    Some(ForcedProgram { bank: Some(_), .. }) => None,
+                    _ => Some(data),
+                }
+            }
+            0xC0 => match self.forced_programs[channel] {
+                Some(_) => None,
+                None => Some(data),
+            },
+            _ => Some(data),
+        }
+    }
+}
+
+fn pack(head: u8, data1: u8, data2: u8) -> u32 {
+    head as u32 | (data1 as u32) << 8 | (data2 as u32) << 16
+}
+
+fn forced_program_events(channel: u8, forced: &ForcedProgram) -> Vec<u32> {
+    let mut events = Vec::new();
+    if let Some(bank) = forced.bank {
+        events.push(pack(0xB0 | channel, CC_BANK_SELECT, bank));
+    }
+    events.push(pack(0xC0 | channel, forced.program, 0));
+    events
+}
+
+#[cfg(test)]
+mod tests;
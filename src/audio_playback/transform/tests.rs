@@ -0,0 +1,47 @@
+use super::{pack, AudioTransform, ForcedProgram};
+
+#[test]
+fn program_forced_without_bank() {
+    let mut transform = AudioTransform::default();
+    transform.forced_programs[2] = Some(ForcedProgram {
+        bank: None,
+        program: 40,
+    });
+
+    // The file's bank selects still apply, its program changes don't
+    assert_eq!(transform.apply(pack(0xB2, 0, 5)), Some(pack(0xB2, 0, 5)));
+    assert_eq!(transform.apply(pack(0xB2, 32, 1)), Some(pack(0xB2, 32, 1)));
+    assert_eq!(transform.apply(pack(0xC2, 3, 0)), None);
+
+    transform.mark_changed();
+    let events = transform.take_changes(true);
+    assert_eq!(events, vec![pack(0xC2, 40, 0)]);
+}
+
+#[test]
+fn program_forced_with_bank() {
+    let mut transform = AudioTransform::default();
+    transform.forced_programs[2] = Some(ForcedProgram {
+        bank: Some(8),
+        program: 40,
+    });
+
+    assert_eq!(transform.apply(pack(0xB2, 0, 5)), None);
+    assert_eq!(transform.apply(pack(0xB2, 32, 1)), None);
+    assert_eq!(transform.apply(pack(0xC2, 3, 0)), None);
+
+    let events = transform.take_changes(true);
+    assert_eq!(events, vec![pack(0xB2, 0, 8), pack(0xC2, 40, 0)]);
+}
+
+#[test]
+fn other_channels_are_not_forced() {
+    let mut transform = AudioTransform::default();
+    transform.forced_programs[2] = Some(ForcedProgram {
+        bank: Some(8),
+        program: 40,
+    });
+
+    assert_eq!(transform.apply(pack(0xB3, 0, 5)), Some(pack(0xB3, 0, 5)));
+    assert_eq!(transform.apply(pack(0xC3, 3, 0)), Some(pack(0xC3, 3, 0)));
+}
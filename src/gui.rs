@@ -1,10 +1,14 @@
 use std::sync::Arc;
 
 use egui_winit_vulkano::Gui;
-use vulkano::device::{Device, Queue};
+use vulkano::{
+    device::{Device, Queue},
+    swapchain::{ColorSpace, PresentMode},
+};
 
-use crate::renderer::swapchain::SwapchainFrame;
+use crate::renderer::{gpu::GpuInfo, swapchain::SwapchainFrame};
 
+pub mod render_pass;
 pub mod window;
 
 pub struct GuiState<'a> {
@@ -18,4 +22,11 @@ pub struct GuiRenderer<'a> {
     pub device: Arc<Device>,
     pub queue: Arc<Queue>,
     pub format: vulkano::format::Format,
+    pub color_space: ColorSpace,
+    pub present_modes: Vec<PresentMode>,
+    pub gpus: Vec<GpuInfo>,
+    /// The GPU that is in use
+    pub gpu: GpuInfo,
+    /// The names of the monitors that fullscreen can be shown on
+    pub monitors: Vec<String>,
 }
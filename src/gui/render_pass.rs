@@ -0,0 +1,93 @@
+use std::sync::Arc;
+
+use egui_winit_vulkano::Gui;
+use vulkano::{
+    command_buffer::{AutoCommandBufferBuilder, CommandBufferUsage, SubpassContents},
+    device::Queue,
+    format::Format,
+    image::{view::ImageView, ImageAccess, SwapchainImage},
+    render_pass::{Framebuffer, FramebufferCreateInfo, RenderPass, Subpass},
+    sync::GpuFuture,
+};
+use winit::window::Window;
+
+/// The render pass the GUI is drawn in. It is created from the swapchain
+/// format, so that egui always renders to the format that was picked for the
+/// surface instead of choosing its own.
+pub struct GuiRenderPass {
+    gfx_queue: Arc<Queue>,
+    render_pass: Arc<RenderPass>,
+}
+
+impl GuiRenderPass {
+    pub fn new(gfx_queue: Arc<Queue>, format: Format) -> Self {
+        let render_pass = vulkano::single_pass_renderpass!(gfx_queue.device().clone(),
+            attachments: {
+                color: {
+                    load: Clear,
+                    store: Store,
+                    format: format,
+                    samples: 1,
+                }
+            },
+            pass: {
+                color: [color],
+                depth_stencil: {}
+            }
+        )
+        .unwrap();
+
+        GuiRenderPass {
+            gfx_queue,
+            render_pass,
+        }
+    }
+
+    pub fn subpass(&self) -> Subpass {
+        Subpass::from(self.render_pass.clone(), 0).unwrap()
+    }
+
+    pub fn draw(
+        &self,
+        gui: &mut Gui,
+        before_future: Box<dyn GpuFuture>,
+        final_image: Arc<ImageView<SwapchainImage<Window>>>,
+    ) -> Box<dyn GpuFuture> {
+        let img_dims = final_image.image().dimensions().width_height();
+
+        let framebuffer = Framebuffer::new(
+            self.render_pass.clone(),
+            FramebufferCreateInfo {
+                attachments: vec![final_image],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let mut command_buffer_builder = AutoCommandBufferBuilder::primary(
+            self.gfx_queue.device().clone(),
+            self.gfx_queue.family(),
+            CommandBufferUsage::OneTimeSubmit,
+        )
+        .unwrap();
+
+        command_buffer_builder
+            .begin_render_pass(
+                framebuffer,
+                SubpassContents::SecondaryCommandBuffers,
+                vec![[0.0, 0.0, 0.0, 1.0].into()],
+            )
+            .unwrap()
+            .execute_commands(gui.draw_on_subpass_image(img_dims))
+            .unwrap()
+            .end_render_pass()
+            .unwrap();
+
+        let command_buffer = command_buffer_builder.build().unwrap();
+
+        before_future
+            .then_execute(self.gfx_queue.clone(), command_buffer)
+            .unwrap()
+            .boxed()
+    }
+}
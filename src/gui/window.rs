@@ -1,34 +1,124 @@
 mod keyboard;
 mod keyboard_layout;
+mod roll_input;
 mod scene;
 
-use std::{collections::VecDeque,time::{Duration, Instant},env,};
+use std::{collections::VecDeque,ops::RangeInclusive,path::Path,sync::Arc,time::{Duration, Instant},env,};
 
 use egui::{style::Margin, Frame, Label, Visuals, Ui};
 
-use crate::{audio_playback::SimpleTemporaryPlayer,midi::{InRamMIDIFile, MIDIFileBase, MIDIFileUnion},};
+use crate::{audio_playback::{limiter::SharedNoteLimiter, thru::{MIDIThru, SharedMIDIThru}, transform::{ForcedProgram, SharedAudioTransform}, SharedPlayerState, SimpleTemporaryPlayer},midi::{InRamMIDIFile, LiveMIDIFile, MIDIFileBase, MIDIFileUnion},};
 
-use self::{keyboard::GuiKeyboard, scene::GuiRenderScene};
+use self::{keyboard::{GuiKeyboard, KeyboardHeight, KeyboardTheme}, keyboard_layout::{CustomLayout, KeyboardParams, RollOrientation, Scale}, roll_input::{RollInput, ScrubAudio}, scene::{supported_msaa_samples, AntiAliasing, EffectsSettings, GuiRenderScene, KeyColorMode, NoteBufferSettings, NoteOverlap, NoteStyle, OverlapMode, SUPERSAMPLING_FACTORS}};
 
 use super::{GuiRenderer, GuiState};
 
+use crate::{renderer::{gpu::{GpuId, GpuInfo}, pacing::{present_mode_name, FrameSettings}, screenshot::{flatten, save_screenshot, ImageReadback, ScreenshotKind, ScreenshotSettings}, window_mode::{FullscreenMode, FullscreenTarget}}, settings_file::SettingsFile};
+use vulkano::{device::Queue, image::ImageAccess, swapchain::{ColorSpace, PresentMode}};
+
 use native_dialog::{FileDialog};
 
-struct FPS(VecDeque<Instant>);
+/// The side of the remaining space that a panel is attached to
+#[derive(Clone, Copy)]
+enum PanelSide {
+    Top,
+    Bottom,
+    Left,
+    Right,
+}
+
+/// Shows a fixed size panel on the given side of the remaining space
+fn show_panel<R>(
+    ctx: &egui::Context,
+    id: &str,
+    side: PanelSide,
+    size: f32,
+    frame: Frame,
+    add_contents: impl FnOnce(&mut Ui) -> R,
+) -> R {
+    match side {
+        PanelSide::Top => egui::TopBottomPanel::top(id)
+            .height_range(size..=size)
+            .frame(frame)
+            .show(ctx, add_contents)
+            .inner,
+        PanelSide::Bottom => egui::TopBottomPanel::bottom(id)
+            .height_range(size..=size)
+            .frame(frame)
+            .show(ctx, add_contents)
+            .inner,
+        PanelSide::Left => egui::SidePanel::left(id)
+            .resizable(false)
+            .width_range(size..=size)
+            .frame(frame)
+            .show(ctx, add_contents)
+            .inner,
+        PanelSide::Right => egui::SidePanel::right(id)
+            .resizable(false)
+            .width_range(size..=size)
+            .frame(frame)
+            .show(ctx, add_contents)
+            .inner,
+    }
+}
+
+struct FPS {
+    frames: VecDeque<Instant>,
+    frame_times: VecDeque<f64>,
+    /// The last computed frame time percentiles and when they were computed
+    percentiles: Option<([f64; 3], Instant)>,
+}
 
 const FPS_WINDOW: f64 = 0.5;
+/// The amount of frames the frame time percentiles are calculated from
+const FRAME_TIME_HISTORY: usize = 1000;
+/// How often the frame time percentiles are recomputed while they are shown
+const FRAME_TIME_REFRESH: Duration = Duration::from_millis(250);
+
+/// Common keyboard sizes, as (name, first key, last key)
+const KEY_RANGE_PRESETS: [(&str, usize, usize); 3] = [
+    ("88 Keys", 21, 108),
+    ("128 Keys", 0, 127),
+    ("256 Keys", 0, 255),
+];
+
+/// Fits the first and last keys around the used keys, after shifting them
+fn fit_key_range(used: RangeInclusive<usize>, key_shift: i32) -> (usize, usize) {
+    let first = (*used.start() as i32 + key_shift).clamp(0, 254);
+    let last = (*used.end() as i32 + key_shift).clamp(first + 1, 255);
+    (first as usize, last as usize)
+}
+
+/// The file name without the extension, screenshots are named after it
+fn song_name(path: &Path) -> String {
+    path.file_stem()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "Untitled".to_string())
+}
 
 impl FPS {
     fn new() -> Self {
-        Self(VecDeque::new())
+        Self {
+            frames: VecDeque::new(),
+            frame_times: VecDeque::new(),
+            percentiles: None,
+        }
     }
 
     fn update(&mut self) {
-        self.0.push_back(Instant::now());
+        let now = Instant::now();
+        if let Some(last) = self.frames.back() {
+            self.frame_times.push_back((now - *last).as_secs_f64());
+            if self.frame_times.len() > FRAME_TIME_HISTORY {
+                self.frame_times.pop_front();
+            }
+        }
+
+        self.frames.push_back(now);
         loop {
-            if let Some(front) = self.0.front() {
+            if let Some(front) = self.frames.front() {
                 if front.elapsed().as_secs_f64() > FPS_WINDOW {
-                    self.0.pop_front();
+                    self.frames.pop_front();
                 } else {
                     break;
                 }
@@ -39,30 +129,99 @@ impl FPS {
     }
 
     fn get_fps(&self) -> f64 {
-        if self.0.len() == 0 {
+        if self.frames.len() == 0 {
             return 0.0;
         } else {
-            self.0.len() as f64 / self.0.front().unwrap().elapsed().as_secs_f64()
+            self.frames.len() as f64 / self.frames.front().unwrap().elapsed().as_secs_f64()
         }
     }
+
+    /// The median, 95th and 99th percentile frame times in milliseconds, over
+    /// the last [`FRAME_TIME_HISTORY`] frames. Only recomputed every
+    /// [`FRAME_TIME_REFRESH`], so they cost nothing while the stats are hidden.
+    fn get_frame_time_percentiles(&mut self) -> [f64; 3] {
+        if let Some((percentiles, computed)) = self.percentiles {
+            if computed.elapsed() < FRAME_TIME_REFRESH {
+                return percentiles;
+            }
+        }
+
+        let mut sorted: Vec<f64> = self.frame_times.iter().copied().collect();
+        sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
+
+        let percentiles = [50.0, 95.0, 99.0].map(|percentile| {
+            if sorted.is_empty() {
+                return 0.0;
+            }
+            let index = ((sorted.len() - 1) as f64 * percentile / 100.0).round() as usize;
+            sorted[index] * 1000.0
+        });
+        self.percentiles = Some((percentiles, Instant::now()));
+        percentiles
+    }
 }
 
 pub struct GuiWasabiWindow {
     render_scene: GuiRenderScene,
     keyboard_layout: keyboard_layout::KeyboardLayout,
+    /// The layout picked in the settings, the layout is rebuilt when it changes
+    keyboard_params: KeyboardParams,
+    /// The scale coloring the keys, the layout is rebuilt when it changes
+    scale: Option<Scale>,
+    /// Draws the notes at their pitch bend and MTS tuning offsets
+    tuned_pitch: bool,
     keyboard: GuiKeyboard,
+    keyboard_theme: KeyboardTheme,
+    key_color_mode: KeyColorMode,
     midi_file: MIDIFileUnion,
+    /// The open file's name without the extension, used for screenshot names
+    song_name: String,
     fps: FPS,
     notes: usize,
     note_speed: f64,
-    //keyboard_height: f32,
+    roll_input: RollInput,
+    scrub_audio: ScrubAudio,
+    keyboard_height: KeyboardHeight,
     polyphony: usize,
     first_key: usize,
     last_key: usize,
+    visual_transpose: i32,
+    auto_fit_keys: bool,
+    orientation: RollOrientation,
+    mirror_keys: bool,
+    note_style: NoteStyle,
+    overlap: NoteOverlap,
+    antialiasing: AntiAliasing,
+    msaa_options: Vec<u32>,
+    effects: EffectsSettings,
+    note_buffer: NoteBufferSettings,
+    frame_settings: FrameSettings,
+    present_modes: Vec<PresentMode>,
+    gpus: Vec<GpuInfo>,
+    gpu: GpuInfo,
+    /// The GPU saved in the settings, used on the next start
+    preferred_gpu: Option<GpuId>,
+    /// The swapchain format and color space, for showing in the settings
+    output_format: String,
+    /// Whether HDR output is requested in the settings, used on the next start
+    hdr: bool,
+    hdr_active: bool,
     background_color: egui::Color32,
-    //bar_color: egui::Color32,
     is_show_setting: bool,
     is_full_screen: bool,
+    fullscreen_mode: FullscreenMode,
+    /// The monitor to go fullscreen on, `None` for the one the window is on
+    fullscreen_monitor: Option<String>,
+    monitors: Vec<String>,
+    /// Hides the top panel and stats in fullscreen, for clean captures
+    hide_ui_in_fullscreen: bool,
+    /// Used to read screenshots back from the GPU
+    gfx_queue: Arc<Queue>,
+    screenshot: ScreenshotSettings,
+    screenshot_requested: bool,
+    /// Set when a whole window screenshot should be copied from this frame
+    window_screenshot_requested: bool,
+    player_state: SharedPlayerState,
 }
 
 impl GuiWasabiWindow {
@@ -80,29 +239,637 @@ impl GuiWasabiWindow {
             },
         };
 
+        let player_state = SharedPlayerState::default();
+
+        let song_name = song_name(&path);
         let mut midi_file = MIDIFileUnion::InRam(InRamMIDIFile::load_from_file(
             &path.into_os_string().into_string().unwrap(),
-            SimpleTemporaryPlayer::new(),
+            SimpleTemporaryPlayer::new(player_state.clone()),
         ));
 
         midi_file.timer_mut().play();
 
         GuiWasabiWindow {
             render_scene: GuiRenderScene::new(renderer),
-            keyboard_layout: keyboard_layout::KeyboardLayout::new(&Default::default()),
-            keyboard: GuiKeyboard::new(),
+            keyboard_layout: keyboard_layout::KeyboardLayout::new(&Default::default(), None),
+            keyboard_params: Default::default(),
+            scale: None,
+            tuned_pitch: false,
+            keyboard: GuiKeyboard::new(renderer),
+            keyboard_theme: Default::default(),
+            key_color_mode: Default::default(),
             midi_file,
+            song_name,
             fps: FPS::new(),
             notes: 0,
             note_speed: 0.50,
-            //keyboard_height: 70.0 / 760.0,
+            roll_input: RollInput::new(),
+            scrub_audio: Default::default(),
+            keyboard_height: Default::default(),
             polyphony: 0,
             first_key: 0,
             last_key: 127,
+            visual_transpose: 0,
+            auto_fit_keys: false,
+            orientation: Default::default(),
+            mirror_keys: false,
+            note_style: Default::default(),
+            overlap: Default::default(),
+            antialiasing: Default::default(),
+            msaa_options: supported_msaa_samples(renderer.device.physical_device()),
+            effects: Default::default(),
+            note_buffer: Default::default(),
+            frame_settings: Default::default(),
+            present_modes: renderer.present_modes.clone(),
+            gpus: renderer.gpus.clone(),
+            gpu: renderer.gpu.clone(),
+            preferred_gpu: SettingsFile::load().get("gpu"),
+            output_format: format!("{:?}, {:?}", renderer.format, renderer.color_space),
+            hdr: SettingsFile::load().get("hdr").unwrap_or(false),
+            hdr_active: renderer.color_space == ColorSpace::ExtendedSrgbLinear,
             background_color: egui::Color32::from_rgb(0, 0, 0),
-            //bar_color: egui::Color32::from_rgb(127, 0, 0),
             is_show_setting: false,
             is_full_screen: false,
+            fullscreen_mode: FullscreenMode::Borderless,
+            fullscreen_monitor: None,
+            monitors: renderer.monitors.clone(),
+            hide_ui_in_fullscreen: false,
+            gfx_queue: renderer.queue.clone(),
+            screenshot: Default::default(),
+            screenshot_requested: false,
+            window_screenshot_requested: false,
+            player_state,
+        }
+    }
+
+    /// Recreates everything that holds GPU objects, after the renderer was
+    /// rebuilt on a new device. The MIDI file and its playback are kept.
+    pub fn rebuild_renderer(&mut self, renderer: &mut GuiRenderer) {
+        self.render_scene = GuiRenderScene::new(renderer);
+        self.keyboard = GuiKeyboard::new(renderer);
+        self.gfx_queue = renderer.queue.clone();
+        self.msaa_options = supported_msaa_samples(renderer.device.physical_device());
+        if !self.msaa_options.contains(&self.antialiasing.msaa_samples) {
+            self.antialiasing.msaa_samples = 1;
+        }
+        self.present_modes = renderer.present_modes.clone();
+        self.gpus = renderer.gpus.clone();
+        self.gpu = renderer.gpu.clone();
+        self.output_format = format!("{:?}, {:?}", renderer.format, renderer.color_space);
+    }
+
+    /// How the window's frames should be presented and paced
+    pub fn frame_settings(&self) -> &FrameSettings {
+        &self.frame_settings
+    }
+
+    /// The fullscreen state the window should be in, `None` for windowed
+    pub fn fullscreen_target(&self) -> Option<FullscreenTarget> {
+        if self.is_full_screen {
+            Some(FullscreenTarget {
+                mode: self.fullscreen_mode,
+                monitor: self.fullscreen_monitor.clone(),
+            })
+        } else {
+            None
+        }
+    }
+
+    /// The name screenshots of the current file are saved under
+    pub fn song_name(&self) -> &str {
+        &self.song_name
+    }
+
+    /// Whether a whole window screenshot was requested in this frame's layout,
+    /// clearing the request
+    pub fn take_window_screenshot_request(&mut self) -> bool {
+        std::mem::take(&mut self.window_screenshot_requested)
+    }
+
+    /// The amount of keys the note columns are shifted by on the piano roll
+    fn key_shift(&self) -> i32 {
+        let transform = self.player_state.transform.lock().unwrap();
+        if transform.show_transpose {
+            self.visual_transpose + transform.transpose as i32
+        } else {
+            self.visual_transpose
+        }
+    }
+
+    /// Fits the visible keys to the keys used by the current file
+    fn fit_keys_to_file(&mut self) {
+        if let Some(used) = self.midi_file.used_key_range() {
+            let (first_key, last_key) = fit_key_range(used, self.key_shift());
+            self.first_key = first_key;
+            self.last_key = last_key;
+        }
+    }
+
+    fn open_live_input(
+        &mut self,
+        open: impl FnOnce(SimpleTemporaryPlayer) -> Result<LiveMIDIFile, String>,
+    ) {
+        self.midi_file.timer_mut().pause();
+
+        match open(SimpleTemporaryPlayer::new(self.player_state.clone())) {
+            Ok(file) => {
+                self.midi_file = MIDIFileUnion::Live(file);
+                self.song_name = "Live Input".to_string();
+            }
+            Err(e) => {
+                println!("Failed to open live input: {}", e);
+                self.midi_file.timer_mut().play();
+            }
+        }
+    }
+
+    fn show_keyboard_layout_settings(ui: &mut Ui, params: &mut KeyboardParams) {
+        let mut layouts = vec![
+            KeyboardParams::default_classic(),
+            KeyboardParams::SameWidth,
+            KeyboardParams::Piano,
+            KeyboardParams::Isomorphic,
+        ];
+        if let KeyboardParams::Custom(_) = params {
+            layouts.push(params.clone());
+        }
+
+        egui::ComboBox::from_label("Keyboard Layout")
+            .selected_text(params.name())
+            .show_ui(ui, |ui| {
+                for layout in layouts {
+                    let selected = std::mem::discriminant(params) == std::mem::discriminant(&layout);
+                    if ui.selectable_label(selected, layout.name()).clicked() && !selected {
+                        *params = layout;
+                    }
+                }
+            });
+
+        if let KeyboardParams::Classic {
+            black_key_2_set_offset,
+            black_key_3_set_offset,
+            black_key_scale,
+        } = params
+        {
+            ui.add(egui::Slider::new(black_key_scale, 0.3..=1.0).text("Black Key Width"));
+            ui.add(egui::Slider::new(black_key_2_set_offset, 0.0..=1.0).text("Black Key Offset (2 set)"));
+            ui.add(egui::Slider::new(black_key_3_set_offset, 0.0..=1.0).text("Black Key Offset (3 set)"));
+        }
+
+        if ui.button("Load Layout").clicked() {
+            let path = FileDialog::new()
+                .set_location("~/")
+                .add_filter("Keyboard Layout", &["json", "JSON"])
+                .show_open_single_file()
+                .unwrap();
+
+            if let Some(path) = path {
+                match CustomLayout::load(path) {
+                    Ok(layout) => *params = KeyboardParams::Custom(layout),
+                    Err(e) => println!("Failed to load the keyboard layout: {}", e),
+                }
+            }
+        }
+    }
+
+    fn show_tuning_settings(ui: &mut Ui, tuned_pitch: &mut bool, scale: &mut Option<Scale>) {
+        ui.checkbox(tuned_pitch, "Draw notes at their tuned pitch (pitch bend and MTS)");
+
+        ui.horizontal(|ui| {
+            if ui.button("Load Scale").clicked() {
+                let path = FileDialog::new()
+                    .set_location("~/")
+                    .add_filter("Scala Scale", &["scl", "SCL"])
+                    .show_open_single_file()
+                    .unwrap();
+
+                if let Some(path) = path {
+                    match Scale::load(path) {
+                        Ok(loaded) => *scale = Some(loaded),
+                        Err(e) => println!("Failed to load the scale: {}", e),
+                    }
+                }
+            }
+            if scale.is_some() && ui.button("Clear Scale").clicked() {
+                *scale = None;
+            }
+        });
+
+        if let Some(scale) = scale {
+            ui.label(format!("{}: {} ({} notes)", scale.name(), scale.description, scale.notes_per_period()));
+            ui.add(egui::Slider::new(&mut scale.root_key, 0..=127).text("Scale Root Key"));
+        }
+    }
+
+    fn show_keyboard_height_settings(ui: &mut Ui, height: &mut KeyboardHeight) {
+        let modes = [
+            KeyboardHeight::default(),
+            KeyboardHeight::Pixels(70.0),
+            KeyboardHeight::Hidden,
+        ];
+        egui::ComboBox::from_label("Keyboard Height")
+            .selected_text(height.name())
+            .show_ui(ui, |ui| {
+                for mode in modes {
+                    let selected = std::mem::discriminant(height) == std::mem::discriminant(&mode);
+                    if ui.selectable_label(selected, mode.name()).clicked() && !selected {
+                        *height = mode;
+                    }
+                }
+            });
+
+        match height {
+            KeyboardHeight::Fraction(fraction) => {
+                ui.add(egui::Slider::new(fraction, 0.02..=0.3).text("Height (of width)"));
+            }
+            KeyboardHeight::Pixels(pixels) => {
+                ui.add(egui::Slider::new(pixels, 10.0..=400.0).text("Height (px)"));
+            }
+            KeyboardHeight::Hidden => {}
+        }
+    }
+
+    fn show_keyboard_theme_settings(ui: &mut Ui, theme: &mut KeyboardTheme) {
+        egui::ComboBox::from_label("Keyboard Theme")
+            .selected_text("Presets")
+            .show_ui(ui, |ui| {
+                for (name, preset) in KeyboardTheme::presets() {
+                    if ui.selectable_label(*theme == preset, name).clicked() {
+                        *theme = preset;
+                    }
+                }
+            });
+
+        let colors = [
+            ("Bar color", &mut theme.bar_color),
+            ("White key color", &mut theme.white_key),
+            ("White key front color", &mut theme.white_key_side),
+            ("Black key color", &mut theme.black_key),
+            ("Black key top color", &mut theme.black_key_top),
+            ("Key border color", &mut theme.border),
+        ];
+        for (name, color) in colors {
+            ui.horizontal(|ui| {
+                egui::color_picker::color_edit_button_srgba(ui, color, egui::color_picker::Alpha::Opaque);
+                ui.label(name);
+            });
+        }
+
+        ui.add(egui::Slider::new(&mut theme.pressed_blend, 0.0..=1.0).text("Pressed Key Blend"));
+        ui.add(egui::Slider::new(&mut theme.velocity_brightness, 0.0..=1.0).text("Velocity Brightness"));
+        ui.add(egui::Slider::new(&mut theme.bevel_depth, 0.0..=3.0).text("Key Depth"));
+        ui.add(egui::Slider::new(&mut theme.key_down_offset, 0.0..=1.0).text("Key Down Offset"));
+        ui.add(egui::Slider::new(&mut theme.press_time, 0.0..=0.2).text("Key Press Time (s)"));
+    }
+
+    fn show_note_style_settings(ui: &mut Ui, style: &mut NoteStyle) {
+        egui::ComboBox::from_label("Note Style")
+            .selected_text("Presets")
+            .show_ui(ui, |ui| {
+                for (name, preset) in NoteStyle::presets() {
+                    if ui.selectable_label(*style == preset, name).clicked() {
+                        *style = preset;
+                    }
+                }
+            });
+
+        ui.add(egui::Slider::new(&mut style.border_width, 0.0..=8.0).text("Border Width"));
+        ui.add(egui::Slider::new(&mut style.border_darken, 0.0..=1.0).text("Border Darkness"));
+        ui.add(egui::Slider::new(&mut style.gradient, 0.0..=1.0).text("Gradient"));
+        ui.add(egui::Slider::new(&mut style.corner_radius, 0.0..=16.0).text("Corner Radius"));
+        ui.add(egui::Slider::new(&mut style.shading, 0.0..=1.0).text("Shading"));
+        ui.add(egui::Slider::new(&mut style.glow, 0.0..=2.0).text("Glow"));
+
+        ui.horizontal(|ui| {
+            if ui.button("Load Texture").clicked() {
+                let path = FileDialog::new()
+                    .set_location("~/")
+                    .add_filter("PNG Image", &["png", "PNG"])
+                    .show_open_single_file()
+                    .unwrap();
+
+                if path.is_some() {
+                    style.texture_path = path;
+                }
+            }
+            if style.texture_path.is_some() && ui.button("Clear Texture").clicked() {
+                style.texture_path = None;
+            }
+        });
+    }
+
+    fn show_overlap_settings(ui: &mut Ui, overlap: &mut NoteOverlap) {
+        egui::ComboBox::from_label("Note Overlap")
+            .selected_text(overlap.mode.name())
+            .show_ui(ui, |ui| {
+                for mode in OverlapMode::ALL {
+                    ui.selectable_value(&mut overlap.mode, mode, mode.name());
+                }
+            });
+        if overlap.mode == OverlapMode::Transparent {
+            ui.add(egui::Slider::new(&mut overlap.opacity, 0.05..=1.0).text("Note Opacity"));
+        }
+        ui.checkbox(&mut overlap.black_over_white, "Black key notes over white key notes");
+    }
+
+    fn show_antialiasing_settings(ui: &mut Ui, antialiasing: &mut AntiAliasing, msaa_options: &[u32]) {
+        let sample_name = |samples: u32| if samples == 1 { "Off".to_string() } else { format!("{}x", samples) };
+        egui::ComboBox::from_label("MSAA")
+            .selected_text(sample_name(antialiasing.msaa_samples))
+            .show_ui(ui, |ui| {
+                for &samples in msaa_options {
+                    ui.selectable_value(&mut antialiasing.msaa_samples, samples, sample_name(samples));
+                }
+            });
+        egui::ComboBox::from_label("Supersampling")
+            .selected_text(sample_name(antialiasing.supersampling))
+            .show_ui(ui, |ui| {
+                for factor in SUPERSAMPLING_FACTORS {
+                    ui.selectable_value(&mut antialiasing.supersampling, factor, sample_name(factor));
+                }
+            });
+    }
+
+    fn show_effects_settings(ui: &mut Ui, effects: &mut EffectsSettings) {
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut effects.flashes, "Hit Flashes");
+            ui.checkbox(&mut effects.beams, "Light Beams");
+            ui.checkbox(&mut effects.particles, "Particles");
+        });
+        if effects.particles {
+            ui.add(egui::Slider::new(&mut effects.particles_per_hit, 1..=32).text("Particles Per Hit"));
+        }
+        if effects.enabled() {
+            ui.add(egui::Slider::new(&mut effects.max_effects, 100..=100000).logarithmic(true).text("Max Effects"));
+        }
+    }
+
+    fn show_note_buffer_settings(ui: &mut Ui, settings: &mut NoteBufferSettings, buffer_size: u64) {
+        let mut manual = settings.manual_size.is_some();
+        ui.checkbox(&mut manual, "Manual note buffer size");
+        if manual {
+            let mut millions = settings.manual_size.unwrap_or(25_000_000) as f64 / 1_000_000.0;
+            ui.add(egui::Slider::new(&mut millions, 0.1..=100.0).logarithmic(true).text("Million Notes Per Buffer"));
+            settings.manual_size = Some((millions * 1_000_000.0) as u64);
+        } else {
+            settings.manual_size = None;
+            let mut percent = settings.memory_fraction * 100.0;
+            ui.add(egui::Slider::new(&mut percent, 1.0..=75.0).suffix("%").text("Memory For Note Buffers"));
+            settings.memory_fraction = percent / 100.0;
+        }
+        ui.label(format!("Note buffers: 2 x {} notes", buffer_size));
+    }
+
+    fn show_frame_settings(ui: &mut Ui, settings: &mut FrameSettings, present_modes: &[PresentMode]) {
+        ui.add_enabled_ui(!settings.benchmark, |ui| {
+            egui::ComboBox::from_label("Present Mode")
+                .selected_text(present_mode_name(settings.present_mode))
+                .show_ui(ui, |ui| {
+                    for &mode in present_modes {
+                        ui.selectable_value(&mut settings.present_mode, mode, present_mode_name(mode));
+                    }
+                });
+
+            let mut capped = settings.fps_cap.is_some();
+            ui.checkbox(&mut capped, "Limit FPS");
+            if capped {
+                let mut fps_cap = settings.fps_cap.unwrap_or(60);
+                ui.add(egui::Slider::new(&mut fps_cap, 10..=1000).logarithmic(true).text("FPS Limit"));
+                settings.fps_cap = Some(fps_cap);
+            } else {
+                settings.fps_cap = None;
+            }
+        });
+        ui.checkbox(&mut settings.benchmark, "Unlimited (benchmark)");
+    }
+
+    fn show_fullscreen_settings(ui: &mut Ui, mode: &mut FullscreenMode, monitor: &mut Option<String>, monitors: &[String], hide_ui: &mut bool) {
+        egui::ComboBox::from_label("Fullscreen Mode")
+            .selected_text(mode.name())
+            .show_ui(ui, |ui| {
+                for option in FullscreenMode::ALL {
+                    ui.selectable_value(mode, option, option.name());
+                }
+            });
+        egui::ComboBox::from_label("Fullscreen Monitor")
+            .selected_text(monitor.clone().unwrap_or_else(|| "Current".to_string()))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(monitor, None, "Current");
+                for name in monitors {
+                    ui.selectable_value(monitor, Some(name.clone()), name);
+                }
+            });
+        ui.checkbox(hide_ui, "Hide top panel and stats in fullscreen");
+    }
+
+    fn show_screenshot_settings(ui: &mut Ui, settings: &mut ScreenshotSettings, requested: &mut bool) {
+        egui::ComboBox::from_label("Screenshot")
+            .selected_text(settings.kind.name())
+            .show_ui(ui, |ui| {
+                for kind in ScreenshotKind::ALL {
+                    ui.selectable_value(&mut settings.kind, kind, kind.name());
+                }
+            });
+        ui.add_enabled_ui(settings.kind == ScreenshotKind::Scene, |ui| {
+            ui.add(egui::Slider::new(&mut settings.scale, 1..=4).text("Screenshot Scale"));
+            ui.checkbox(&mut settings.include_keyboard, "Include keyboard");
+        });
+        if ui.button("Take Screenshot (P)").clicked() {
+            *requested = true;
+        }
+    }
+
+    fn show_output_settings(ui: &mut Ui, output_format: &str, hdr: &mut bool, hdr_active: bool) {
+        if ui.checkbox(hdr, "HDR output").changed() {
+            SettingsFile::update(|settings| settings.set("hdr", *hdr));
+        }
+        ui.label(format!("Output: {}", output_format));
+        if *hdr != hdr_active {
+            ui.label("HDR changes apply after a restart, if the display supports HDR");
+        }
+    }
+
+    fn show_gpu_settings(ui: &mut Ui, gpus: &[GpuInfo], gpu: &GpuInfo, preferred_gpu: &mut Option<GpuId>) {
+        let previous = *preferred_gpu;
+        let selected_text = match preferred_gpu {
+            Some(id) => gpus
+                .iter()
+                .find(|info| info.id == *id)
+                .map(|info| info.describe())
+                .unwrap_or_else(|| id.to_string()),
+            None => "Automatic".to_string(),
+        };
+        egui::ComboBox::from_label("GPU")
+            .selected_text(selected_text)
+            .show_ui(ui, |ui| {
+                ui.selectable_value(preferred_gpu, None, "Automatic");
+                for info in gpus {
+                    ui.selectable_value(preferred_gpu, Some(info.id), info.describe());
+                }
+            });
+
+        if *preferred_gpu != previous {
+            SettingsFile::update(|settings| match *preferred_gpu {
+                Some(id) => settings.set("gpu", id),
+                None => settings.remove("gpu"),
+            });
+        }
+
+        ui.label(format!("In use: {}", gpu.describe()));
+        if preferred_gpu.map_or(false, |id| id != gpu.id) {
+            ui.label("The GPU change applies after a restart");
+        }
+    }
+
+    fn show_nps_limit_setting(ui: &mut Ui, limit: &mut Option<u32>, text: &str) {
+        let mut limited = limit.is_some();
+        ui.checkbox(&mut limited, text);
+        if limited {
+            let mut nps = limit.unwrap_or(10000);
+            ui.add(egui::Slider::new(&mut nps, 100..=1000000).logarithmic(true).text("Max NPS"));
+            *limit = Some(nps);
+        } else {
+            *limit = None;
+        }
+    }
+
+    fn show_limiter_settings(ui: &mut Ui, limiter: &SharedNoteLimiter) {
+        let mut limiter = limiter.lock().unwrap();
+        let settings = &mut limiter.settings;
+
+        Self::show_nps_limit_setting(ui, &mut settings.max_notes_per_second, "Limit synth NPS");
+        if settings.max_notes_per_second.is_some() {
+            ui.add(egui::Slider::new(&mut settings.overload_velocity_threshold, 0..=127).text("Overload Velocity Threshold"));
+        }
+
+        let mut capped = settings.max_voices_per_key.is_some();
+        ui.checkbox(&mut capped, "Limit voices per key");
+        if capped {
+            let mut voices = settings.max_voices_per_key.unwrap_or(16);
+            ui.add(egui::Slider::new(&mut voices, 1..=256).text("Max Voices Per Key"));
+            settings.max_voices_per_key = Some(voices);
+        } else {
+            settings.max_voices_per_key = None;
+        }
+    }
+
+    fn show_transform_settings(ui: &mut Ui, transform: &SharedAudioTransform) {
+        let mut transform = transform.lock().unwrap();
+        let before = (
+            transform.transpose,
+            transform.low_key,
+            transform.high_key,
+            transform.forced_programs,
+        );
+
+        ui.add(egui::Slider::new(&mut transform.transpose, -24..=24).text("Transpose"));
+        ui.checkbox(&mut transform.show_transpose, "Show transpose on piano roll");
+        ui.add(egui::Slider::new(&mut transform.velocity_curve, 0.25..=4.0).logarithmic(true).text("Velocity Curve"));
+        ui.add(egui::Slider::new(&mut transform.velocity_scale, 0.0..=2.0).text("Velocity Scale"));
+
+        let high_key = transform.high_key;
+        ui.add(egui::Slider::new(&mut transform.low_key, 0..=high_key).text("Lowest Audible Key"));
+        let low_key = transform.low_key;
+        ui.add(egui::Slider::new(&mut transform.high_key, low_key..=127).text("Highest Audible Key"));
+
+        ui.collapsing("Forced Programs", |ui| {
+            egui::Grid::new("Forced programs grid").num_columns(3).show(ui, |ui| {
+                for (channel, forced) in transform.forced_programs.iter_mut().enumerate() {
+                    let mut enabled = forced.is_some();
+                    ui.checkbox(&mut enabled, format!("Channel {}", channel + 1));
+
+                    match (enabled, forced.as_mut()) {
+                        (true, Some(forced)) => {
+                            ui.add(egui::DragValue::new(&mut forced.program).clamp_range(0..=127).prefix("Program "));
+                            ui.horizontal(|ui| {
+                                let mut set_bank = forced.bank.is_some();
+                                ui.checkbox(&mut set_bank, "Bank");
+                                if set_bank {
+                                    let mut bank = forced.bank.unwrap_or(0);
+                                    ui.add(egui::DragValue::new(&mut bank).clamp_range(0..=127));
+                                    forced.bank = Some(bank);
+                                } else {
+                                    forced.bank = None;
+                                }
+                            });
+                        }
+                        (true, None) => {
+                            *forced = Some(ForcedProgram {
+                                bank: None,
+                                program: 0,
+                            });
+                        }
+                        (false, _) => *forced = None,
+                    }
+                    ui.end_row();
+                }
+            });
+        });
+
+        let after = (
+            transform.transpose,
+            transform.low_key,
+            transform.high_key,
+            transform.forced_programs,
+        );
+        if before != after {
+            transform.mark_changed();
+        }
+    }
+
+    fn show_midi_thru_settings(ui: &mut Ui, midi_thru: &SharedMIDIThru) {
+        // The playback thread locks the port for every event, so it's not
+        // held while the ports are listed
+        let open_name = midi_thru
+            .lock()
+            .unwrap()
+            .as_ref()
+            .map(|thru| thru.port_name().to_string());
+
+        let mut close_port = false;
+        let mut open_port = None;
+        egui::ComboBox::from_label("MIDI Output")
+            .selected_text(open_name.as_deref().unwrap_or("None"))
+            .show_ui(ui, |ui| {
+                if ui.selectable_label(open_name.is_none(), "None").clicked() {
+                    close_port = true;
+                }
+                for (i, name) in MIDIThru::list_ports().iter().enumerate() {
+                    if ui.selectable_label(open_name.as_ref() == Some(name), name).clicked() {
+                        open_port = Some(i);
+                    }
+                }
+            });
+
+        let mut thru = midi_thru.lock().unwrap();
+        if close_port || open_port.is_some() {
+            // Silence the old port before letting go of it
+            if let Some(mut old) = thru.take() {
+                old.reset();
+            }
+        }
+
+        if let Some(index) = open_port {
+            match MIDIThru::open_port(index) {
+                Ok(new) => *thru = Some(new),
+                Err(e) => println!("Failed to open MIDI output: {}", e),
+            }
+        }
+
+        if let Some(thru) = thru.as_mut() {
+            Self::show_nps_limit_setting(ui, &mut thru.limiter.settings.max_notes_per_second, "Limit output NPS");
+
+            ui.collapsing("Channel Mapping", |ui| {
+                egui::Grid::new("Channel mapping grid").num_columns(2).show(ui, |ui| {
+                    for channel in 0..16 {
+                        let mut mapped = thru.channel_map()[channel] + 1;
+                        ui.label(format!("Channel {}", channel + 1));
+                        ui.add(egui::DragValue::new(&mut mapped).clamp_range(1..=16));
+                        thru.map_channel(channel, mapped - 1);
+                        ui.end_row();
+                    }
+                });
+            });
         }
     }
 
@@ -116,93 +883,140 @@ impl GuiWasabiWindow {
 
         ctx.set_visuals(Visuals::dark());
 
+        // Handle the shortcuts here instead of in the top panel, it can be hidden
+        let one_sec = Duration::from_secs(1);
+        let _five_sec = Duration::from_secs(5);
+        let time = self.midi_file.timer().get_time();
+        let events = ctx.input().events.clone();
+        for event in &events {
+            match event {
+                egui::Event::Key{key, pressed, ..} => if pressed == &true {
+                    match key {
+                        egui::Key::ArrowRight => self.midi_file.timer_mut().seek(time + one_sec),
+                        egui::Key::ArrowLeft => self.midi_file.timer_mut().seek(time - one_sec),
+                        egui::Key::Space => self.midi_file.timer_mut().toggle_pause(),
+                        egui::Key::F => self.is_full_screen = !self.is_full_screen,
+                        egui::Key::Escape => self.is_full_screen = false,
+                        egui::Key::P => self.screenshot_requested = true,
+                        _ => {},
+                    }
+                },
+                _ => {},
+            }
+        }
+
+        let show_ui = !(self.is_full_screen && self.hide_ui_in_fullscreen);
+
         // Render the top panel
         let panel_height = 40.0;
         let panel_frame = Frame::default()
             .margin(egui::style::Margin::same(10.0))
             .fill(egui::Color32::from_rgb(42, 42, 42));
 
-        egui::TopBottomPanel::top("Top panel")
-            .height_range(panel_height..=panel_height)
-            .frame(panel_frame)
-            .show(&ctx, |ui| {
-                let one_sec = Duration::from_secs(1);
-                let _five_sec = Duration::from_secs(5);
-                let time = self.midi_file.timer().get_time();
-                let events = ui.input().events.clone();
-                for event in &events {
-                    match event {
-                        egui::Event::Key{key, pressed, ..} => if pressed == &true {
-                            match key {
-                                egui::Key::ArrowRight => self.midi_file.timer_mut().seek(time + one_sec),
-                                egui::Key::ArrowLeft => self.midi_file.timer_mut().seek(time - one_sec),
-                                egui::Key::Space => self.midi_file.timer_mut().toggle_pause(),
-                                egui::Key::F => self.is_full_screen = !self.is_full_screen,
-                                _ => {},
-                            }
-                        },
-                        _ => {},
-                    }
-                }
-
-                ui.horizontal(|ui| {
-                    if ui.button("Open MIDI").clicked() {
-                        self.midi_file.timer_mut().pause();
+        if show_ui {
+            egui::TopBottomPanel::top("Top panel")
+                .height_range(panel_height..=panel_height)
+                .frame(panel_frame)
+                .show(&ctx, |ui| {
+                    ui.horizontal(|ui| {
+                        if ui.button("Open MIDI").clicked() {
+                            self.midi_file.timer_mut().pause();
                         
-                        let path = FileDialog::new()
-                        .set_location("~/")
-                        .add_filter("MIDI File", &["mid","MID"])
-                        .show_open_single_file()
-                        .unwrap();
+                            let path = FileDialog::new()
+                            .set_location("~/")
+                            .add_filter("MIDI File", &["mid","MID"])
+                            .show_open_single_file()
+                            .unwrap();
                         
-                        let path = match path {
-                            Some(path) => path,
-                            None => {
-                                panic!("File Not Found or Cancelled");
-                            },
-                        };
+                            let path = match path {
+                                Some(path) => path,
+                                None => {
+                                    panic!("File Not Found or Cancelled");
+                                },
+                            };
                 
-                        self.midi_file = MIDIFileUnion::InRam(InRamMIDIFile::load_from_file(
-                            &path.into_os_string().into_string().unwrap(),
-                            SimpleTemporaryPlayer::new(),
-                        ));
+                            self.song_name = song_name(&path);
+                            self.midi_file = MIDIFileUnion::InRam(InRamMIDIFile::load_from_file(
+                                &path.into_os_string().into_string().unwrap(),
+                                SimpleTemporaryPlayer::new(self.player_state.clone()),
+                            ));
+
+                            if self.auto_fit_keys {
+                                self.fit_keys_to_file();
+                            }
                 
-                        self.midi_file.timer_mut().play();
-                    }
-                    if ui.button("Play").clicked() {
-                        self.midi_file.timer_mut().play();
-                    }
-                    if ui.button("Pause").clicked() {
-                        self.midi_file.timer_mut().pause();
-                    }
-                    if ui.button("Settings").clicked() {
-                        self.is_show_setting = !self.is_show_setting;
-                    }
-                });
+                            self.midi_file.timer_mut().play();
+                        }
+                        ui.menu_button("Live Input", |ui| {
+                            for (i, name) in LiveMIDIFile::list_ports().iter().enumerate() {
+                                if ui.button(name).clicked() {
+                                    self.open_live_input(|player| LiveMIDIFile::open_port(i, player));
+                                    ui.close_menu();
+                                }
+                            }
+                            #[cfg(unix)]
+                            if ui.button("Virtual Port").clicked() {
+                                self.open_live_input(LiveMIDIFile::open_virtual);
+                                ui.close_menu();
+                            }
+                        });
+                        if ui.button("Play").clicked() {
+                            self.midi_file.timer_mut().play();
+                        }
+                        if ui.button("Pause").clicked() {
+                            self.midi_file.timer_mut().pause();
+                        }
+                        if ui.button("Settings").clicked() {
+                            self.is_show_setting = !self.is_show_setting;
+                        }
+                    });
 
-                if let Some(length) = self.midi_file.midi_length() {
-                    let time = self.midi_file.timer().get_time().as_secs_f64();
-                    let mut progress = time / length;
-                    let progress_prev = progress.clone();
-                    let slider = egui::Slider::new(&mut progress, 0.0..=1.0).show_value(false);
-                    ui.spacing_mut().slider_width = window_size[0] - 15.0;
-                    ui.add(slider);
-                    if progress_prev != progress {
-                        let position = Duration::from_secs_f64(progress * length);
-                        self.midi_file.timer_mut().seek(position);
+                    if let Some(length) = self.midi_file.midi_length() {
+                        let time = self.midi_file.timer().get_time().as_secs_f64();
+                        let mut progress = time / length;
+                        let progress_prev = progress.clone();
+                        let slider = egui::Slider::new(&mut progress, 0.0..=1.0).show_value(false);
+                        ui.spacing_mut().slider_width = window_size[0] - 15.0;
+                        ui.add(slider);
+                        if progress_prev != progress {
+                            let position = Duration::from_secs_f64(progress * length);
+                            self.midi_file.timer_mut().seek(position);
+                        }
                     }
-                }
-            });
+                });
+        }
 
         // Calculate available space left for keyboard and notes
         // We must render notes before keyboard because the notes
         // renderer tells us the key colors
         let available = ctx.available_rect();
-        let height = available.height();
-        let keyboard_height = 70.0 / 760.0 * available.width() as f32;
-        let notes_height = height - keyboard_height;
+        let (key_axis, depth_axis) = if self.orientation.is_horizontal() {
+            (available.height(), available.width())
+        } else {
+            (available.width(), available.height())
+        };
+        let keyboard_height = self.keyboard_height.height(key_axis, depth_axis);
+        let notes_height = depth_axis - keyboard_height;
+
+        // The notes panel is always added first, so it has to be attached to
+        // the side that is away from the keyboard
+        let (notes_side, keyboard_side) = match self.orientation {
+            RollOrientation::Down => (PanelSide::Top, PanelSide::Top),
+            RollOrientation::Up => (PanelSide::Bottom, PanelSide::Top),
+            RollOrientation::Horizontal => (PanelSide::Right, PanelSide::Left),
+        };
 
-        let key_view = self.keyboard_layout.get_view_for_keys(self.first_key, self.last_key);
+        if *self.keyboard_layout.params() != self.keyboard_params
+            || self.keyboard_layout.scale() != self.scale.as_ref()
+        {
+            self.keyboard_layout =
+                keyboard_layout::KeyboardLayout::new(&self.keyboard_params, self.scale.as_ref());
+        }
+
+        let key_view = self
+            .keyboard_layout
+            .get_view_for_keys(self.first_key, self.last_key)
+            .oriented(self.orientation, self.mirror_keys);
 
         let no_frame = Frame::default()
             .margin(Margin::same(0.0))
@@ -210,14 +1024,30 @@ impl GuiWasabiWindow {
         
         
 
+        let key_shift = self.key_shift();
+
         let mut render_result_data = None;
+        let mut fit_keys = false;
+
+        // Scene screenshots are read back while drawing the scene, whole
+        // window ones after the GUI is drawn on top of it
+        let scene_screenshot_scale = match self.screenshot.kind {
+            ScreenshotKind::Scene if self.screenshot_requested => Some(self.screenshot.scale),
+            _ => None,
+        };
+        if self.screenshot.kind == ScreenshotKind::Window && self.screenshot_requested {
+            self.window_screenshot_requested = true;
+        }
+        self.screenshot_requested = false;
 
         // Render the notes
-        egui::TopBottomPanel::top("Note panel")
-            .height_range(notes_height..=notes_height)
-            .frame(no_frame)
-            .show(&ctx, |mut ui| {
-                let result = self.render_scene.draw(state, &mut ui, &key_view, &mut self.midi_file, &mut self.note_speed);
+        show_panel(&ctx, "Note panel", notes_side, notes_height, no_frame, |mut ui| {
+                let (result, response) = self.render_scene.draw(state, &mut ui, &key_view, &mut self.midi_file, &mut self.note_speed, key_shift, &self.note_style, &self.overlap, self.key_color_mode, &self.antialiasing, self.tuned_pitch, &self.effects, &self.note_buffer, scene_screenshot_scale);
+                let note_buffer_size = result.note_buffer_size;
+
+                self.roll_input.seek_and_scrub(&response, &key_view, &mut self.midi_file, self.note_speed, self.scrub_audio);
+                self.roll_input.zoom(ui, &response, &key_view, &mut self.note_speed, &mut self.first_key, &mut self.last_key);
+                self.roll_input.show_note_tooltip(ui, &response, &key_view, &mut self.midi_file, self.note_speed, key_shift);
 
                 // Render the stats
                 let stats_frame = Frame::default()
@@ -225,6 +1055,7 @@ impl GuiWasabiWindow {
                     .fill(egui::Color32::from_rgba_unmultiplied(0, 0, 0, 170))
                     .rounding(egui::Rounding::same(5.0));
 
+                let mut show_stats = show_ui;
                 egui::Window::new("Stats")
                     .resizable(false)
                     .collapsible(false)
@@ -232,6 +1063,7 @@ impl GuiWasabiWindow {
                     .scroll2([false, false])
                     .enabled(true)
                     .frame(stats_frame)
+                    .open(&mut show_stats)
                     //.fixed_pos(egui::Pos2::new(10.0, panel_height + 38.0))
                     .show(&ctx, |ui| {
                         if let Some(length) = self.midi_file.midi_length() {
@@ -248,54 +1080,149 @@ impl GuiWasabiWindow {
                             }
                         }
                         ui.add(Label::new(format!("FPS: {}", self.fps.get_fps().round())));
+                        let [p50, p95, p99] = self.fps.get_frame_time_percentiles();
+                        ui.add(Label::new(format!("Frame Time: {:.1}ms (95%: {:.1}ms, 99%: {:.1}ms)", p50, p95, p99)));
                         ui.add(Label::new(format!("Total Notes: {}", stats.total_notes)));
                         ui.add(Label::new(format!("Passed Notes: {}", self.notes)));  // TODO
                         ui.add(Label::new(format!("Polyphony: {}", self.polyphony)));  // TODO
                         ui.add(Label::new(format!("NPS: {}", self.polyphony)));  // TODO
                         ui.add(Label::new(format!("Rendered: {}", result.notes_rendered)));
-                        render_result_data = Some(result);
+                        let dropped = self.player_state.limiter.lock().unwrap().dropped_count();
+                        ui.add(Label::new(format!("Dropped Notes: {}", dropped)));
                     });
+                render_result_data = Some(result);
 
-                    if self.is_show_setting {
+                    if self.is_show_setting && show_ui {
                         egui::Window::new("Settings")
                         .resizable(true)
                         .collapsible(true)
                         .title_bar(true)
-                        .scroll2([false, false])
+                        // Scrolls down to the sections that don't fit in the window
+                        .scroll2([false, true])
                         .enabled(true)
                         .frame(stats_frame)
                         .show(&ctx, |ui| {
                             ui.label("wasabi - Arduano > MBMS > Ced Mod");
                             let slider2 = egui::Slider::new(&mut self.note_speed, 0.01..=4.0).text("Note Speed");
                             ui.add(slider2);
+                            egui::ComboBox::from_label("Audio While Dragging Notes")
+                                .selected_text(self.scrub_audio.name())
+                                .show_ui(ui, |ui| {
+                                    for mode in ScrubAudio::ALL {
+                                        ui.selectable_value(&mut self.scrub_audio, mode, mode.name());
+                                    }
+                                });
         
-                            let slider3 = egui::Slider::new(&mut self.first_key, 0..=63).text("First Key");
+                            let last_key = self.last_key;
+                            let slider3 = egui::Slider::new(&mut self.first_key, 0..=last_key - 1).text("First Key");
                             ui.add(slider3);
         
-                            let slider4 = egui::Slider::new(&mut self.last_key, 64..=255).text("Last Key");
+                            let first_key = self.first_key;
+                            let slider4 = egui::Slider::new(&mut self.last_key, first_key + 1..=255).text("Last Key");
                             ui.add(slider4);
 
+                            ui.horizontal(|ui| {
+                                if ui.button("Auto-fit").clicked() {
+                                    fit_keys = true;
+                                }
+                                for (name, first_key, last_key) in KEY_RANGE_PRESETS {
+                                    if ui.button(name).clicked() {
+                                        self.first_key = first_key;
+                                        self.last_key = last_key;
+                                    }
+                                }
+                            });
+                            ui.checkbox(&mut self.auto_fit_keys, "Auto-fit keys when opening a MIDI");
+
+                            let slider6 = egui::Slider::new(&mut self.visual_transpose, -48..=48).text("Visual Transpose");
+                            ui.add(slider6);
+
+                            egui::ComboBox::from_label("Orientation")
+                                .selected_text(self.orientation.name())
+                                .show_ui(ui, |ui| {
+                                    for orientation in RollOrientation::ALL {
+                                        ui.selectable_value(&mut self.orientation, orientation, orientation.name());
+                                    }
+                                });
+                            ui.checkbox(&mut self.mirror_keys, "Mirror keys");
+
                             ui.label("Background color");
                             egui::color_picker::color_picker_color32(ui, &mut self.background_color, egui::color_picker::Alpha::OnlyBlend);
 
-                            //ui.label("Bar color");
-                            //egui::color_picker::color_picker_color32(ui, &mut self.bar_color, egui::color_picker::Alpha::OnlyBlend);
-                            //let slider5 = egui::Slider::new(&mut self.keyboard_height, 40.0..=90.0).text("Keyboard Height");
-                            //ui.add(slider5);
+                            ui.separator();
+                            Self::show_keyboard_layout_settings(ui, &mut self.keyboard_params);
+                            Self::show_tuning_settings(ui, &mut self.tuned_pitch, &mut self.scale);
+                            Self::show_keyboard_height_settings(ui, &mut self.keyboard_height);
+                            Self::show_keyboard_theme_settings(ui, &mut self.keyboard_theme);
+                            egui::ComboBox::from_label("Key Colors")
+                                .selected_text(self.key_color_mode.name())
+                                .show_ui(ui, |ui| {
+                                    for mode in KeyColorMode::ALL {
+                                        ui.selectable_value(&mut self.key_color_mode, mode, mode.name());
+                                    }
+                                });
+
+                            ui.separator();
+                            Self::show_note_style_settings(ui, &mut self.note_style);
+                            Self::show_overlap_settings(ui, &mut self.overlap);
+                            Self::show_antialiasing_settings(ui, &mut self.antialiasing, &self.msaa_options);
+
+                            ui.separator();
+                            Self::show_effects_settings(ui, &mut self.effects);
+
+                            ui.separator();
+                            Self::show_note_buffer_settings(ui, &mut self.note_buffer, note_buffer_size);
+
+                            ui.separator();
+                            Self::show_frame_settings(ui, &mut self.frame_settings, &self.present_modes);
+                            Self::show_gpu_settings(ui, &self.gpus, &self.gpu, &mut self.preferred_gpu);
+                            Self::show_output_settings(ui, &self.output_format, &mut self.hdr, self.hdr_active);
+                            Self::show_screenshot_settings(ui, &mut self.screenshot, &mut self.screenshot_requested);
+
+                            ui.separator();
+                            Self::show_fullscreen_settings(ui, &mut self.fullscreen_mode, &mut self.fullscreen_monitor, &self.monitors, &mut self.hide_ui_in_fullscreen);
+
+                            ui.separator();
+                            Self::show_transform_settings(ui, &self.player_state.transform);
+
+                            ui.separator();
+                            Self::show_limiter_settings(ui, &self.player_state.limiter);
+
+                            ui.separator();
+                            Self::show_midi_thru_settings(ui, &self.player_state.thru);
                         });
                     }
             });
 
         let render_result_data = render_result_data.unwrap();
 
-        // Render the keyboard
-        egui::TopBottomPanel::top("Keyboard panel")
-            .height_range(keyboard_height..=keyboard_height)
-            .frame(no_frame)
-            .show(&ctx, |ui| {
-                let pressed = self.keyboard.draw(ui, &key_view, &render_result_data.key_colors);
-                self.polyphony = 0;
-                //self.notes = pressed;      disabeld due to an error...
-            });
+        // Render the keyboard, a hidden one leaves the whole space to the notes
+        if keyboard_height > 0.0 {
+            show_panel(&ctx, "Keyboard panel", keyboard_side, keyboard_height, no_frame, |ui| {
+                    let pressed = self.keyboard.draw(ui, &key_view, &render_result_data.key_presses, &self.keyboard_theme);
+                    //self.notes = pressed;      disabeld due to an error...
+                });
+        }
+        self.polyphony = 0;
+
+        // The keyboard is drawn next to the notes with its current key state
+        if let (Some(notes), Some(scale)) = (self.render_scene.take_screenshot(), scene_screenshot_scale) {
+            let image = if self.screenshot.include_keyboard && keyboard_height > 0.0 {
+                let keyboard_size = (keyboard_height * scale as f32).round() as u32;
+                self.keyboard.render_with_notes(notes, &key_view, &render_result_data.key_presses, &self.keyboard_theme, keyboard_size, scale as f32)
+            } else {
+                Some(notes)
+            };
+
+            if let Some(mut image) = image.and_then(|image| ImageReadback::read_now(&self.gfx_queue, image.image().clone())) {
+                let background = self.background_color;
+                flatten(&mut image, [background.r(), background.g(), background.b()]);
+                save_screenshot(&image, &self.song_name);
+            }
+        }
+
+        if fit_keys {
+            self.fit_keys_to_file();
+        }
     }
 }
\ No newline at end of file
@@ -1,113 +1,336 @@
-use egui::{Color32, Mesh, Pos2, Rect, Sense, Ui};
+mod height;
+mod render_pass;
+mod theme;
 
-use crate::midi::MIDIColor;
+use std::{sync::Arc, time::Instant};
 
-use super::keyboard_layout::KeyboardView;
+use egui::{Color32, Mesh, Pos2, Rect, Sense, Ui, Vec2};
+use vulkano::{
+    command_buffer::{AutoCommandBufferBuilder, CommandBufferUsage},
+    device::Queue,
+    format::ClearValue,
+    image::{view::ImageView, AttachmentImage, ImageAccess, ImageUsage},
+    sync::{self, GpuFuture},
+};
 
-pub struct GuiKeyboard {}
+use crate::{gui::GuiRenderer, midi::MIDIColor};
+
+pub use self::{height::KeyboardHeight, theme::KeyboardTheme};
+
+use self::render_pass::KeyboardRenderPass;
+
+use super::{
+    keyboard_layout::{KeyboardView, RollOrientation},
+    scene::KeyPress,
+};
+
+pub struct GuiKeyboard {
+    render_pass: KeyboardRenderPass,
+    gfx_queue: Arc<Queue>,
+    /// How far each key is pressed down, animated towards 0 or 1
+    key_press: Vec<f32>,
+    last_update: Instant,
+}
 
 impl GuiKeyboard {
-    pub fn new() -> GuiKeyboard {
-        GuiKeyboard {}
+    pub fn new(renderer: &GuiRenderer) -> GuiKeyboard {
+        GuiKeyboard {
+            render_pass: KeyboardRenderPass::new(renderer),
+            gfx_queue: renderer.queue.clone(),
+            key_press: vec![0.0; 256],
+            last_update: Instant::now(),
+        }
     }
 
-    pub fn draw(&mut self, ui: &mut Ui, key_view: &KeyboardView, colors: &Vec<Option<MIDIColor>>) {
-        let (rect, _) = ui.allocate_exact_size(ui.available_size(), Sense::click());
+    /// Moves the keys towards being pressed or released
+    fn animate(&mut self, presses: &[KeyPress], theme: &KeyboardTheme) {
+        let now = Instant::now();
+        let delta = now.duration_since(self.last_update).as_secs_f32();
+        self.last_update = now;
 
-        let mut mesh = Mesh::default();
+        let step = if theme.press_time > 0.0 {
+            delta / theme.press_time
+        } else {
+            1.0
+        };
+
+        for (press, key_press) in self.key_press.iter_mut().zip(presses) {
+            let target = if key_press.is_pressed() { 1.0 } else { 0.0 };
+            *press = if *press < target {
+                (*press + step).min(target)
+            } else {
+                (*press - step).max(target)
+            };
+        }
+    }
+
+    pub fn draw(
+        &mut self,
+        ui: &mut Ui,
+        key_view: &KeyboardView,
+        presses: &[KeyPress],
+        theme: &KeyboardTheme,
+    ) {
+        self.animate(presses, theme);
+
+        let (screen_rect, _) = ui.allocate_exact_size(ui.available_size(), Sense::click());
 
         let painter = ui.painter();
-        let ppp = ui.ctx().pixels_per_point();
-        let unit = 1.0 / ppp;
         let rounded = painter.round_to_pixel(1.0); // + 0.5 * unit;
 
+        let mut mesh = Mesh::default();
+        for (rect, color) in self.key_rects(key_view, presses, theme, screen_rect, rounded) {
+            mesh.add_colored_rect(rect, color);
+        }
+
+        ui.painter().add(mesh);
+    }
+
+    /// Renders the keyboard next to an image of the notes, into a new image.
+    /// The keyboard is `keyboard_size` pixels deep and is placed on the side
+    /// the orientation puts it on. `pixel` is the size of a GUI pixel.
+    pub fn render_with_notes(
+        &mut self,
+        notes: Arc<ImageView<AttachmentImage>>,
+        key_view: &KeyboardView,
+        presses: &[KeyPress],
+        theme: &KeyboardTheme,
+        keyboard_size: u32,
+        pixel: f32,
+    ) -> Option<Arc<ImageView<AttachmentImage>>> {
+        let [width, height] = notes.image().dimensions().width_height();
+
+        let (size, notes_offset, keyboard_rect) = match key_view.orientation {
+            RollOrientation::Down => (
+                [width, height + keyboard_size],
+                [0, 0],
+                Rect::from_min_size(
+                    Pos2::new(0.0, height as f32),
+                    Vec2::new(width as f32, keyboard_size as f32),
+                ),
+            ),
+            RollOrientation::Up => (
+                [width, height + keyboard_size],
+                [0, keyboard_size as i32],
+                Rect::from_min_size(Pos2::ZERO, Vec2::new(width as f32, keyboard_size as f32)),
+            ),
+            RollOrientation::Horizontal => (
+                [width + keyboard_size, height],
+                [keyboard_size as i32, 0],
+                Rect::from_min_size(Pos2::ZERO, Vec2::new(keyboard_size as f32, height as f32)),
+            ),
+        };
+
+        let usage = ImageUsage {
+            color_attachment: true,
+            transfer_source: true,
+            transfer_destination: true,
+            ..ImageUsage::none()
+        };
+        let image = match AttachmentImage::with_usage(
+            self.gfx_queue.device().clone(),
+            size,
+            notes.image().format(),
+            usage,
+        ) {
+            Ok(image) => {
+                ImageView::new_default(image).expect("Failed to create keyboard image view")
+            }
+            Err(e) => {
+                println!(
+                    "Failed to create a {}x{} keyboard image: {:?}",
+                    size[0], size[1], e
+                );
+                return None;
+            }
+        };
+
+        // Start out transparent like the notes, and copy them over
+        let mut command_buffer_builder = AutoCommandBufferBuilder::primary(
+            self.gfx_queue.device().clone(),
+            self.gfx_queue.family(),
+            CommandBufferUsage::OneTimeSubmit,
+        )
+        .unwrap();
+        command_buffer_builder
+            .clear_color_image(image.image().clone(), ClearValue::Float([0.0; 4]))
+            .unwrap()
+            .copy_image(
+                notes.image().clone(),
+                [0, 0, 0],
+                0,
+                0,
+                image.image().clone(),
+                [notes_offset[0], notes_offset[1], 0],
+                0,
+                0,
+                [width, height, 1],
+                1,
+            )
+            .unwrap();
+        let command_buffer = command_buffer_builder.build().unwrap();
+
+        if let Err(e) = sync::now(self.gfx_queue.device().clone())
+            .then_execute(self.gfx_queue.clone(), command_buffer)
+            .map_err(|e| format!("{:?}", e))
+            .and_then(|future| {
+                future
+                    .then_signal_fence_and_flush()
+                    .and_then(|future| future.wait(None))
+                    .map_err(|e| format!("{:?}", e))
+            })
+        {
+            println!("Failed to copy the notes next to the keyboard: {}", e);
+            return None;
+        }
+
+        let rects = self.key_rects(key_view, presses, theme, keyboard_rect, pixel);
+        self.render_pass.draw(image.clone(), &rects);
+
+        Some(image)
+    }
+
+    /// The colored rects the keyboard is made of, in the order they're drawn
+    fn key_rects(
+        &self,
+        key_view: &KeyboardView,
+        presses: &[KeyPress],
+        theme: &KeyboardTheme,
+        screen_rect: Rect,
+        rounded: f32,
+    ) -> Vec<(Rect, Color32)> {
+        // The keyboard is laid out as if the notes were falling down onto it,
+        // and then every rect gets rotated into the actual orientation
+        let orientation = key_view.orientation;
+        let rect = if orientation.is_horizontal() {
+            Rect::from_min_size(
+                Pos2::ZERO,
+                Vec2::new(screen_rect.height(), screen_rect.width()),
+            )
+        } else {
+            Rect::from_min_size(Pos2::ZERO, screen_rect.size())
+        };
+
+        let orient = |r: Rect| -> Rect {
+            let map = |pos: Pos2| -> Pos2 {
+                match orientation {
+                    RollOrientation::Down => screen_rect.min + pos.to_vec2(),
+                    RollOrientation::Up => {
+                        Pos2::new(screen_rect.left() + pos.x, screen_rect.bottom() - pos.y)
+                    }
+                    RollOrientation::Horizontal => {
+                        Pos2::new(screen_rect.right() - pos.y, screen_rect.bottom() - pos.x)
+                    }
+                }
+            };
+            Rect::from_two_pos(map(r.min), map(r.max))
+        };
+
+        // Rects with several colors are split into stripes across the key
+        let mut rects = Vec::new();
+        let mut add = |left: f32, top: f32, right: f32, bottom: f32, colors: &[Color32]| {
+            let width = (right - left) / colors.len() as f32;
+            for (i, &color) in colors.iter().enumerate() {
+                let stripe_left = left + width * i as f32;
+                let r = Rect::from_min_max(
+                    Pos2::new(stripe_left, top),
+                    Pos2::new(stripe_left + width, bottom),
+                );
+                rects.push((orient(r), color));
+            }
+        };
+
         let md_height = rect.height() * 0.04;
+        let bevel = md_height * theme.bevel_depth;
 
         let black_key_overlap = md_height / 1.5;
         let top = rect.top() + md_height;
         let bottom = rect.bottom();
-        let black_bottom = rect.bottom() - rect.height() * 0.34;
+        let black_bottom = if key_view.full_height_black_keys() {
+            bottom
+        } else {
+            rect.bottom() - rect.height() * 0.34
+        };
 
         let map_x = |num: f32| rect.left() + num * rect.width();
+        let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
 
         fn map_color(col: MIDIColor) -> Color32 {
             Color32::from_rgb(col.red(), col.green(), col.blue())
         }
 
+        let pressed_colors = |press: &KeyPress, key_color: Color32| -> Vec<Color32> {
+            press
+                .colors
+                .iter()
+                .map(|&col| theme.pressed_color(key_color, map_color(col), press.velocity))
+                .collect()
+        };
+
         for (i, key) in key_view.iter_visible_keys() {
             if !key.black {
-                let color = colors[i].map(map_color).unwrap_or(Color32::WHITE);
-
-                if color == Color32::WHITE {  // Not pressed
-                    let white_key_bottom = md_height;
-
-                    let top_left1 = Pos2::new(map_x(key.left), top);
-                    let bottom_right1 = Pos2::new(map_x(key.right), bottom - white_key_bottom);
-                    let rect1 = Rect::from_min_max(top_left1, bottom_right1);
-
-                    let top_left2 = Pos2::new(map_x(key.left), bottom - white_key_bottom);
-                    let bottom_right2 = Pos2::new(map_x(key.right), bottom);
-                    let rect2 = Rect::from_min_max(top_left2, bottom_right2);
-                    let color2 = Color32::from_rgb(130, 130, 130); //white key bottom color (unpressed)
-
-                    mesh.add_colored_rect(rect1, color);
-                    mesh.add_colored_rect(rect2, color2);
-                } else {  // Pressed
-                    let top_left = Pos2::new(map_x(key.left), top);
-                    let bottom_right = Pos2::new(map_x(key.right), bottom);
-                    let rect = Rect::from_min_max(top_left, bottom_right);
-                    mesh.add_colored_rect(rect, color);
+                let down = self.key_press[i] * theme.key_down_offset;
+                let colors = if presses[i].is_pressed() {
+                    pressed_colors(&presses[i], theme.white_key)
+                } else {
+                    vec![theme.white_key]
+                };
+
+                // The front of the key hides as it goes down
+                let front = bevel * (1.0 - down);
+                let (left, right) = (map_x(key.left), map_x(key.right));
+                add(left, top, right, bottom - front, &colors);
+                if front > 0.0 {
+                    add(left, bottom - front, right, bottom, &[theme.white_key_side]);
                 }
-                let color3 = Color32::from_rgb(33, 33, 33); //key borders
-                let top_left3 = Pos2::new(map_x(key.right), top);
-                let bottom_right3 = Pos2::new(map_x(key.right) - rounded, bottom);
-                let rect3 = Rect::from_min_max(top_left3, bottom_right3);
-                mesh.add_colored_rect(rect3, color3);
+
+                add(right - rounded, top, right, bottom, &[theme.border]);
             }
         }
-         
-        //bar
-        let bar_top_left = Pos2::new(rect.left(), rect.top());
-        let bar_bottom_right = Pos2::new(rect.right(), top);
-        let bar_rect = Rect::from_min_max(bar_top_left, bar_bottom_right);
-        let bar_color = Color32::from_rgb(127, 0, 0); //bar color
 
-        mesh.add_colored_rect(bar_rect, bar_color);
+        //bar
+        add(
+            rect.left(),
+            rect.top(),
+            rect.right(),
+            top,
+            &[theme.bar_color],
+        );
 
         for (i, key) in key_view.iter_visible_keys() {
             if key.black {
-                let color = colors[i].map(map_color).unwrap_or(Color32::BLACK);
-
-                if color == Color32::BLACK {  // Not pressed
-                    let black_key_bottom = md_height;
-
-                    let top_left1 = Pos2::new(map_x(key.left) - rounded, top);
-                    let bottom_right1 = Pos2::new(map_x(key.right) + rounded, black_bottom);
-                    let rect1 = Rect::from_min_max(top_left1, bottom_right1);
-
-                    let top_left2 = Pos2::new(map_x(key.left), top - black_key_overlap);
-                    let bottom_right2 = Pos2::new(map_x(key.right), black_bottom - black_key_bottom);
-                    let rect2 = Rect::from_min_max(top_left2, bottom_right2);
-                    let color2 = Color32::from_rgb(33, 33, 33); //inner color black key
-
-                    mesh.add_colored_rect(rect1, color);
-                    mesh.add_colored_rect(rect2, color2);
-                } else {  // Pressed
-                    let top_left1 = Pos2::new(map_x(key.left), top);
-                    let bottom_right1 = Pos2::new(map_x(key.right), black_bottom);
-                    let rect1 = Rect::from_min_max(top_left1, bottom_right1);
-                    let color1 = Color32::from_rgb(color.r() / 2, color.g() / 2, color.b() / 2);
-
-                    let top_left2 = Pos2::new(map_x(key.left) + rounded, top + rounded);
-                    let bottom_right2 = Pos2::new(map_x(key.right) - rounded, black_bottom - rounded);
-                    let rect2 = Rect::from_min_max(top_left2, bottom_right2);
-
-                    mesh.add_colored_rect(rect1, color1);
-                    mesh.add_colored_rect(rect2, color);
-                }
+                let down = self.key_press[i] * theme.key_down_offset;
+                let (outer_colors, inner_colors) = if presses[i].is_pressed() {
+                    let colors = pressed_colors(&presses[i], theme.black_key);
+                    let dark = colors
+                        .iter()
+                        .map(|color| Color32::from_rgb(color.r() / 2, color.g() / 2, color.b() / 2))
+                        .collect();
+                    (dark, colors)
+                } else {
+                    (vec![theme.black_key], vec![theme.black_key_top])
+                };
+
+                // Going down, the outline shrinks to the key and the top
+                // moves from above the bar to inside the outline
+                let (left, right) = (map_x(key.left), map_x(key.right));
+                add(
+                    left - lerp(rounded, 0.0, down),
+                    top,
+                    right + lerp(rounded, 0.0, down),
+                    black_bottom,
+                    &outer_colors,
+                );
+                add(
+                    left + lerp(0.0, rounded, down),
+                    lerp(top - black_key_overlap, top + rounded, down),
+                    right - lerp(0.0, rounded, down),
+                    lerp(black_bottom - bevel, black_bottom - rounded, down),
+                    &inner_colors,
+                );
             }
         }
 
-        ui.painter().add(mesh);
+        rects
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,41 @@
+/// How much space the keyboard takes across the note axis
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum KeyboardHeight {
+    /// A fraction of the keyboard's length, so it keeps its shape
+    Fraction(f32),
+    /// A fixed size in pixels
+    Pixels(f32),
+    /// No keyboard, the notes fill the window
+    Hidden,
+}
+
+impl Default for KeyboardHeight {
+    fn default() -> Self {
+        KeyboardHeight::Fraction(70.0 / 760.0)
+    }
+}
+
+impl KeyboardHeight {
+    pub fn name(&self) -> &'static str {
+        match self {
+            KeyboardHeight::Fraction(_) => "Relative",
+            KeyboardHeight::Pixels(_) => "Pixels",
+            KeyboardHeight::Hidden => "Hidden",
+        }
+    }
+
+    /// The height of a keyboard that is `length` long, leaving at least half
+    /// of `available` to the notes
+    pub fn height(&self, length: f32, available: f32) -> f32 {
+        let height = match *self {
+            KeyboardHeight::Fraction(fraction) => fraction * length,
+            KeyboardHeight::Pixels(pixels) => pixels,
+            KeyboardHeight::Hidden => 0.0,
+        };
+        // The available space goes negative while the window is tiny
+        height.clamp(0.0, (available / 2.0).max(0.0)).round()
+    }
+}
+
+#[cfg(test)]
+mod tests;
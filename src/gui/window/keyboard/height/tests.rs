@@ -0,0 +1,23 @@
+use super::KeyboardHeight;
+
+#[test]
+fn height() {
+    assert_eq!(KeyboardHeight::Fraction(0.1).height(1000.0, 800.0), 100.0);
+    assert_eq!(KeyboardHeight::Pixels(150.0).height(1000.0, 800.0), 150.0);
+    assert_eq!(KeyboardHeight::Hidden.height(1000.0, 800.0), 0.0);
+}
+
+#[test]
+fn height_leaves_half_to_the_notes() {
+    assert_eq!(KeyboardHeight::Pixels(500.0).height(1000.0, 800.0), 400.0);
+    assert_eq!(KeyboardHeight::Fraction(1.0).height(1000.0, 800.0), 400.0);
+}
+
+#[test]
+fn height_without_space() {
+    for available in [0.0, -1.0, -250.0] {
+        assert_eq!(KeyboardHeight::Pixels(150.0).height(1000.0, available), 0.0);
+        assert_eq!(KeyboardHeight::Fraction(0.1).height(1000.0, available), 0.0);
+        assert_eq!(KeyboardHeight::Hidden.height(1000.0, available), 0.0);
+    }
+}
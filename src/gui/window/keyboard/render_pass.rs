@@ -0,0 +1,219 @@
+use std::sync::Arc;
+
+use bytemuck::{Pod, Zeroable};
+use egui::{Color32, Rect};
+use vulkano::{
+    buffer::{BufferUsage, CpuAccessibleBuffer},
+    command_buffer::{AutoCommandBufferBuilder, CommandBufferUsage, SubpassContents},
+    device::Queue,
+    format::ClearValue,
+    image::{ImageAccess, ImageViewAbstract},
+    pipeline::{
+        graphics::{
+            color_blend::{AttachmentBlend, ColorBlendState},
+            input_assembly::InputAssemblyState,
+            vertex_input::BuffersDefinition,
+            viewport::{Viewport, ViewportState},
+        },
+        GraphicsPipeline, Pipeline,
+    },
+    render_pass::{Framebuffer, FramebufferCreateInfo, RenderPass, Subpass},
+};
+
+use crate::{
+    gui::{
+        window::scene::{submit, wait_for},
+        GuiRenderer,
+    },
+    renderer::color_space::{needs_srgb_encoding, srgb_to_linear},
+};
+
+#[repr(C)]
+#[derive(Default, Debug, Copy, Clone, Zeroable, Pod)]
+pub struct KeyVertex {
+    pub position: [f32; 2],
+    pub color: [f32; 4],
+}
+vulkano::impl_vertex!(KeyVertex, position, color);
+
+/// Draws the keyboard into an image with Vulkan, the same way the GUI draws
+/// it, so that it can be rendered together with the notes off screen
+pub struct KeyboardRenderPass {
+    gfx_queue: Arc<Queue>,
+    pipeline: Arc<GraphicsPipeline>,
+    render_pass: Arc<RenderPass>,
+    encode_srgb: bool,
+}
+
+impl KeyboardRenderPass {
+    pub fn new(renderer: &GuiRenderer) -> KeyboardRenderPass {
+        let gfx_queue = renderer.queue.clone();
+
+        let render_pass = vulkano::single_pass_renderpass!(gfx_queue.device().clone(),
+            attachments: {
+                final_color: {
+                    load: Load,
+                    store: Store,
+                    format: renderer.format,
+                    samples: 1,
+                }
+            },
+            pass: {
+                color: [final_color],
+                depth_stencil: {}
+            }
+        )
+        .unwrap();
+
+        let vs = vs::load(gfx_queue.device().clone()).expect("failed to create shader module");
+        let fs = fs::load(gfx_queue.device().clone()).expect("failed to create shader module");
+
+        let pipeline = GraphicsPipeline::start()
+            .input_assembly_state(InputAssemblyState::new())
+            .vertex_input_state(BuffersDefinition::new().vertex::<KeyVertex>())
+            .vertex_shader(vs.entry_point("main").unwrap(), ())
+            .fragment_shader(fs.entry_point("main").unwrap(), ())
+            .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+            .color_blend_state(ColorBlendState::new(1).blend(AttachmentBlend::alpha()))
+            .render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
+            .build(gfx_queue.device().clone())
+            .unwrap();
+
+        KeyboardRenderPass {
+            gfx_queue,
+            pipeline,
+            render_pass,
+            encode_srgb: needs_srgb_encoding(renderer.format),
+        }
+    }
+
+    /// Draws the rects, given in pixels, over the image in order
+    pub fn draw(
+        &mut self,
+        final_image: Arc<dyn ImageViewAbstract + 'static>,
+        rects: &[(Rect, Color32)],
+    ) {
+        if rects.is_empty() {
+            return;
+        }
+
+        let img_dims = final_image.image().dimensions().width_height();
+        let [width, height] = [img_dims[0] as f32, img_dims[1] as f32];
+
+        let vertices = rects.iter().flat_map(|(rect, color)| {
+            let color = [
+                srgb_to_linear(color.r()),
+                srgb_to_linear(color.g()),
+                srgb_to_linear(color.b()),
+                color.a() as f32 / 255.0,
+            ];
+            let vertex = |x: f32, y: f32| KeyVertex {
+                position: [x / width * 2.0 - 1.0, y / height * 2.0 - 1.0],
+                color,
+            };
+            [
+                vertex(rect.left(), rect.top()),
+                vertex(rect.right(), rect.top()),
+                vertex(rect.left(), rect.bottom()),
+                vertex(rect.right(), rect.top()),
+                vertex(rect.right(), rect.bottom()),
+                vertex(rect.left(), rect.bottom()),
+            ]
+        });
+
+        let vertex_buffer = CpuAccessibleBuffer::from_iter(
+            self.gfx_queue.device().clone(),
+            BufferUsage::vertex_buffer(),
+            false,
+            vertices,
+        )
+        .expect("failed to create keyboard buffer");
+
+        let framebuffer = Framebuffer::new(
+            self.render_pass.clone(),
+            FramebufferCreateInfo {
+                attachments: vec![final_image],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let mut command_buffer_builder = AutoCommandBufferBuilder::primary(
+            self.gfx_queue.device().clone(),
+            self.gfx_queue.family(),
+            CommandBufferUsage::OneTimeSubmit,
+        )
+        .unwrap();
+
+        command_buffer_builder
+            .begin_render_pass(framebuffer, SubpassContents::Inline, vec![ClearValue::None])
+            .unwrap()
+            .bind_pipeline_graphics(self.pipeline.clone())
+            .set_viewport(
+                0,
+                [Viewport {
+                    origin: [0.0, 0.0],
+                    dimensions: [width, height],
+                    depth_range: 0.0..1.0,
+                }],
+            )
+            .push_constants(
+                self.pipeline.layout().clone(),
+                0,
+                fs::ty::PushConstants {
+                    encode_srgb: self.encode_srgb as u32,
+                },
+            )
+            .bind_vertex_buffers(0, vertex_buffer)
+            .draw(rects.len() as u32 * 6, 1, 0, 0)
+            .unwrap()
+            .end_render_pass()
+            .unwrap();
+
+        let command_buffer = command_buffer_builder.build().unwrap();
+        wait_for(submit(&self.gfx_queue, None, command_buffer));
+    }
+}
+
+mod vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: "
+#version 450
+layout(location = 0) in vec2 position;
+layout(location = 1) in vec4 color;
+
+layout(location = 0) out vec4 v_color;
+
+void main() {
+    gl_Position = vec4(position, 0, 1);
+    v_color = color;
+}"
+    }
+}
+
+mod fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        include: ["shaders"],
+        src: "
+#version 450
+
+#include <srgb.glsl>
+
+layout(location = 0) in vec4 v_color;
+
+layout(location = 0) out vec4 out_color;
+
+layout(push_constant) uniform PushConstants {
+    // See srgb.glsl
+    uint encode_srgb;
+} consts;
+
+void main() {
+    vec3 color = v_color.rgb;
+    color = encode_output(color, consts.encode_srgb);
+    out_color = vec4(color, v_color.a);
+}"
+    }
+}
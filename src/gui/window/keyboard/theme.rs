@@ -0,0 +1,113 @@
+use egui::Color32;
+
+/// How the keyboard is drawn
+#[derive(Debug, Clone, PartialEq)]
+pub struct KeyboardTheme {
+    /// The bar between the notes and the keys
+    pub bar_color: Color32,
+    pub white_key: Color32,
+    /// The front of the white keys, visible below their top
+    pub white_key_side: Color32,
+    pub black_key: Color32,
+    /// The top of the black keys, drawn inside their outline
+    pub black_key_top: Color32,
+    pub border: Color32,
+    /// How much a pressed key takes the note color, 0 keeps the key color
+    pub pressed_blend: f32,
+    /// How much quieter notes darken the key, 0 ignores the velocity
+    pub velocity_brightness: f32,
+    /// The height of the key fronts, relative to the default
+    pub bevel_depth: f32,
+    /// How far a pressed key moves down, 1 hides the white key front
+    pub key_down_offset: f32,
+    /// Seconds a key takes to go down or come back up, 0 for instantly
+    pub press_time: f32,
+}
+
+impl Default for KeyboardTheme {
+    fn default() -> Self {
+        KeyboardTheme {
+            bar_color: Color32::from_rgb(127, 0, 0),
+            white_key: Color32::WHITE,
+            white_key_side: Color32::from_rgb(130, 130, 130),
+            black_key: Color32::BLACK,
+            black_key_top: Color32::from_rgb(33, 33, 33),
+            border: Color32::from_rgb(33, 33, 33),
+            pressed_blend: 1.0,
+            velocity_brightness: 0.0,
+            bevel_depth: 1.0,
+            key_down_offset: 1.0,
+            press_time: 0.0,
+        }
+    }
+}
+
+impl KeyboardTheme {
+    pub fn presets() -> Vec<(&'static str, KeyboardTheme)> {
+        vec![
+            ("Wasabi", KeyboardTheme::default()),
+            (
+                "Flat",
+                KeyboardTheme {
+                    bar_color: Color32::from_rgb(60, 60, 60),
+                    black_key_top: Color32::BLACK,
+                    bevel_depth: 0.0,
+                    ..Default::default()
+                },
+            ),
+            (
+                "Soft",
+                KeyboardTheme {
+                    bar_color: Color32::from_rgb(90, 90, 100),
+                    white_key_side: Color32::from_rgb(170, 170, 175),
+                    black_key_top: Color32::from_rgb(50, 50, 55),
+                    pressed_blend: 0.7,
+                    velocity_brightness: 0.5,
+                    key_down_offset: 0.6,
+                    press_time: 0.05,
+                    ..Default::default()
+                },
+            ),
+            (
+                "Dark",
+                KeyboardTheme {
+                    bar_color: Color32::from_rgb(20, 20, 20),
+                    white_key: Color32::from_rgb(70, 70, 70),
+                    white_key_side: Color32::from_rgb(40, 40, 40),
+                    black_key: Color32::from_rgb(10, 10, 10),
+                    black_key_top: Color32::from_rgb(25, 25, 25),
+                    border: Color32::from_rgb(10, 10, 10),
+                    ..Default::default()
+                },
+            ),
+            (
+                "Deep",
+                KeyboardTheme {
+                    bevel_depth: 2.0,
+                    key_down_offset: 0.5,
+                    press_time: 0.08,
+                    ..Default::default()
+                },
+            ),
+        ]
+    }
+
+    /// The color of a key pressed by a note of `note_color` and `velocity`
+    pub fn pressed_color(&self, key_color: Color32, note_color: Color32, velocity: u8) -> Color32 {
+        let brightness = 1.0 - self.velocity_brightness * (1.0 - velocity as f32 / 127.0);
+        let note_color = mix(Color32::BLACK, note_color, brightness);
+        mix(key_color, note_color, self.pressed_blend)
+    }
+}
+
+/// Linearly mixes two colors, `t` = 0 gives `a`
+pub fn mix(a: Color32, b: Color32, t: f32) -> Color32 {
+    let t = t.clamp(0.0, 1.0);
+    let channel = |a: u8, b: u8| (a as f32 + (b as f32 - a as f32) * t).round() as u8;
+    Color32::from_rgba_premultiplied(
+        channel(a.r(), b.r()),
+        channel(a.g(), b.g()),
+        channel(a.b(), b.b()),
+        channel(a.a(), b.a()),
+    )
+}
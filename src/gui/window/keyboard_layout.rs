@@ -1,5 +1,10 @@
+mod custom;
+mod scale;
+
 use std::ops::Range;
 
+pub use self::{custom::CustomLayout, scale::Scale};
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum KeyboardParams {
     SameWidth,
@@ -8,19 +13,87 @@ pub enum KeyboardParams {
         black_key_3_set_offset: f32,
         black_key_scale: f32,
     },
+    /// The proportions of a real piano, black keys are 7/12 of a white key
+    /// wide and each sits in its own twelfth of the octave
+    Piano,
+    /// Every key the same width next to each other, black keys included
+    Isomorphic,
+    Custom(CustomLayout),
 }
 
 impl Default for KeyboardParams {
     fn default() -> Self {
+        KeyboardParams::default_classic()
+    }
+}
+
+impl KeyboardParams {
+    pub fn default_classic() -> Self {
         KeyboardParams::Classic {
             black_key_2_set_offset: 0.3,
             black_key_3_set_offset: 0.5,
             black_key_scale: 0.7,
         }
     }
+
+    pub fn name(&self) -> String {
+        match self {
+            KeyboardParams::SameWidth => "Same Width".to_string(),
+            KeyboardParams::Classic { .. } => "Classic".to_string(),
+            KeyboardParams::Piano => "Piano".to_string(),
+            KeyboardParams::Isomorphic => "Isomorphic".to_string(),
+            KeyboardParams::Custom(layout) => layout.name(),
+        }
+    }
+}
+
+/// The direction notes travel in on the piano roll
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RollOrientation {
+    /// Notes fall down onto a keyboard at the bottom
+    Down,
+    /// Notes rise up onto a keyboard at the top
+    Up,
+    /// Notes travel left onto a keyboard on the left, low keys at the bottom
+    Horizontal,
+}
+
+impl Default for RollOrientation {
+    fn default() -> Self {
+        RollOrientation::Down
+    }
+}
+
+impl RollOrientation {
+    pub const ALL: [RollOrientation; 3] = [
+        RollOrientation::Down,
+        RollOrientation::Up,
+        RollOrientation::Horizontal,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            RollOrientation::Down => "Falling Down",
+            RollOrientation::Up => "Rising Up",
+            RollOrientation::Horizontal => "Horizontal",
+        }
+    }
+
+    /// The orientation as passed to the note shaders
+    pub fn as_u32(&self) -> u32 {
+        match self {
+            RollOrientation::Down => 0,
+            RollOrientation::Up => 1,
+            RollOrientation::Horizontal => 2,
+        }
+    }
+
+    pub fn is_horizontal(&self) -> bool {
+        *self == RollOrientation::Horizontal
+    }
 }
 
-#[derive(Debug, Clone, Copy, Default)]
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
 pub struct KeyPosition {
     pub black: bool,
     pub left: f32,
@@ -40,6 +113,10 @@ impl KeyPosition {
 pub struct KeyboardLayout {
     keys: [KeyPosition; 257],
     notes: [KeyPosition; 257],
+    /// If the black keys don't overlap the white keys, and go all the way down
+    full_height_black_keys: bool,
+    params: KeyboardParams,
+    scale: Option<Scale>,
 }
 
 const fn is_black(key: usize) -> bool {
@@ -66,94 +143,181 @@ fn load_key_numbers() -> [usize; 257] {
 }
 
 impl KeyboardLayout {
-    pub fn new(params: &KeyboardParams) -> KeyboardLayout {
+    /// Builds the layout. A scale that doesn't color the keys like a piano
+    /// lays them out in an even grid instead, except in custom layouts.
+    pub fn new(params: &KeyboardParams, scale: Option<&Scale>) -> KeyboardLayout {
         let mut keys = [Default::default(); 257];
         let mut notes = [Default::default(); 257];
 
         // let mut key_numbers = Vec::new();
 
         let last_key = 256.0;
-
-        match params {
-            KeyboardParams::SameWidth => {
-                for i in 0..257 {
-                    let left = i as f32 / last_key;
-                    let right = (i + 1) as f32 / last_key;
-
-                    notes[i] = KeyPosition::new(i, left, right);
-
-                    let mut left = left;
-                    let mut right = right;
-
-                    let n = i % 12;
-
-                    let half = 1.0 / 2.0;
-                    let third = 1.0 / 3.0;
-                    let quarter = 1.0 / 4.0;
-
-                    if n == 0 {
-                        right += third * 2.0;
-                    } else if n == 2 {
-                        left -= third;
-                        right += third;
-                    } else if n == 4 {
-                        left -= third * 2.0
-                    } else if n == 5 {
-                        right += half + quarter;
-                    } else if n == 7 {
-                        left -= quarter;
-                        right += half;
-                    } else if n == 9 {
-                        left -= half;
-                        right += quarter;
-                    } else if n == 11 {
-                        left -= half + quarter;
-                    }
-
-                    keys[i] = KeyPosition::new(i, left, right);
-                }
+        let mut full_height_black_keys = false;
+
+        let grid_scale = match params {
+            KeyboardParams::Custom(_) => None,
+            _ => scale.filter(|scale| !scale.has_piano_colors()),
+        };
+
+        if let Some(scale) = grid_scale {
+            for i in 0..257 {
+                let left = i as f32;
+                let right = left + 1.0;
+                let key = KeyPosition {
+                    black: scale.is_black(i),
+                    left,
+                    right,
+                };
+
+                notes[i] = key;
+                keys[i] = key;
             }
-            KeyboardParams::Classic {
-                black_key_2_set_offset,
-                black_key_3_set_offset,
-                black_key_scale,
-            } => {
-                let key_numbers = load_key_numbers();
-
-                for i in 0..257 {
-                    if !is_black(i) {
-                        let left = key_numbers[i] as f32;
-                        let right = left + 1.0;
+            full_height_black_keys = true;
+        } else {
+            match params {
+                KeyboardParams::SameWidth => {
+                    for i in 0..257 {
+                        let left = i as f32 / last_key;
+                        let right = (i + 1) as f32 / last_key;
 
                         notes[i] = KeyPosition::new(i, left, right);
+
+                        let mut left = left;
+                        let mut right = right;
+
+                        let n = i % 12;
+
+                        let half = 1.0 / 2.0;
+                        let third = 1.0 / 3.0;
+                        let quarter = 1.0 / 4.0;
+
+                        if n == 0 {
+                            right += third * 2.0;
+                        } else if n == 2 {
+                            left -= third;
+                            right += third;
+                        } else if n == 4 {
+                            left -= third * 2.0
+                        } else if n == 5 {
+                            right += half + quarter;
+                        } else if n == 7 {
+                            left -= quarter;
+                            right += half;
+                        } else if n == 9 {
+                            left -= half;
+                            right += quarter;
+                        } else if n == 11 {
+                            left -= half + quarter;
+                        }
+
                         keys[i] = KeyPosition::new(i, left, right);
-                    } else {
-                        let _i = i + 1;
-                        let half_width = black_key_scale / 2.0;
-                        let black_num = key_numbers[i] % 5;
-                        let mut offset = half_width;
-
-                        if black_num == 0 {
-                            offset += half_width * black_key_2_set_offset;
-                        } else if black_num == 2 {
-                            offset += half_width * black_key_3_set_offset;
-                        } else if black_num == 1 {
-                            offset -= half_width * black_key_2_set_offset;
-                        } else if black_num == 4 {
-                            offset -= half_width * black_key_3_set_offset;
+                    }
+                }
+                KeyboardParams::Classic {
+                    black_key_2_set_offset,
+                    black_key_3_set_offset,
+                    black_key_scale,
+                } => {
+                    let key_numbers = load_key_numbers();
+
+                    for i in 0..257 {
+                        if !is_black(i) {
+                            let left = key_numbers[i] as f32;
+                            let right = left + 1.0;
+
+                            notes[i] = KeyPosition::new(i, left, right);
+                            keys[i] = KeyPosition::new(i, left, right);
+                        } else {
+                            let _i = i + 1;
+                            let half_width = black_key_scale / 2.0;
+                            let black_num = key_numbers[i] % 5;
+                            let mut offset = half_width;
+
+                            if black_num == 0 {
+                                offset += half_width * black_key_2_set_offset;
+                            } else if black_num == 2 {
+                                offset += half_width * black_key_3_set_offset;
+                            } else if black_num == 1 {
+                                offset -= half_width * black_key_2_set_offset;
+                            } else if black_num == 4 {
+                                offset -= half_width * black_key_3_set_offset;
+                            }
+
+                            let left = key_numbers[_i] as f32 - offset;
+                            let right = left + black_key_scale;
+
+                            notes[i] = KeyPosition::new(i, left, right);
+                            keys[i] = KeyPosition::new(i, left, right);
                         }
+                    }
+                }
+                KeyboardParams::Piano => {
+                    let key_numbers = load_key_numbers();
+                    let slot = 7.0 / 12.0;
+
+                    for i in 0..257 {
+                        let (left, right) = if !is_black(i) {
+                            let left = key_numbers[i] as f32;
+                            (left, left + 1.0)
+                        } else {
+                            let octave_start = (i / 12) as f32 * 7.0;
+                            let left = octave_start + (i % 12) as f32 * slot;
+                            (left, left + slot)
+                        };
 
-                        let left = key_numbers[_i] as f32 - offset;
-                        let right = left + black_key_scale;
+                        notes[i] = KeyPosition::new(i, left, right);
+                        keys[i] = KeyPosition::new(i, left, right);
+                    }
+                }
+                KeyboardParams::Isomorphic => {
+                    for i in 0..257 {
+                        let left = i as f32;
+                        let right = left + 1.0;
 
                         notes[i] = KeyPosition::new(i, left, right);
                         keys[i] = KeyPosition::new(i, left, right);
                     }
+                    full_height_black_keys = true;
+                }
+                KeyboardParams::Custom(layout) => {
+                    // Keys the file doesn't describe get squashed at its right edge
+                    let edge = layout
+                        .keys
+                        .iter()
+                        .fold(0.0f32, |edge, key| edge.max(key.right));
+
+                    for i in 0..257 {
+                        let key = layout.keys.get(i).copied().unwrap_or(KeyPosition {
+                            black: is_black(i),
+                            left: edge,
+                            right: edge,
+                        });
+
+                        notes[i] = key;
+                        keys[i] = key;
+                    }
+                    full_height_black_keys = layout.full_height_black_keys;
                 }
             }
         }
 
-        KeyboardLayout { keys, notes }
+        KeyboardLayout {
+            keys,
+            notes,
+            full_height_black_keys,
+            params: params.clone(),
+            scale: scale.cloned(),
+        }
+    }
+
+    /// The params the layout was built from
+    pub fn params(&self) -> &KeyboardParams {
+        &self.params
+    }
+
+    /// The scale the layout was built with
+    pub fn scale(&self) -> Option<&Scale> {
+        self.scale.as_ref()
     }
 
     pub fn get_range_for_keys(&self, first_key: usize, last_key: usize) -> KeyboardRange {
@@ -170,16 +334,18 @@ impl KeyboardLayout {
         let mut right_key = last_key;
 
         if self.keys[left_key].black {
-            left_key -= 1;
+            left_key = left_key.saturating_sub(1);
         }
-        if self.keys[right_key - 1].black {
-            right_key += 1;
+        if self.keys[right_key.saturating_sub(1)].black {
+            right_key = (right_key + 1).min(257);
         }
 
         KeyboardView {
             layout: self,
             range,
             visible_range: left_key..right_key,
+            orientation: Default::default(),
+            mirrored: false,
         }
     }
 
@@ -188,7 +354,7 @@ impl KeyboardLayout {
             .keys
             .iter()
             .position(|x| x.right >= range.left)
-            .unwrap_or(257);
+            .unwrap_or(256);
         let mut right_key = self
             .keys
             .iter()
@@ -196,10 +362,10 @@ impl KeyboardLayout {
             .unwrap_or(257);
 
         if self.keys[left_key].black {
-            left_key -= 1;
+            left_key = left_key.saturating_sub(1);
         }
-        if self.keys[right_key - 1].black {
-            right_key += 1;
+        if self.keys[right_key.saturating_sub(1)].black {
+            right_key = (right_key + 1).min(257);
         }
 
         if left_key > right_key {
@@ -210,6 +376,8 @@ impl KeyboardLayout {
             layout: self,
             range,
             visible_range: left_key..right_key,
+            orientation: Default::default(),
+            mirrored: false,
         }
     }
 }
@@ -234,25 +402,48 @@ pub struct KeyboardView<'a> {
     layout: &'a KeyboardLayout,
     pub range: KeyboardRange,
     pub visible_range: Range<usize>,
+    pub orientation: RollOrientation,
+    /// If true, the high keys are on the left (or the top, when horizontal)
+    pub mirrored: bool,
 }
 
 impl<'a> KeyboardView<'a> {
-    pub fn key(&self, key: usize) -> KeyPosition {
-        let key = self.layout.keys[key];
-        KeyPosition {
-            black: key.black,
-            left: self.range.transform(key.left),
-            right: self.range.transform(key.right),
+    /// If the black keys go all the way down like the white keys
+    pub fn full_height_black_keys(&self) -> bool {
+        self.layout.full_height_black_keys
+    }
+
+    pub fn oriented(mut self, orientation: RollOrientation, mirrored: bool) -> Self {
+        self.orientation = orientation;
+        self.mirrored = mirrored;
+        self
+    }
+
+    fn transform(&self, position: KeyPosition) -> KeyPosition {
+        let left = self.range.transform(position.left);
+        let right = self.range.transform(position.right);
+
+        if self.mirrored {
+            KeyPosition {
+                black: position.black,
+                left: 1.0 - right,
+                right: 1.0 - left,
+            }
+        } else {
+            KeyPosition {
+                black: position.black,
+                left,
+                right,
+            }
         }
     }
 
+    pub fn key(&self, key: usize) -> KeyPosition {
+        self.transform(self.layout.keys[key])
+    }
+
     pub fn note(&self, key: usize) -> KeyPosition {
-        let note = self.layout.notes[key];
-        KeyPosition {
-            black: note.black,
-            left: self.range.transform(note.left),
-            right: self.range.transform(note.right),
-        }
+        self.transform(self.layout.notes[key])
     }
 
     pub fn iter_visible_keys<'b>(&'b self) -> impl 'b + Iterator<Item = (usize, KeyPosition)> {
@@ -0,0 +1,82 @@
+use std::{fs, path::PathBuf};
+
+use serde::Deserialize;
+
+use super::KeyPosition;
+
+/// A layout file, a JSON object like
+/// `{"keys": [{"left": 0.0, "right": 1.0, "black": false}, ...]}`
+/// with the keys in order from key 0. The positions can use any unit.
+#[derive(Deserialize)]
+struct LayoutFile {
+    keys: Vec<LayoutFileKey>,
+    /// Whether the black keys go down to the bottom of the keyboard, for
+    /// layouts where they don't overlap the white keys
+    #[serde(default)]
+    full_height_black_keys: bool,
+}
+
+#[derive(Deserialize)]
+struct LayoutFileKey {
+    left: f32,
+    right: f32,
+    black: bool,
+}
+
+/// A keyboard layout loaded from a file
+#[derive(Debug, Clone, PartialEq)]
+pub struct CustomLayout {
+    pub path: PathBuf,
+    pub keys: Vec<KeyPosition>,
+    pub full_height_black_keys: bool,
+}
+
+impl CustomLayout {
+    pub fn load(path: PathBuf) -> Result<Self, String> {
+        let text = fs::read_to_string(&path).map_err(|e| e.to_string())?;
+        let file: LayoutFile = serde_json::from_str(&text).map_err(|e| e.to_string())?;
+
+        if file.keys.is_empty() {
+            return Err("The layout has no keys".to_string());
+        }
+        if file.keys.len() > 257 {
+            return Err(format!(
+                "The layout has {} keys, at most 257 are supported",
+                file.keys.len()
+            ));
+        }
+        if let Some(i) = file
+            .keys
+            .iter()
+            .position(|key| key.left.is_nan() || key.right.is_nan() || key.left > key.right)
+        {
+            return Err(format!("Key {} ends before it starts", i));
+        }
+
+        let keys = file
+            .keys
+            .iter()
+            .map(|key| KeyPosition {
+                black: key.black,
+                left: key.left,
+                right: key.right,
+            })
+            .collect();
+
+        Ok(CustomLayout {
+            path,
+            keys,
+            full_height_black_keys: file.full_height_black_keys,
+        })
+    }
+
+    pub fn name(&self) -> String {
+        self.path
+            .file_stem()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "Custom".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,83 @@
+use std::{env, fs};
+
+use super::CustomLayout;
+
+/// Loads the text as a layout file from the temporary directory
+fn load(name: &str, text: &str) -> Result<CustomLayout, String> {
+    let path = env::temp_dir().join(format!("wasabi_layout_test_{}.json", name));
+    fs::write(&path, text).unwrap();
+    let layout = CustomLayout::load(path.clone());
+    fs::remove_file(&path).unwrap();
+    layout
+}
+
+#[test]
+fn load_keys() {
+    let layout = load(
+        "keys",
+        r#"{"keys": [
+            {"left": 0.0, "right": 2.0, "black": false},
+            {"left": 1.5, "right": 2.5, "black": true},
+            {"left": 2, "right": 4, "black": false}
+        ]}"#,
+    )
+    .unwrap();
+
+    let keys: Vec<_> = layout
+        .keys
+        .iter()
+        .map(|key| (key.left, key.right, key.black))
+        .collect();
+    assert_eq!(
+        keys,
+        [(0.0, 2.0, false), (1.5, 2.5, true), (2.0, 4.0, false)]
+    );
+    assert!(!layout.full_height_black_keys);
+    assert_eq!(layout.name(), "wasabi_layout_test_keys");
+}
+
+#[test]
+fn load_full_height_black_keys() {
+    let layout = load(
+        "full_height",
+        r#"{"keys": [{"left": 0, "right": 1, "black": true}], "full_height_black_keys": true}"#,
+    )
+    .unwrap();
+
+    assert!(layout.full_height_black_keys);
+}
+
+#[test]
+fn load_max_keys() {
+    let key = r#"{"left": 0, "right": 1, "black": false}"#;
+    let keys = |count: usize| vec![key; count].join(",");
+
+    let layout = load("257_keys", &format!(r#"{{"keys": [{}]}}"#, keys(257))).unwrap();
+    assert_eq!(layout.keys.len(), 257);
+
+    assert!(load("258_keys", &format!(r#"{{"keys": [{}]}}"#, keys(258))).is_err());
+}
+
+#[test]
+fn load_malformed() {
+    assert!(load("empty_file", "").is_err());
+    assert!(load("not_json", "keys: []").is_err());
+    assert!(load("no_keys", r#"{"keys": []}"#).is_err());
+    assert!(load("missing_field", r#"{"keys": [{"left": 0, "right": 1}]}"#).is_err());
+    assert!(load(
+        "wrong_type",
+        r#"{"keys": [{"left": "0", "right": 1, "black": false}]}"#
+    )
+    .is_err());
+    assert!(load(
+        "backwards",
+        r#"{"keys": [{"left": 1, "right": 0, "black": false}]}"#
+    )
+    .is_err());
+}
+
+#[test]
+fn load_missing_file() {
+    let path = env::temp_dir().join("wasabi_layout_test_missing.json");
+    assert!(CustomLayout::load(path).is_err());
+}
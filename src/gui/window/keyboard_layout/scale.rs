@@ -0,0 +1,132 @@
+use std::{fs, path::PathBuf};
+
+/// A scale loaded from a Scala `.scl` file, which decides the key colors
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scale {
+    pub path: PathBuf,
+    pub description: String,
+    /// The pitch of each degree above the first one in cents, starting at 0
+    degrees: Vec<f32>,
+    /// Which degrees get black keys
+    black: Vec<bool>,
+    /// The key that plays the first degree
+    pub root_key: usize,
+}
+
+impl Scale {
+    pub fn load(path: PathBuf) -> Result<Self, String> {
+        let text = fs::read_to_string(&path).map_err(|e| e.to_string())?;
+
+        // Lines starting with ! are comments. The first line is a description,
+        // then the amount of notes, then a pitch per note. The last pitch is
+        // the period, the first degree at 0 cents isn't listed.
+        let mut lines = text.lines().filter(|line| !line.starts_with('!'));
+        let description = lines.next().ok_or("The file is empty")?.trim().to_string();
+        let count: usize = lines
+            .next()
+            .and_then(|line| line.split_whitespace().next())
+            .and_then(|count| count.parse().ok())
+            .ok_or("The note count is missing")?;
+        if count == 0 {
+            return Err("The scale has no notes".to_string());
+        }
+
+        let mut pitches = Vec::with_capacity(count);
+        for line in lines.take(count) {
+            pitches.push(parse_pitch(line)?);
+        }
+        if pitches.len() < count {
+            return Err(format!(
+                "Expected {} pitches, found {}",
+                count,
+                pitches.len()
+            ));
+        }
+
+        let period = pitches.pop().unwrap();
+        if period <= 0.0 {
+            return Err("The scale's period isn't above its first note".to_string());
+        }
+
+        let mut degrees = vec![0.0];
+        degrees.append(&mut pitches);
+        let black = black_degrees(&degrees, period);
+
+        Ok(Scale {
+            path,
+            description,
+            degrees,
+            black,
+            root_key: 60,
+        })
+    }
+
+    pub fn name(&self) -> String {
+        self.path
+            .file_stem()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "Scale".to_string())
+    }
+
+    /// The amount of notes before the scale repeats
+    pub fn notes_per_period(&self) -> usize {
+        self.degrees.len()
+    }
+
+    pub fn is_black(&self, key: usize) -> bool {
+        let degree = (key as i64 - self.root_key as i64).rem_euclid(self.notes_per_period() as i64);
+        self.black[degree as usize]
+    }
+
+    /// If the scale colors the keys like a regular piano
+    pub fn has_piano_colors(&self) -> bool {
+        (0..257).all(|key| self.is_black(key) == super::is_black(key))
+    }
+}
+
+/// Parses a pitch line, either cents if it has a period or a ratio like
+/// `3/2` or `2`. Anything after the number is a comment.
+fn parse_pitch(line: &str) -> Result<f32, String> {
+    let token = line.split_whitespace().next().unwrap_or("");
+    let invalid = || format!("Invalid pitch \"{}\"", line.trim());
+
+    if token.contains('.') {
+        return token.parse().map_err(|_| invalid());
+    }
+
+    let (numerator, denominator) = token.split_once('/').unwrap_or((token, "1"));
+    let numerator: f64 = numerator.parse().map_err(|_| invalid())?;
+    let denominator: f64 = denominator.parse().map_err(|_| invalid())?;
+    if numerator <= 0.0 || denominator <= 0.0 {
+        return Err(invalid());
+    }
+
+    Ok((1200.0 * (numerator / denominator).log2()) as f32)
+}
+
+/// Matches every degree to the nearest 12-EDO key, as if the period was an
+/// octave. The degree closest to each white key gets a white key, all the
+/// others are black.
+fn black_degrees(degrees: &[f32], period: f32) -> Vec<bool> {
+    let semitones: Vec<f32> = degrees.iter().map(|cents| cents / period * 12.0).collect();
+    let nearest = |semitone: f32| (semitone.round() as i32).rem_euclid(12);
+    let distance = |semitone: f32| (semitone - semitone.round()).abs();
+
+    let mut black = vec![true; degrees.len()];
+    for white in [0, 2, 4, 5, 7, 9, 11] {
+        let closest = semitones
+            .iter()
+            .enumerate()
+            .filter(|(_, &semitone)| nearest(semitone) == white)
+            .min_by(|(_, &a), (_, &b)| distance(a).total_cmp(&distance(b)));
+
+        if let Some((degree, _)) = closest {
+            black[degree] = false;
+        }
+    }
+
+    black
+}
+
+#[cfg(test)]
+mod tests;
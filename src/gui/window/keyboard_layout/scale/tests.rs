@@ -0,0 +1,91 @@
+use std::{env, fs};
+
+use super::{parse_pitch, Scale};
+
+/// Loads the text as a scale file from the temporary directory
+fn load(name: &str, text: &str) -> Result<Scale, String> {
+    let path = env::temp_dir().join(format!("wasabi_scale_test_{}.scl", name));
+    fs::write(&path, text).unwrap();
+    let scale = Scale::load(path.clone());
+    fs::remove_file(&path).unwrap();
+    scale
+}
+
+fn assert_close(a: f32, b: f32) {
+    assert!((a - b).abs() < 1e-3, "{} != {}", a, b);
+}
+
+#[test]
+fn cents() {
+    assert_eq!(parse_pitch("100.0"), Ok(100.0));
+    assert_eq!(parse_pitch("  701.955"), Ok(701.955));
+    assert_eq!(parse_pitch("-20."), Ok(-20.0));
+    assert_eq!(parse_pitch("350.0 neutral third"), Ok(350.0));
+}
+
+#[test]
+fn ratios() {
+    assert_close(parse_pitch("3/2").unwrap(), 701.955);
+    assert_close(parse_pitch(" 5/4 just major third").unwrap(), 386.314);
+    assert_eq!(parse_pitch("2"), Ok(1200.0));
+    assert_eq!(parse_pitch("2/1"), Ok(1200.0));
+}
+
+#[test]
+fn malformed_pitches() {
+    assert!(parse_pitch("").is_err());
+    assert!(parse_pitch("third").is_err());
+    assert!(parse_pitch("1.2.3").is_err());
+    assert!(parse_pitch("3/").is_err());
+    assert!(parse_pitch("3/0").is_err());
+    assert!(parse_pitch("0/1").is_err());
+    assert!(parse_pitch("-5/4").is_err());
+}
+
+#[test]
+fn load_equal_temperament() {
+    let mut text = "! 12edo.scl\n!\n12 tone equal temperament\n 12\n!\n".to_string();
+    for step in 1..=12 {
+        text += &format!(" {}.0\n", step * 100);
+    }
+
+    let scale = load("12edo", &text).unwrap();
+    assert_eq!(scale.description, "12 tone equal temperament");
+    assert_eq!(scale.notes_per_period(), 12);
+    assert_eq!(scale.degrees[0], 0.0);
+    assert_eq!(scale.degrees[11], 1100.0);
+    assert!(scale.has_piano_colors());
+    assert_eq!(scale.name(), "wasabi_scale_test_12edo");
+}
+
+#[test]
+fn load_just_pentatonic() {
+    let text = concat!(
+        "! pentatonic.scl\nJust pentatonic\n5\n",
+        "! the octave is the period\n9/8\n5/4\n3/2\n5/3\n2/1\n"
+    );
+
+    let scale = load("pentatonic", text).unwrap();
+    assert_eq!(scale.notes_per_period(), 5);
+    assert_close(scale.degrees[2], 386.314);
+
+    // All the degrees are closest to white keys
+    for key in 60..65 {
+        assert!(!scale.is_black(key), "key {}", key);
+    }
+    // The scale repeats every 5 keys from the root key
+    assert!(!scale.has_piano_colors());
+    assert_eq!(scale.is_black(55), scale.is_black(60));
+}
+
+#[test]
+fn load_malformed() {
+    assert!(load("empty", "").is_err());
+    assert!(load("only_comments", "! nothing\n!\n").is_err());
+    assert!(load("no_count", "Description\n").is_err());
+    assert!(load("bad_count", "Description\nmany\n100.0\n").is_err());
+    assert!(load("zero_count", "Description\n0\n").is_err());
+    assert!(load("missing_pitches", "Description\n3\n100.0\n200.0\n").is_err());
+    assert!(load("bad_pitch", "Description\n2\n100.0\nfifth\n").is_err());
+    assert!(load("bad_period", "Description\n2\n100.0\n-1200.0\n").is_err());
+}
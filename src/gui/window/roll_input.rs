@@ -0,0 +1,264 @@
+use std::time::Duration;
+
+use egui::{Id, Pos2, Rect, Response, Ui, Vec2};
+
+use crate::midi::{MIDIFileBase, MIDIFileUnion};
+
+use super::keyboard_layout::{KeyPosition, KeyboardView, RollOrientation};
+
+const KEY_NAMES: [&str; 12] = [
+    "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+];
+
+/// The name of a key with its octave, key 60 is C4
+pub fn key_name(key: usize) -> String {
+    format!("{}{}", KEY_NAMES[key % 12], key as i32 / 12 - 1)
+}
+
+/// What happens to the audio while the notes are dragged
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScrubAudio {
+    /// Playback pauses until the drag ends
+    Muted,
+    /// Playback keeps going from wherever the notes are dragged to
+    Playing,
+}
+
+impl Default for ScrubAudio {
+    fn default() -> Self {
+        ScrubAudio::Muted
+    }
+}
+
+impl ScrubAudio {
+    pub const ALL: [ScrubAudio; 2] = [ScrubAudio::Muted, ScrubAudio::Playing];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ScrubAudio::Muted => "Muted",
+            ScrubAudio::Playing => "Keep Playing",
+        }
+    }
+}
+
+/// A point on the piano roll, both from 0 to 1. `key` is along the key axis
+/// like the positions of a [`KeyboardView`], `time` goes from the keyboard
+/// to the far end of the roll.
+#[derive(Debug, Clone, Copy)]
+struct RollPoint {
+    key: f32,
+    time: f32,
+}
+
+impl RollPoint {
+    fn new(rect: Rect, orientation: RollOrientation, pos: Pos2) -> Self {
+        let x = (pos.x - rect.left()) / rect.width();
+        let y = (pos.y - rect.top()) / rect.height();
+        match orientation {
+            RollOrientation::Down => RollPoint {
+                key: x,
+                time: 1.0 - y,
+            },
+            RollOrientation::Up => RollPoint { key: x, time: y },
+            RollOrientation::Horizontal => RollPoint {
+                key: 1.0 - y,
+                time: x,
+            },
+        }
+    }
+}
+
+/// How far a drag moves the notes towards the keyboard, as a fraction of
+/// the roll
+fn drag_towards_keyboard(rect: Rect, orientation: RollOrientation, delta: Vec2) -> f32 {
+    match orientation {
+        RollOrientation::Down => delta.y / rect.height(),
+        RollOrientation::Up => -delta.y / rect.height(),
+        RollOrientation::Horizontal => -delta.x / rect.width(),
+    }
+}
+
+/// The key under a position along the key axis. Black keys are checked
+/// first, their notes are the narrow ones.
+fn key_at(key_view: &KeyboardView, position: f32) -> Option<usize> {
+    let contains = |note: &KeyPosition| note.left <= position && position < note.right;
+
+    let black = key_view
+        .iter_visible_notes()
+        .find(|(_, note)| note.black && contains(note));
+    black
+        .or_else(|| {
+            key_view
+                .iter_visible_notes()
+                .find(|(_, note)| contains(note))
+        })
+        .map(|(key, _)| key)
+}
+
+/// Handles the mouse on the piano roll: clicking seeks, dragging scrubs, the
+/// wheel changes the note speed and ctrl + wheel zooms the keys
+#[derive(Default)]
+pub struct RollInput {
+    /// Whether the timer was paused when the current drag started
+    drag_was_paused: Option<bool>,
+}
+
+impl RollInput {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Seeks to the clicked time, and moves the time along with a drag
+    pub fn seek_and_scrub(
+        &mut self,
+        response: &Response,
+        key_view: &KeyboardView,
+        midi_file: &mut MIDIFileUnion,
+        note_speed: f64,
+        scrub_audio: ScrubAudio,
+    ) {
+        let length = match midi_file.midi_length() {
+            Some(length) => length,
+            None => return,
+        };
+        let rect = response.rect;
+        let orientation = key_view.orientation;
+        let time = midi_file.timer().get_time().as_secs_f64();
+        let seek = |midi_file: &mut MIDIFileUnion, time: f64| {
+            let time = time.clamp(0.0, length);
+            midi_file
+                .timer_mut()
+                .set_time(Duration::from_secs_f64(time));
+        };
+
+        if response.clicked() {
+            if let Some(pos) = response.interact_pointer_pos() {
+                let point = RollPoint::new(rect, orientation, pos);
+                seek(midi_file, time + point.time as f64 * note_speed);
+            }
+        }
+
+        if response.drag_started() {
+            let paused = midi_file.timer().is_paused();
+            self.drag_was_paused = Some(paused);
+            if scrub_audio == ScrubAudio::Muted && !paused {
+                midi_file.timer_mut().pause();
+            }
+        }
+
+        if response.dragged() {
+            let moved = drag_towards_keyboard(rect, orientation, response.drag_delta());
+            if moved != 0.0 {
+                seek(midi_file, time + moved as f64 * note_speed);
+            }
+        }
+
+        if response.drag_released() {
+            let was_paused = self.drag_was_paused.take();
+            if was_paused == Some(false) && midi_file.timer().is_paused() {
+                midi_file.timer_mut().play();
+            }
+        }
+    }
+
+    /// The wheel changes the note speed, and with ctrl it zooms the visible
+    /// keys around the cursor
+    pub fn zoom(
+        &self,
+        ui: &Ui,
+        response: &Response,
+        key_view: &KeyboardView,
+        note_speed: &mut f64,
+        first_key: &mut usize,
+        last_key: &mut usize,
+    ) {
+        let pos = match response.hover_pos() {
+            Some(pos) => pos,
+            None => return,
+        };
+
+        let input = ui.input();
+        let mut key_zoom = input.zoom_delta();
+        let scroll = input.scroll_delta.y;
+        if input.modifiers.ctrl {
+            key_zoom *= (scroll / 200.0).exp();
+        } else if scroll != 0.0 {
+            let speed = *note_speed * (-scroll as f64 / 200.0).exp();
+            *note_speed = speed.clamp(0.01, 4.0);
+        }
+
+        if key_zoom == 1.0 {
+            return;
+        }
+
+        // Keep the key under the cursor in place
+        let point = RollPoint::new(response.rect, key_view.orientation, pos);
+        let anchor = if key_view.mirrored {
+            1.0 - point.key
+        } else {
+            point.key
+        };
+
+        let count = (*last_key - *first_key) as f32;
+        let mut new_count = (count / key_zoom).round().clamp(1.0, 255.0);
+        if new_count == count {
+            new_count = if key_zoom > 1.0 {
+                (count - 1.0).max(1.0)
+            } else {
+                (count + 1.0).min(255.0)
+            };
+        }
+
+        let anchor_key = *first_key as f32 + anchor * count;
+        let first = (anchor_key - anchor * new_count)
+            .round()
+            .clamp(0.0, 255.0 - new_count);
+        *first_key = first as usize;
+        *last_key = (first + new_count) as usize;
+    }
+
+    /// Shows the key, track, channel, start and length of the note under the
+    /// cursor. Only in-ram files can be searched for notes.
+    pub fn show_note_tooltip(
+        &self,
+        ui: &Ui,
+        response: &Response,
+        key_view: &KeyboardView,
+        midi_file: &mut MIDIFileUnion,
+        note_speed: f64,
+        key_shift: i32,
+    ) {
+        if self.drag_was_paused.is_some() {
+            return;
+        }
+        let (pos, file) = match (response.hover_pos(), midi_file) {
+            (Some(pos), MIDIFileUnion::InRam(file)) => (pos, file),
+            _ => return,
+        };
+
+        let point = RollPoint::new(response.rect, key_view.orientation, pos);
+        let key = match key_at(key_view, point.key) {
+            Some(key) => key,
+            None => return,
+        };
+        let column = key as i32 - key_shift;
+        if !(0..256).contains(&column) {
+            return;
+        }
+
+        let data = file.current_view_data(note_speed);
+        let time = data.view_range().start + point.time as f64 * note_speed;
+        let (start, length, track_chan) = match data.find_note(column as usize, time) {
+            Some(note) => note,
+            None => return,
+        };
+
+        egui::show_tooltip_at_pointer(ui.ctx(), Id::new("Note tooltip"), |ui| {
+            ui.label(format!("Key: {}", key_name(column as usize)));
+            ui.label(format!("Track: {}", track_chan / 16 + 1));
+            ui.label(format!("Channel: {}", track_chan % 16 + 1));
+            ui.label(format!("Start: {:.3}s", start));
+            ui.label(format!("Length: {:.3}s", length));
+        });
+    }
+}
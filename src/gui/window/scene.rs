@@ -1,16 +1,35 @@
 mod draw_system;
 
-use egui::Ui;
+use std::sync::Arc;
+
+use egui::{Response, Sense, Ui};
+use vulkano::{
+    device::Queue,
+    image::{view::ImageView, AttachmentImage, ImageAccess, ImageUsage, ImageViewAbstract},
+};
 
 use crate::{midi::MIDIFileUnion, scenes::SceneSwapchain};
 
-use self::draw_system::{NoteRenderer, RenderResultData};
+pub use self::draw_system::{
+    antialiasing::{supported_msaa_samples, AntiAliasing, SUPERSAMPLING_FACTORS},
+    buffer_budget::NoteBufferSettings,
+    effects::EffectsSettings,
+    key_press::{KeyColorMode, KeyPress},
+    note_style::NoteStyle,
+    overlap::{NoteOverlap, OverlapMode},
+    submit, wait_for,
+};
+
+use self::draw_system::{DrawTarget, NoteRenderer, RenderResultData};
 
 use super::{keyboard_layout::KeyboardView, GuiRenderer, GuiState};
 
 pub struct GuiRenderScene {
     swap_chain: SceneSwapchain,
     draw_system: NoteRenderer,
+    queue: Arc<Queue>,
+    /// The image the last screenshot was drawn to, until it is taken
+    screenshot: Option<Arc<ImageView<AttachmentImage>>>,
 }
 
 impl GuiRenderScene {
@@ -19,6 +38,43 @@ impl GuiRenderScene {
         Self {
             swap_chain: SceneSwapchain::new(renderer.device.clone()),
             draw_system,
+            queue: renderer.queue.clone(),
+            screenshot: None,
+        }
+    }
+
+    /// The image of the screenshot drawn by the last [`GuiRenderScene::draw`]
+    /// call. It stays valid until the next frame is drawn to it.
+    pub fn take_screenshot(&mut self) -> Option<Arc<ImageView<AttachmentImage>>> {
+        self.screenshot.take()
+    }
+
+    /// Lowers the screenshot scale until the image fits in the device's size limit
+    fn clamp_screenshot_scale(&self, scale: u32, size: [u32; 2]) -> u32 {
+        let max_size = self.queue.device().physical_device().properties().max_image_dimension2_d;
+        let max_scale = (max_size / size[0].max(size[1]).max(1)).max(1);
+        if scale > max_scale {
+            println!("The screenshot is rendered at {}x, the GPU doesn't support larger images", max_scale);
+        }
+        scale.min(max_scale)
+    }
+
+    /// Creates an image to re-render the scene into for a screenshot
+    fn create_screenshot_image(&self, size: [u32; 2], scene_image: &AttachmentImage) -> Option<Arc<ImageView<AttachmentImage>>> {
+        let usage = ImageUsage {
+            sampled: true,
+            input_attachment: true,
+            color_attachment: true,
+            transfer_source: true,
+            transfer_destination: true,
+            ..ImageUsage::none()
+        };
+        match AttachmentImage::with_usage(self.queue.device().clone(), size, scene_image.format(), usage) {
+            Ok(image) => Some(ImageView::new_default(image).expect("Failed to create screenshot image view")),
+            Err(e) => {
+                println!("Failed to create a {}x{} screenshot image: {:?}", size[0], size[1], e);
+                None
+            }
         }
     }
 
@@ -29,19 +85,55 @@ impl GuiRenderScene {
         key_view: &KeyboardView,
         midi_file: &mut MIDIFileUnion,
         view_range: &mut f64,
-    ) -> RenderResultData {
+        key_shift: i32,
+        style: &NoteStyle,
+        overlap: &NoteOverlap,
+        key_color_mode: KeyColorMode,
+        antialiasing: &AntiAliasing,
+        tuned_pitch: bool,
+        effects: &EffectsSettings,
+        buffer_settings: &NoteBufferSettings,
+        screenshot_scale: Option<u32>,
+    ) -> (RenderResultData, Response) {
         let size = ui.available_size();
         let size = [size.x as u32, size.y as u32];
 
-        let scene_image = self.swap_chain.get_next_image(state, size);
+        let scene_image = match self.swap_chain.get_next_image(state, size) {
+            Some(scene_image) => scene_image,
+            None => {
+                let response = ui.allocate_response(ui.available_size(), Sense::click_and_drag());
+                return (RenderResultData::empty(), response);
+            }
+        };
         let frame = scene_image.image.clone();
+        let id = scene_image.id;
 
-        let result = match midi_file {
-            MIDIFileUnion::InRam(file) => self.draw_system.draw(key_view, frame, file, view_range),
+        let mut draw = |draw_system: &mut NoteRenderer, image: Arc<dyn ImageViewAbstract>, target| match midi_file {
+            MIDIFileUnion::InRam(file) => draw_system.draw_in_ram(key_view, image, target, file, view_range, key_shift, style, overlap, key_color_mode, antialiasing, tuned_pitch, effects, buffer_settings),
+            MIDIFileUnion::Live(file) => draw_system.draw(key_view, image, target, file, view_range, key_shift, style, overlap, key_color_mode, antialiasing, tuned_pitch, effects, buffer_settings),
         };
 
-        ui.image(scene_image.id, [size[0] as f32, size[1] as f32]);
+        let result = draw(&mut self.draw_system, frame.clone(), DrawTarget::Frame);
+
+        let response = ui.add(egui::Image::new(id, [size[0] as f32, size[1] as f32]).sense(Sense::click_and_drag()));
+
+        // Larger screenshots draw the same frame again into their own images,
+        // without touching the ones of the frame or stepping the effects. The
+        // draws wait for the GPU so the image can be read right after.
+        self.screenshot = match screenshot_scale.map(|scale| self.clamp_screenshot_scale(scale, size)) {
+            Some(scale) if scale > 1 => {
+                let screenshot_size = [size[0] * scale, size[1] * scale];
+                let image = self.create_screenshot_image(screenshot_size, frame.image()).map(|image| {
+                    draw(&mut self.draw_system, image.clone(), DrawTarget::Screenshot);
+                    image
+                });
+                self.draw_system.free_screenshot_targets();
+                image
+            }
+            Some(_) => Some(frame),
+            None => None,
+        };
 
-        result
+        (result, response)
     }
 }
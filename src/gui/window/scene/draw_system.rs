@@ -1,19 +1,67 @@
+pub mod antialiasing;
+pub mod buffer_budget;
+pub mod effects;
+mod effects_render_pass;
+pub mod key_press;
+pub mod note_style;
 mod notes_render_pass;
+pub mod overlap;
 
 use std::{cell::UnsafeCell, sync::Arc};
 
 use rayon::iter::{IntoParallelRefMutIterator, ParallelIterator};
-use vulkano::{buffer::TypedBufferAccess, image::ImageViewAbstract};
+use vulkano::{
+    buffer::TypedBufferAccess,
+    image::{ImageAccess, ImageViewAbstract},
+};
 
 use crate::{
     gui::{window::keyboard_layout::KeyboardView, GuiRenderer},
-    midi::{DisplacedMIDINote, MIDIColor, MIDIFile, MIDINoteColumnView, MIDINoteViews},
+    midi::{
+        DisplacedMIDINote, InRamMIDIFile, MIDIColor, MIDIFile, MIDINoteColumnView, MIDINoteViews,
+    },
+};
+
+use self::{
+    antialiasing::AntiAliasing,
+    buffer_budget::NoteBufferSettings,
+    effects::{EffectsSettings, EffectsSystem},
+    effects_render_pass::EffectsRenderPass,
+    key_press::{KeyColorMode, KeyPress, KeyPressBuilder},
+    note_style::NoteStyle,
+    overlap::NoteOverlap,
+    notes_render_pass::{
+        NotePassStatus, NoteRenderPass, NoteVertex, ResidentColumn, ResidentDraw, SubmitFuture,
+    },
 };
 
-use self::notes_render_pass::{NotePassStatus, NoteRenderPass, NoteVertex};
+pub use self::notes_render_pass::{submit, wait_for, DrawTarget};
+
+/// At most this many notes are uploaded to the GPU per frame, except for
+/// columns that are larger on their own
+const UPLOAD_NOTES_PER_FRAME: usize = 1 << 21;
+
+/// The columns of an in-ram file uploaded to the GPU. The columns are uploaded
+/// in order over several frames, the file is streamed until all of them are
+/// there, or for good if the upload failed.
+struct ResidentNotes {
+    file_id: u64,
+    columns: Vec<Option<ResidentColumn>>,
+    failed: bool,
+}
+
+impl ResidentNotes {
+    fn is_complete(&self) -> bool {
+        !self.failed && self.columns.len() == 256
+    }
+}
 
 pub struct NoteRenderer {
     render_pass: NoteRenderPass,
+    effects_pass: EffectsRenderPass,
+    effects: EffectsSystem,
+    resident: Option<ResidentNotes>,
+    last_view_start: f64,
     thrad_pool: rayon::ThreadPool,
 }
 
@@ -34,32 +82,66 @@ unsafe impl<T> Send for UnsafeSyncCell<T> {}
 
 pub struct RenderResultData {
     pub notes_rendered: u64,
-    pub key_colors: Vec<Option<MIDIColor>>,
+    /// The notes holding down each key
+    pub key_presses: Vec<KeyPress>,
+    /// The amount of notes each streaming buffer holds
+    pub note_buffer_size: u64,
+}
+
+impl RenderResultData {
+    /// The result when nothing was drawn
+    pub fn empty() -> Self {
+        RenderResultData {
+            notes_rendered: 0,
+            key_presses: vec![KeyPress::default(); 256],
+            note_buffer_size: 0,
+        }
+    }
 }
 
 impl NoteRenderer {
     pub fn new(renderer: &GuiRenderer) -> NoteRenderer {
         NoteRenderer {
             render_pass: NoteRenderPass::new(renderer),
+            effects_pass: EffectsRenderPass::new(renderer),
+            effects: EffectsSystem::new(),
+            resident: None,
+            last_view_start: 0.0,
             thrad_pool: rayon::ThreadPoolBuilder::new().build().unwrap(),
         }
     }
 
+    /// Draws the notes of the view range and the effects. Screenshots are
+    /// drawn after the frame and show the same effects, without stepping them.
     pub fn draw(
         &mut self,
         key_view: &KeyboardView,
         final_image: Arc<dyn ImageViewAbstract + 'static>,
+        target: DrawTarget,
         mut midi_file: impl MIDIFile,
         mut view_range: &mut f64,
+        key_shift: i32,
+        style: &NoteStyle,
+        overlap: &NoteOverlap,
+        key_color_mode: KeyColorMode,
+        antialiasing: &AntiAliasing,
+        tuned_pitch: bool,
+        effects: &EffectsSettings,
+        buffer_settings: &NoteBufferSettings,
     ) -> RenderResultData {
+        let note_buffer_size = self.render_pass.update_buffer_size(buffer_settings);
+
         let note_views = midi_file.get_current_column_views(&mut view_range);
 
+        let hit_window = self.hit_window(note_views.range().start, target, effects);
+
         struct ColumnViewInfo<Iter: ExactSizeIterator<Item = DisplacedMIDINote> + Send> {
             offset: usize,
             iter: Iter,
             key: u8,
             remaining: usize,
-            color: Option<MIDIColor>,
+            press: KeyPressBuilder,
+            hit_color: Option<MIDIColor>,
         }
 
         let mut total_notes = 0;
@@ -68,35 +150,27 @@ impl NoteRenderer {
 
         let mut columns_view_info = Vec::new();
 
-        // Add black keys first
-        for (i, column) in columns.iter().enumerate() {
-            if key_view.key(i).black {
-                let iter = column.iterate_displaced_notes();
-                let length = iter.len();
-                columns_view_info.push(ColumnViewInfo {
-                    offset: total_notes,
-                    iter,
-                    key: i as u8,
-                    remaining: length,
-                    color: None,
-                });
-                total_notes += length;
-            }
-        }
-
-        // Then white keys after
-        for (i, column) in columns.iter().enumerate() {
-            if !key_view.key(i).black {
-                let iter = column.iterate_displaced_notes();
-                let length = iter.len();
-                columns_view_info.push(ColumnViewInfo {
-                    offset: total_notes,
-                    iter,
-                    key: i as u8,
-                    remaining: length,
-                    color: None,
-                });
-                total_notes += length;
+        let shifted_key = |i: usize| shifted_key(i, key_shift);
+
+        for black in overlap.key_draw_order() {
+            for (i, column) in columns.iter().enumerate() {
+                let key = match shifted_key(i) {
+                    Some(key) => key,
+                    None => continue,
+                };
+                if key_view.key(key).black == black {
+                    let iter = column.iterate_displaced_notes();
+                    let length = iter.len();
+                    columns_view_info.push(ColumnViewInfo {
+                        offset: total_notes,
+                        iter,
+                        key: key as u8,
+                        remaining: length,
+                        press: KeyPressBuilder::default(),
+                        hit_color: None,
+                    });
+                    total_notes += length;
+                }
             }
         }
 
@@ -106,11 +180,12 @@ impl NoteRenderer {
 
         let mut view_range = note_views.range().length() as f32;
 
-        self.render_pass
-            .draw(final_image, key_view, view_range, |buffer| {
+        let notes = self.render_pass
+            .draw(final_image.clone(), target, key_view, view_range, style, overlap, antialiasing, tuned_pitch, |buffer, pitch_buffer| {
                 let buffer_length = buffer.len() as usize;
 
                 let buffer_writer = UnsafeSyncCell::new(buffer.write().unwrap());
+                let pitch_writer = pitch_buffer.map(|buffer| UnsafeSyncCell::new(buffer.write().unwrap()));
 
                 let written_notes = self.thrad_pool.install(|| {
                     let written_notes_per_key = columns_view_info.par_iter_mut().map(|column| {
@@ -144,11 +219,18 @@ impl NoteRenderer {
                                         note.len,
                                         column.key,
                                         note.color.as_u32(),
+                                        note.track_chan,
                                     );
+                                    if let Some(pitch_writer) = &pitch_writer {
+                                        pitch_writer.get_mut()[i + offset] = note.pitch_offset;
+                                    }
 
                                     if note.start <= 0.0 {
-                                        if column.color.is_none() && note.start + note.len > 0.0 {
-                                            column.color = Some(note.color);
+                                        if note.start + note.len > 0.0 {
+                                            column.press.add(&note);
+                                        }
+                                        if column.hit_color.is_none() && note.start > -hit_window {
+                                            column.hit_color = Some(note.color);
                                         }
                                     }
                                 } else {
@@ -178,15 +260,244 @@ impl NoteRenderer {
                 }
             });
 
-        // Sort for output metrics
-        columns_view_info.sort_by_key(|k| k.key);
+        let hits = columns_view_info
+            .iter()
+            .filter_map(|column| Some((column.key, column.hit_color?)));
+        self.draw_effects(key_view, final_image, target, effects, hits, notes);
+
+        // Collect per key for output metrics, keys without a column stay empty
+        let mut key_presses = vec![KeyPress::default(); 256];
+        for column in columns_view_info {
+            key_presses[column.key as usize] = column.press.build(key_color_mode, overlap.mode);
+        }
 
         RenderResultData {
             notes_rendered: notes_pushed as u64,
-            key_colors: columns_view_info
-                .iter()
-                .map(|column| column.color)
-                .collect(),
+            key_presses,
+            note_buffer_size,
         }
     }
+
+    /// Draws an in-ram file from note data uploaded to the GPU once per file.
+    /// Per frame, only the visible index range of each column is computed, so
+    /// the CPU cost doesn't grow with the amount of notes on screen. Falls back
+    /// to [`NoteRenderer::draw`] while the notes are being uploaded, and for
+    /// good if they don't fit in GPU memory.
+    pub fn draw_in_ram(
+        &mut self,
+        key_view: &KeyboardView,
+        final_image: Arc<dyn ImageViewAbstract + 'static>,
+        target: DrawTarget,
+        midi_file: &mut InRamMIDIFile,
+        view_range: &mut f64,
+        key_shift: i32,
+        style: &NoteStyle,
+        overlap: &NoteOverlap,
+        key_color_mode: KeyColorMode,
+        antialiasing: &AntiAliasing,
+        tuned_pitch: bool,
+        effects: &EffectsSettings,
+        buffer_settings: &NoteBufferSettings,
+    ) -> RenderResultData {
+        if self.resident.as_ref().map(|resident| resident.file_id) != Some(midi_file.id()) {
+            // Drop the old file's buffers before uploading the new ones
+            self.resident = None;
+            self.resident = Some(ResidentNotes {
+                file_id: midi_file.id(),
+                columns: Vec::new(),
+                failed: false,
+            });
+        }
+
+        // The upload only moves on with the frames, not with their screenshots
+        let resident = self.resident.as_mut().unwrap();
+        if target == DrawTarget::Frame && !resident.failed && !resident.is_complete() {
+            let data = midi_file.current_view_data(*view_range);
+
+            let mut notes = 0;
+            let columns = (resident.columns.len()..256)
+                .take_while(|&key| {
+                    let count = data.column_note_count(key);
+                    let fits = notes == 0 || notes + count <= UPLOAD_NOTES_PER_FRAME;
+                    notes += count;
+                    fits
+                })
+                .map(|key| {
+                    let notes = data.iter_column_notes(key).map(
+                        move |(start, len, color, track_chan, pitch_offset)| {
+                            let note = NoteVertex::new_absolute(
+                                start,
+                                len,
+                                key as u8,
+                                color.as_u32(),
+                                track_chan,
+                            );
+                            (note, pitch_offset)
+                        },
+                    );
+                    (data.column_note_count(key), notes)
+                });
+
+            match self.render_pass.upload_columns(columns) {
+                Some(mut uploaded) => resident.columns.append(&mut uploaded),
+                None => {
+                    resident.failed = true;
+                    resident.columns.clear();
+                }
+            }
+        }
+
+        if !resident.is_complete() {
+            return self.draw(
+                key_view,
+                final_image,
+                target,
+                midi_file,
+                view_range,
+                key_shift,
+                style,
+                overlap,
+                key_color_mode,
+                antialiasing,
+                tuned_pitch,
+                effects,
+                buffer_settings,
+            );
+        }
+
+        let data = midi_file.current_view_data(*view_range);
+        let range = data.view_range();
+
+        let hit_window = self.hit_window(range.start, target, effects);
+
+        let resident_columns = &self.resident.as_ref().unwrap().columns;
+
+        let mut draws = Vec::new();
+        let mut notes_rendered = 0;
+        for black in overlap.key_draw_order() {
+            for (i, column) in resident_columns.iter().enumerate() {
+                let column = match (column, shifted_key(i, key_shift)) {
+                    (Some(column), Some(key)) if key_view.key(key).black == black => column,
+                    _ => continue,
+                };
+
+                let indexes = data.column_view_indexes(i);
+                notes_rendered += indexes.len();
+                draws.push(ResidentDraw {
+                    column,
+                    range: indexes.start as u32..indexes.end as u32,
+                });
+            }
+        }
+
+        let notes = self.render_pass.draw_resident(
+            final_image.clone(),
+            target,
+            key_view,
+            range.length() as f32,
+            range.start,
+            key_shift,
+            style,
+            overlap,
+            antialiasing,
+            tuned_pitch,
+            &draws,
+        );
+
+        // Only the notes crossing the hit line are needed for the key colors
+        let mut key_presses = vec![KeyPress::default(); 256];
+        let mut hits = Vec::new();
+        for i in 0..256 {
+            let key = match shifted_key(i, key_shift) {
+                Some(key) => key,
+                None => continue,
+            };
+
+            let mut hit_color = None;
+            let mut press = KeyPressBuilder::default();
+            for note in data.iter_notes_at_view_start(i) {
+                if hit_color.is_none() && note.start > -hit_window {
+                    hit_color = Some(note.color);
+                }
+                if note.start + note.len > 0.0 {
+                    press.add(&note);
+                }
+            }
+            key_presses[key] = press.build(key_color_mode, overlap.mode);
+
+            if let Some(color) = hit_color {
+                hits.push((key as u8, color));
+            }
+        }
+
+        self.draw_effects(key_view, final_image, target, effects, hits.into_iter(), notes);
+
+        RenderResultData {
+            notes_rendered: notes_rendered as u64,
+            key_presses,
+            // The streaming buffers are only allocated when something streams
+            note_buffer_size: self.render_pass.buffer_size(),
+        }
+    }
+
+    /// Frees the images only needed while a screenshot is drawn
+    pub fn free_screenshot_targets(&mut self) {
+        self.render_pass.free_screenshot_targets();
+    }
+
+    /// Notes that crossed the hit line since the last frame count as hits.
+    /// Seeks, pauses and screenshots don't produce any.
+    fn hit_window(
+        &mut self,
+        view_start: f64,
+        target: DrawTarget,
+        effects: &EffectsSettings,
+    ) -> f32 {
+        if target == DrawTarget::Screenshot {
+            return 0.0;
+        }
+
+        let hit_window = (view_start - self.last_view_start) as f32;
+        self.last_view_start = view_start;
+
+        if effects.enabled() && hit_window < 1.0 {
+            hit_window
+        } else {
+            0.0
+        }
+    }
+
+    /// Only the frames step the effects, screenshots draw them as they are
+    fn draw_effects(
+        &mut self,
+        key_view: &KeyboardView,
+        final_image: Arc<dyn ImageViewAbstract + 'static>,
+        target: DrawTarget,
+        effects: &EffectsSettings,
+        hits: impl Iterator<Item = (u8, MIDIColor)>,
+        notes: Option<SubmitFuture>,
+    ) {
+        if target == DrawTarget::Frame {
+            if effects.enabled() {
+                for (key, color) in hits {
+                    self.effects.hit(effects, key_view, key, color);
+                }
+            }
+            self.effects.update();
+        }
+
+        let image_size = final_image.image().dimensions().width_height();
+        let vertices = self.effects.build_vertices(key_view, image_size);
+        self.effects_pass.draw(final_image, vertices, notes);
+    }
+}
+
+/// Columns shifted off the keyboard aren't drawn
+fn shifted_key(column: usize, key_shift: i32) -> Option<usize> {
+    let key = column as i32 + key_shift;
+    if key >= 0 && key < 256 {
+        Some(key as usize)
+    } else {
+        None
+    }
 }
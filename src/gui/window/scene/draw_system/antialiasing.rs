@@ -0,0 +1,43 @@
+use vulkano::device::physical::PhysicalDevice;
+
+/// The supersampling factors that can be picked, the notes are downsampled by
+/// halving the size until it matches the scene
+pub const SUPERSAMPLING_FACTORS: [u32; 3] = [1, 2, 4];
+
+/// How the note edges are smoothed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AntiAliasing {
+    /// The MSAA sample count, 1 disables it
+    pub msaa_samples: u32,
+    /// The notes are rendered this many times larger and then downsampled.
+    /// This is expensive, it is meant for recordings where the frame rate
+    /// doesn't matter.
+    pub supersampling: u32,
+}
+
+impl Default for AntiAliasing {
+    fn default() -> Self {
+        AntiAliasing {
+            msaa_samples: 1,
+            supersampling: 1,
+        }
+    }
+}
+
+/// The MSAA sample counts the device supports for both color and depth attachments
+pub fn supported_msaa_samples(physical: PhysicalDevice) -> Vec<u32> {
+    let properties = physical.properties();
+    let color = properties.framebuffer_color_sample_counts;
+    let depth = properties.framebuffer_depth_sample_counts;
+
+    [
+        (1, color.sample1 && depth.sample1),
+        (2, color.sample2 && depth.sample2),
+        (4, color.sample4 && depth.sample4),
+        (8, color.sample8 && depth.sample8),
+    ]
+    .into_iter()
+    .filter(|(_, supported)| *supported)
+    .map(|(samples, _)| samples)
+    .collect()
+}
@@ -0,0 +1,54 @@
+use vulkano::device::physical::PhysicalDevice;
+
+/// The smallest note buffer worth allocating, below this the amount of
+/// passes per frame gets too high
+pub const MIN_NOTE_BUFFER_SIZE: u64 = 100_000;
+/// The largest note buffer, more than this doesn't speed anything up
+pub const MAX_NOTE_BUFFER_SIZE: u64 = 100_000_000;
+
+/// How large the buffers used to stream notes to the GPU are
+#[derive(Debug, Clone, PartialEq)]
+pub struct NoteBufferSettings {
+    /// The fraction of the largest host visible memory heap the buffers may
+    /// use. The buffers are written by the CPU, so they live in that heap
+    /// rather than in VRAM.
+    pub memory_fraction: f32,
+    /// A fixed amount of notes per buffer, overriding the fraction
+    pub manual_size: Option<u64>,
+}
+
+impl Default for NoteBufferSettings {
+    fn default() -> Self {
+        NoteBufferSettings {
+            memory_fraction: 0.25,
+            manual_size: None,
+        }
+    }
+}
+
+impl NoteBufferSettings {
+    /// The amount of notes each of the `buffer_count` buffers should hold
+    pub fn notes_per_buffer(
+        &self,
+        physical: PhysicalDevice,
+        note_size: u64,
+        buffer_count: u64,
+    ) -> u64 {
+        let notes = match self.manual_size {
+            Some(size) => size,
+            None => {
+                let memory = physical
+                    .memory_types()
+                    .filter(|memory_type| memory_type.is_host_visible())
+                    .map(|memory_type| memory_type.heap().size())
+                    .max()
+                    .unwrap_or(0);
+
+                let budget = (memory as f64 * self.memory_fraction as f64) as u64;
+                budget / note_size / buffer_count
+            }
+        };
+
+        notes.clamp(MIN_NOTE_BUFFER_SIZE, MAX_NOTE_BUFFER_SIZE)
+    }
+}
@@ -0,0 +1,254 @@
+use std::time::Instant;
+
+use rand::Rng;
+
+use crate::{
+    gui::window::keyboard_layout::{KeyboardView, RollOrientation},
+    midi::MIDIColor,
+    renderer::color_space::srgb_to_linear,
+};
+
+use super::effects_render_pass::{EffectVertex, SHAPE_BEAM, SHAPE_GLOW};
+
+const FLASH_LIFE: f32 = 0.15;
+const BEAM_LIFE: f32 = 0.3;
+const PARTICLE_LIFE: f32 = 0.8;
+
+/// The height of a beam, as a fraction of the time axis
+const BEAM_LENGTH: f32 = 0.25;
+/// Downward acceleration of particles, in time axis fractions per second squared
+const GRAVITY: f32 = 1.5;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct EffectsSettings {
+    pub particles: bool,
+    pub flashes: bool,
+    pub beams: bool,
+    pub particles_per_hit: usize,
+    /// The maximum amount of effects alive at the same time, so that dense
+    /// files don't tank the frame rate
+    pub max_effects: usize,
+}
+
+impl Default for EffectsSettings {
+    fn default() -> Self {
+        EffectsSettings {
+            particles: false,
+            flashes: false,
+            beams: false,
+            particles_per_hit: 4,
+            max_effects: 5000,
+        }
+    }
+}
+
+impl EffectsSettings {
+    pub fn enabled(&self) -> bool {
+        self.particles || self.flashes || self.beams
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum EffectKind {
+    Flash,
+    Beam,
+    Particle,
+}
+
+struct Effect {
+    kind: EffectKind,
+    key: u8,
+    /// Position along the key axis (0 to 1), and the time axis (0 is the hit line)
+    position: [f32; 2],
+    velocity: [f32; 2],
+    /// Size in pixels
+    size: f32,
+    color: [f32; 3],
+    life: f32,
+    max_life: f32,
+}
+
+/// Simulates the effects spawned when notes hit the keyboard
+pub struct EffectsSystem {
+    effects: Vec<Effect>,
+    last_update: Instant,
+}
+
+/// The linear color, the effects are blended additively in linear space
+fn color_to_floats(color: MIDIColor) -> [f32; 3] {
+    [
+        srgb_to_linear(color.red()),
+        srgb_to_linear(color.green()),
+        srgb_to_linear(color.blue()),
+    ]
+}
+
+/// Maps a position on the key axis and the time axis (both -1 to 1, with -1
+/// being the keyboard side) to the screen, matching the note shaders
+fn to_screen(orientation: RollOrientation, key_pos: f32, time_pos: f32) -> [f32; 2] {
+    match orientation {
+        RollOrientation::Down => [key_pos, -time_pos],
+        RollOrientation::Up => [key_pos, time_pos],
+        RollOrientation::Horizontal => [time_pos, -key_pos],
+    }
+}
+
+impl EffectsSystem {
+    pub fn new() -> Self {
+        EffectsSystem {
+            effects: Vec::new(),
+            last_update: Instant::now(),
+        }
+    }
+
+    fn push(&mut self, settings: &EffectsSettings, effect: Effect) {
+        if self.effects.len() < settings.max_effects {
+            self.effects.push(effect);
+        }
+    }
+
+    /// Spawns the effects for a note hitting the given key
+    pub fn hit(&mut self, settings: &EffectsSettings, key_view: &KeyboardView, key: u8, color: MIDIColor) {
+        let note = key_view.note(key as usize);
+        let center = (note.left + note.right) / 2.0;
+        let color = color_to_floats(color);
+
+        if settings.flashes {
+            self.push(
+                settings,
+                Effect {
+                    kind: EffectKind::Flash,
+                    key,
+                    position: [center, 0.0],
+                    velocity: [0.0, 0.0],
+                    size: 0.0,
+                    color,
+                    life: FLASH_LIFE,
+                    max_life: FLASH_LIFE,
+                },
+            );
+        }
+
+        if settings.beams {
+            self.push(
+                settings,
+                Effect {
+                    kind: EffectKind::Beam,
+                    key,
+                    position: [center, 0.0],
+                    velocity: [0.0, 0.0],
+                    size: 0.0,
+                    color,
+                    life: BEAM_LIFE,
+                    max_life: BEAM_LIFE,
+                },
+            );
+        }
+
+        if settings.particles {
+            let mut rng = rand::thread_rng();
+            let width = note.right - note.left;
+            for _ in 0..settings.particles_per_hit {
+                let life = PARTICLE_LIFE * rng.gen_range(0.5..1.0);
+                self.push(
+                    settings,
+                    Effect {
+                        kind: EffectKind::Particle,
+                        key,
+                        position: [center + width * rng.gen_range(-0.5..0.5), 0.0],
+                        velocity: [rng.gen_range(-0.05..0.05), rng.gen_range(0.3..0.8)],
+                        size: rng.gen_range(2.0..5.0),
+                        color,
+                        life,
+                        max_life: life,
+                    },
+                );
+            }
+        }
+    }
+
+    /// Advances the simulation by the real time passed since the last update
+    pub fn update(&mut self) {
+        let delta = self.last_update.elapsed().as_secs_f32();
+        self.last_update = Instant::now();
+
+        for effect in self.effects.iter_mut() {
+            effect.life -= delta;
+            if effect.kind == EffectKind::Particle {
+                effect.velocity[1] -= GRAVITY * delta;
+                effect.position[0] += effect.velocity[0] * delta;
+                effect.position[1] += effect.velocity[1] * delta;
+            }
+        }
+
+        self.effects
+            .retain(|effect| effect.life > 0.0 && effect.position[1] >= 0.0);
+    }
+
+    /// Builds the quads for all the alive effects, as two triangles each
+    pub fn build_vertices(&self, key_view: &KeyboardView, image_size: [u32; 2]) -> Vec<EffectVertex> {
+        let orientation = key_view.orientation;
+        let (key_pixels, time_pixels) = if orientation.is_horizontal() {
+            (image_size[1] as f32, image_size[0] as f32)
+        } else {
+            (image_size[0] as f32, image_size[1] as f32)
+        };
+
+        let mut vertices = Vec::with_capacity(self.effects.len() * 6);
+
+        for effect in self.effects.iter() {
+            let fade = effect.life / effect.max_life;
+            let note = key_view.note(effect.key as usize);
+            let key_width = note.right - note.left;
+
+            // Extents along the key axis and time axis, in 0 to 1 fractions
+            let (key_half, time_start, time_end, shape) = match effect.kind {
+                EffectKind::Flash => {
+                    let half = key_width * 1.5;
+                    let time_half = half * key_pixels / time_pixels;
+                    (half, -time_half, time_half, SHAPE_GLOW)
+                }
+                EffectKind::Beam => (key_width / 2.0, 0.0, BEAM_LENGTH, SHAPE_BEAM),
+                EffectKind::Particle => {
+                    let half = effect.size / key_pixels;
+                    let time_half = effect.size / time_pixels;
+                    (
+                        half,
+                        effect.position[1] - time_half,
+                        effect.position[1] + time_half,
+                        SHAPE_GLOW,
+                    )
+                }
+            };
+
+            let left = (effect.position[0] - key_half) * 2.0 - 1.0;
+            let right = (effect.position[0] + key_half) * 2.0 - 1.0;
+            let start = time_start * 2.0 - 1.0;
+            let end = time_end * 2.0 - 1.0;
+
+            let color = [effect.color[0], effect.color[1], effect.color[2], fade];
+            let corner = |key_pos: f32, time_pos: f32, u: f32, v: f32| EffectVertex {
+                position: to_screen(orientation, key_pos, time_pos),
+                uv: [u, v],
+                color,
+                shape,
+            };
+
+            let top_left = corner(left, start, 0.0, 0.0);
+            let top_right = corner(right, start, 1.0, 0.0);
+            let bottom_left = corner(left, end, 0.0, 1.0);
+            let bottom_right = corner(right, end, 1.0, 1.0);
+
+            vertices.extend_from_slice(&[
+                top_left,
+                top_right,
+                bottom_left,
+                top_right,
+                bottom_right,
+                bottom_left,
+            ]);
+        }
+
+        vertices
+    }
+}
@@ -0,0 +1,264 @@
+use std::sync::Arc;
+
+use bytemuck::{Pod, Zeroable};
+use vulkano::{
+    buffer::{BufferUsage, CpuAccessibleBuffer},
+    command_buffer::{AutoCommandBufferBuilder, CommandBufferUsage, SubpassContents},
+    device::Queue,
+    format::ClearValue,
+    image::{ImageAccess, ImageViewAbstract},
+    pipeline::{
+        graphics::{
+            color_blend::{AttachmentBlend, ColorBlendState},
+            input_assembly::InputAssemblyState,
+            vertex_input::BuffersDefinition,
+            viewport::{Viewport, ViewportState},
+        },
+        GraphicsPipeline, Pipeline,
+    },
+    render_pass::{Framebuffer, FramebufferCreateInfo, RenderPass, Subpass},
+};
+
+use crate::{gui::GuiRenderer, renderer::color_space::needs_srgb_encoding};
+
+use super::notes_render_pass::{submit, wait_for, SubmitFuture};
+
+pub const SHAPE_GLOW: f32 = 0.0;
+pub const SHAPE_BEAM: f32 = 1.0;
+
+#[repr(C)]
+#[derive(Default, Debug, Copy, Clone, Zeroable, Pod)]
+pub struct EffectVertex {
+    pub position: [f32; 2],
+    pub uv: [f32; 2],
+    pub color: [f32; 4],
+    pub shape: f32,
+}
+vulkano::impl_vertex!(EffectVertex, position, uv, color, shape);
+
+/// Draws the hit effects additively over the already rendered notes
+pub struct EffectsRenderPass {
+    gfx_queue: Arc<Queue>,
+    pipeline: Arc<GraphicsPipeline>,
+    render_pass: Arc<RenderPass>,
+    encode_srgb: bool,
+    /// Kept between frames and only reallocated when the effects outgrow it
+    vertex_buffer: Option<Arc<CpuAccessibleBuffer<[EffectVertex]>>>,
+}
+
+impl EffectsRenderPass {
+    pub fn new(renderer: &GuiRenderer) -> EffectsRenderPass {
+        let gfx_queue = renderer.queue.clone();
+
+        let render_pass = vulkano::single_pass_renderpass!(gfx_queue.device().clone(),
+            attachments: {
+                final_color: {
+                    load: Load,
+                    store: Store,
+                    format: renderer.format,
+                    samples: 1,
+                }
+            },
+            pass: {
+                color: [final_color],
+                depth_stencil: {}
+            }
+        )
+        .unwrap();
+
+        let vs = vs::load(gfx_queue.device().clone()).expect("failed to create shader module");
+        let fs = fs::load(gfx_queue.device().clone()).expect("failed to create shader module");
+
+        let pipeline = GraphicsPipeline::start()
+            .input_assembly_state(InputAssemblyState::new())
+            .vertex_input_state(BuffersDefinition::new().vertex::<EffectVertex>())
+            .vertex_shader(vs.entry_point("main").unwrap(), ())
+            .fragment_shader(fs.entry_point("main").unwrap(), ())
+            .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+            .color_blend_state(ColorBlendState::new(1).blend(AttachmentBlend::additive()))
+            .render_pass(Subpass::from(render_pass.clone(), 0).unwrap())
+            .build(gfx_queue.device().clone())
+            .unwrap();
+
+        EffectsRenderPass {
+            gfx_queue,
+            pipeline,
+            render_pass,
+            encode_srgb: needs_srgb_encoding(renderer.format),
+            vertex_buffer: None,
+        }
+    }
+
+    /// Returns a buffer holding the vertices, reusing the last one if they fit
+    fn write_vertices(
+        &mut self,
+        vertices: &[EffectVertex],
+    ) -> Option<Arc<CpuAccessibleBuffer<[EffectVertex]>>> {
+        let fits = matches!(
+            &self.vertex_buffer,
+            Some(buffer) if buffer.len() >= vertices.len() as u64
+        );
+        if !fits {
+            self.vertex_buffer = None;
+            let size = vertices.len().next_power_of_two() as u64;
+            let buffer = unsafe {
+                CpuAccessibleBuffer::uninitialized_array(
+                    self.gfx_queue.device().clone(),
+                    size,
+                    BufferUsage::vertex_buffer(),
+                    false,
+                )
+            };
+            match buffer {
+                Ok(buffer) => self.vertex_buffer = Some(buffer),
+                Err(e) => {
+                    println!("Failed to create effects buffer: {:?}", e);
+                    return None;
+                }
+            }
+        }
+
+        let buffer = self.vertex_buffer.clone().unwrap();
+        match buffer.write() {
+            Ok(mut write) => write[..vertices.len()].copy_from_slice(vertices),
+            Err(e) => {
+                println!("Failed to write effects buffer: {:?}", e);
+                return None;
+            }
+        }
+        Some(buffer)
+    }
+
+    /// Draws the effects after the note passes in `notes`, and waits for both.
+    /// The effects buffer is free again for the next frame once this returns.
+    pub fn draw(
+        &mut self,
+        final_image: Arc<dyn ImageViewAbstract + 'static>,
+        vertices: Vec<EffectVertex>,
+        notes: Option<SubmitFuture>,
+    ) {
+        if vertices.is_empty() {
+            wait_for(notes);
+            return;
+        }
+
+        let img_dims = final_image.image().dimensions().width_height();
+
+        let vertex_count = vertices.len() as u32;
+        let vertex_buffer = match self.write_vertices(&vertices) {
+            Some(buffer) => buffer,
+            None => {
+                wait_for(notes);
+                return;
+            }
+        };
+
+        let framebuffer = Framebuffer::new(
+            self.render_pass.clone(),
+            FramebufferCreateInfo {
+                attachments: vec![final_image],
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        let mut command_buffer_builder = AutoCommandBufferBuilder::primary(
+            self.gfx_queue.device().clone(),
+            self.gfx_queue.family(),
+            CommandBufferUsage::OneTimeSubmit,
+        )
+        .unwrap();
+
+        command_buffer_builder
+            .begin_render_pass(framebuffer, SubpassContents::Inline, vec![ClearValue::None])
+            .unwrap()
+            .bind_pipeline_graphics(self.pipeline.clone())
+            .set_viewport(
+                0,
+                [Viewport {
+                    origin: [0.0, 0.0],
+                    dimensions: [img_dims[0] as f32, img_dims[1] as f32],
+                    depth_range: 0.0..1.0,
+                }],
+            )
+            .push_constants(
+                self.pipeline.layout().clone(),
+                0,
+                fs::ty::PushConstants {
+                    encode_srgb: self.encode_srgb as u32,
+                },
+            )
+            .bind_vertex_buffers(0, vertex_buffer)
+            .draw(vertex_count, 1, 0, 0)
+            .unwrap()
+            .end_render_pass()
+            .unwrap();
+
+        let command_buffer = command_buffer_builder.build().unwrap();
+
+        // If the notes failed to draw, the effects are skipped as well
+        if notes.is_some() {
+            wait_for(submit(&self.gfx_queue, notes, command_buffer));
+        }
+    }
+}
+
+mod vs {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        src: "
+#version 450
+layout(location = 0) in vec2 position;
+layout(location = 1) in vec2 uv;
+layout(location = 2) in vec4 color;
+layout(location = 3) in float shape;
+
+layout(location = 0) out vec2 v_uv;
+layout(location = 1) out vec4 v_color;
+layout(location = 2) out float v_shape;
+
+void main() {
+    gl_Position = vec4(position, 0, 1);
+    v_uv = uv;
+    v_color = color;
+    v_shape = shape;
+}"
+    }
+}
+
+mod fs {
+    vulkano_shaders::shader! {
+        ty: "fragment",
+        include: ["shaders"],
+        src: "
+#version 450
+
+#include <srgb.glsl>
+
+layout(location = 0) in vec2 v_uv;
+layout(location = 1) in vec4 v_color;
+layout(location = 2) in float v_shape;
+
+layout(location = 0) out vec4 out_color;
+
+layout(push_constant) uniform PushConstants {
+    // See srgb.glsl
+    uint encode_srgb;
+} consts;
+
+void main() {
+    float strength;
+    if(v_shape > 0.5) {
+        // Beams fade out along their length and towards their sides
+        strength = (1 - v_uv.y) * (1 - abs(v_uv.x * 2 - 1));
+    } else {
+        // Glows are soft circles
+        strength = max(1 - length(v_uv * 2 - 1), 0);
+        strength *= strength;
+    }
+    vec3 color = v_color.rgb * v_color.a * strength;
+    color = encode_output(color, consts.encode_srgb);
+    out_color = vec4(color, 1);
+}"
+    }
+}
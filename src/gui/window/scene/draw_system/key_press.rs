@@ -0,0 +1,129 @@
+use crate::midi::{DisplacedMIDINote, MIDIColor};
+
+use super::overlap::OverlapMode;
+
+/// The most stripes a key is split into, further notes only change the velocity
+pub const MAX_KEY_STRIPES: usize = 4;
+
+/// How a key held by several notes at once gets its color
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyColorMode {
+    /// The note that is visible on top at the keyboard, following the overlap mode
+    TopMost,
+    Newest,
+    /// The average of all the notes' colors
+    Blend,
+    /// The key is split into a stripe for each note color
+    Stripes,
+}
+
+impl Default for KeyColorMode {
+    fn default() -> Self {
+        KeyColorMode::TopMost
+    }
+}
+
+impl KeyColorMode {
+    pub const ALL: [KeyColorMode; 4] = [
+        KeyColorMode::TopMost,
+        KeyColorMode::Newest,
+        KeyColorMode::Blend,
+        KeyColorMode::Stripes,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            KeyColorMode::TopMost => "Top Most Note",
+            KeyColorMode::Newest => "Newest Note",
+            KeyColorMode::Blend => "Blend",
+            KeyColorMode::Stripes => "Stripes",
+        }
+    }
+}
+
+/// The notes holding down a key
+#[derive(Debug, Clone, Default)]
+pub struct KeyPress {
+    /// The colors the key is drawn with, several only for stripes. Empty if
+    /// the key isn't pressed.
+    pub colors: Vec<MIDIColor>,
+    /// The highest velocity of the notes
+    pub velocity: u8,
+}
+
+impl KeyPress {
+    pub fn is_pressed(&self) -> bool {
+        !self.colors.is_empty()
+    }
+}
+
+/// Collects the notes crossing the keyboard in a column, newest first
+#[derive(Default)]
+pub struct KeyPressBuilder {
+    newest: Option<MIDIColor>,
+    oldest: Option<MIDIColor>,
+    highest_track: Option<(u32, MIDIColor)>,
+    lowest_track: Option<(u32, MIDIColor)>,
+    sum: [u32; 3],
+    count: u32,
+    stripes: Vec<MIDIColor>,
+    velocity: u8,
+}
+
+impl KeyPressBuilder {
+    pub fn add(&mut self, note: &DisplacedMIDINote) {
+        let color = note.color;
+
+        self.newest.get_or_insert(color);
+        self.oldest = Some(color);
+        if self.highest_track.map_or(true, |(track, _)| note.track_chan > track) {
+            self.highest_track = Some((note.track_chan, color));
+        }
+        if self.lowest_track.map_or(true, |(track, _)| note.track_chan < track) {
+            self.lowest_track = Some((note.track_chan, color));
+        }
+
+        self.sum[0] += color.red() as u32;
+        self.sum[1] += color.green() as u32;
+        self.sum[2] += color.blue() as u32;
+        self.count += 1;
+
+        if self.stripes.len() < MAX_KEY_STRIPES
+            && !self.stripes.iter().any(|stripe| stripe.as_u32() == color.as_u32())
+        {
+            self.stripes.push(color);
+        }
+
+        self.velocity = self.velocity.max(note.velocity);
+    }
+
+    pub fn build(self, mode: KeyColorMode, overlap: OverlapMode) -> KeyPress {
+        let colors = match mode {
+            KeyColorMode::TopMost => {
+                let top = match overlap {
+                    OverlapMode::NewestOnTop | OverlapMode::Transparent => self.newest,
+                    OverlapMode::OldestOnTop => self.oldest,
+                    OverlapMode::HighestTrackOnTop => self.highest_track.map(|(_, color)| color),
+                    OverlapMode::LowestTrackOnTop => self.lowest_track.map(|(_, color)| color),
+                };
+                top.into_iter().collect()
+            }
+            KeyColorMode::Newest => self.newest.into_iter().collect(),
+            KeyColorMode::Blend if self.count > 0 => {
+                let average = |sum: u32| (sum / self.count) as u8;
+                vec![MIDIColor::new(
+                    average(self.sum[0]),
+                    average(self.sum[1]),
+                    average(self.sum[2]),
+                )]
+            }
+            KeyColorMode::Blend => Vec::new(),
+            KeyColorMode::Stripes => self.stripes,
+        };
+
+        KeyPress {
+            colors,
+            velocity: self.velocity,
+        }
+    }
+}
@@ -0,0 +1,110 @@
+use std::path::PathBuf;
+
+use bytemuck::{Pod, Zeroable};
+
+/// How the notes are shaded by the fragment shader
+#[derive(Debug, Clone, PartialEq)]
+pub struct NoteStyle {
+    /// Border width in pixels
+    pub border_width: f32,
+    /// The border color is the note color multiplied by this
+    pub border_darken: f32,
+    /// How much the note darkens towards its end
+    pub gradient: f32,
+    /// Corner radius in pixels
+    pub corner_radius: f32,
+    /// Strength of the horizontal shading across the note
+    pub shading: f32,
+    /// Strength of the glow in the center of the note
+    pub glow: f32,
+    /// A PNG image that gets multiplied with the note color
+    pub texture_path: Option<PathBuf>,
+}
+
+impl Default for NoteStyle {
+    fn default() -> Self {
+        NoteStyle {
+            border_width: 1.0,
+            border_darken: 0.4,
+            gradient: 0.0,
+            corner_radius: 0.0,
+            shading: 0.4,
+            glow: 0.0,
+            texture_path: None,
+        }
+    }
+}
+
+impl NoteStyle {
+    pub fn presets() -> Vec<(&'static str, NoteStyle)> {
+        vec![
+            ("Wasabi", NoteStyle::default()),
+            (
+                "Flat",
+                NoteStyle {
+                    border_width: 0.0,
+                    shading: 0.0,
+                    ..Default::default()
+                },
+            ),
+            (
+                "Piano From Above",
+                NoteStyle {
+                    border_width: 1.0,
+                    border_darken: 0.5,
+                    gradient: 0.3,
+                    shading: 0.0,
+                    ..Default::default()
+                },
+            ),
+            (
+                "Rounded",
+                NoteStyle {
+                    border_width: 1.5,
+                    border_darken: 0.3,
+                    corner_radius: 4.0,
+                    shading: 0.2,
+                    ..Default::default()
+                },
+            ),
+            (
+                "Glow",
+                NoteStyle {
+                    border_width: 0.0,
+                    shading: 0.0,
+                    glow: 1.0,
+                    ..Default::default()
+                },
+            ),
+        ]
+    }
+
+    pub fn as_uniform(&self, use_texture: bool, alpha: f32, encode_srgb: bool) -> NoteStyleUniform {
+        NoteStyleUniform {
+            border_width: self.border_width,
+            border_darken: self.border_darken,
+            gradient: self.gradient,
+            corner_radius: self.corner_radius,
+            shading: self.shading,
+            glow: self.glow,
+            use_texture: use_texture as u32,
+            alpha,
+            encode_srgb: encode_srgb as u32,
+        }
+    }
+}
+
+/// The note style as laid out in the fragment shader's uniform buffer
+#[repr(C)]
+#[derive(Default, Debug, Copy, Clone, Zeroable, Pod)]
+pub struct NoteStyleUniform {
+    border_width: f32,
+    border_darken: f32,
+    gradient: f32,
+    corner_radius: f32,
+    shading: f32,
+    glow: f32,
+    use_texture: u32,
+    alpha: f32,
+    encode_srgb: u32,
+}
@@ -1,113 +1,399 @@
-use std::sync::Arc;
+use std::{
+    ops::Range,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use bytemuck::{Pod, Zeroable};
 use vulkano::{
-    buffer::{BufferUsage, CpuAccessibleBuffer, TypedBufferAccess},
-    command_buffer::{AutoCommandBufferBuilder, CommandBufferUsage, SubpassContents},
+    buffer::{BufferAccess, BufferUsage, CpuAccessibleBuffer, DeviceLocalBuffer, TypedBufferAccess},
+    command_buffer::{
+        AutoCommandBufferBuilder, CommandBufferUsage, PrimaryAutoCommandBuffer, SubpassContents,
+    },
     descriptor_set::{PersistentDescriptorSet, WriteDescriptorSet},
     device::{Device, Queue},
     format::{ClearValue, Format},
-    image::{view::ImageView, AttachmentImage, ImageAccess, ImageViewAbstract},
+    image::{
+        view::ImageView, AttachmentImage, ImageAccess, ImageCreationError, ImageDimensions,
+        ImageUsage, ImageViewAbstract, ImmutableImage, MipmapsCount, SampleCount,
+    },
     pipeline::{
         graphics::{
-            depth_stencil::DepthStencilState,
+            color_blend::{AttachmentBlend, BlendFactor, BlendOp, ColorBlendState},
+            depth_stencil::{CompareOp, DepthStencilState},
             input_assembly::{InputAssemblyState, PrimitiveTopology},
+            multisample::MultisampleState,
             vertex_input::BuffersDefinition,
             viewport::{Viewport, ViewportState},
         },
-        GraphicsPipeline, Pipeline, PipelineBindPoint,
+        GraphicsPipeline, Pipeline, PipelineBindPoint, StateMode,
     },
     render_pass::{Framebuffer, FramebufferCreateInfo, RenderPass, Subpass},
+    sampler::{Filter, Sampler, SamplerCreateInfo},
     sync::{self, FenceSignalFuture, GpuFuture},
 };
 
-use crate::gui::{window::keyboard_layout::KeyboardView, GuiRenderer};
+use crate::{
+    gui::{window::keyboard_layout::KeyboardView, GuiRenderer},
+    renderer::color_space::needs_srgb_encoding,
+};
+
+use super::{
+    antialiasing::AntiAliasing,
+    buffer_budget::{NoteBufferSettings, MIN_NOTE_BUFFER_SIZE},
+    note_style::{NoteStyle, NoteStyleUniform},
+    overlap::{NoteOverlap, OverlapMode},
+};
+
+/// The notes are streamed through two buffers, so one can be filled while the
+/// other one is drawn
+const NOTE_BUFFER_COUNT: u64 = 2;
 
-const NOTE_BUFFER_SIZE: u64 = 25000000;
+/// How long a new buffer size has to stay the same before the buffers are
+/// reallocated, so dragging the setting doesn't reallocate them every frame
+const BUFFER_RESIZE_DELAY: Duration = Duration::from_millis(500);
 
+/// A note as the shaders read it. How far each note plays from its key's
+/// pitch, in semitones, is kept in a separate buffer of `f32`s at the same
+/// indexes, which only exists while the notes are drawn at their tuned pitch
+/// or for the uploaded columns that have such notes.
 #[repr(C)]
 #[derive(Default, Debug, Copy, Clone, Zeroable, Pod)]
 pub struct NoteVertex {
     pub start_length: [f32; 2],
     pub key_color: u32,
+    /// The low part of the start time, for notes uploaded with absolute times
+    pub start_low: f32,
+    pub track_chan: u32,
 }
-vulkano::impl_vertex!(NoteVertex, start_length, key_color);
+vulkano::impl_vertex!(NoteVertex, start_length, key_color, start_low, track_chan);
 
 impl NoteVertex {
-    pub fn new(start: f32, len: f32, key: u8, color: u32) -> Self {
+    pub fn new(start: f32, len: f32, key: u8, color: u32, track_chan: u32) -> Self {
         Self {
             start_length: [start, len],
             key_color: key as u32 | (color << 8),
+            start_low: 0.0,
+            track_chan,
+        }
+    }
+
+    /// A note with an absolute start time, which gets displaced by the view
+    /// start in the shaders. The time is split into two floats so that notes
+    /// late in long files keep their precision.
+    pub fn new_absolute(start: f64, len: f32, key: u8, color: u32, track_chan: u32) -> Self {
+        let (high, low) = split_time(start);
+        Self {
+            start_length: [high, len],
+            key_color: key as u32 | (color << 8),
+            start_low: low,
+            track_chan,
         }
     }
 }
 
+fn split_time(time: f64) -> (f32, f32) {
+    let high = time as f32;
+    let low = (time - high as f64) as f32;
+    (high, low)
+}
+
+/// The notes of a column uploaded to device local memory once per file
+pub struct ResidentColumn {
+    buffer: Arc<DeviceLocalBuffer<[NoteVertex]>>,
+    /// Only uploaded if some of the column's notes aren't at their key's pitch
+    pitch_offsets: Option<Arc<DeviceLocalBuffer<[f32]>>>,
+}
+
+/// A range of notes in a resident column to draw
+pub struct ResidentDraw<'a> {
+    pub column: &'a ResidentColumn,
+    pub range: Range<u32>,
+}
+
 struct BufferSet {
     vertex_buffers: Vec<Arc<CpuAccessibleBuffer<[NoteVertex]>>>,
+    /// The pitch offsets of the notes in each vertex buffer, only allocated
+    /// while the notes are drawn at their tuned pitch
+    pitch_buffers: Vec<Arc<CpuAccessibleBuffer<[f32]>>>,
     index: usize,
 }
 
-fn get_buffer(device: &Arc<Device>) -> Arc<CpuAccessibleBuffer<[NoteVertex]>> {
+fn get_buffer<T: Pod + Send + Sync>(
+    device: &Arc<Device>,
+    size: u64,
+) -> Option<Arc<CpuAccessibleBuffer<[T]>>> {
     unsafe {
-        CpuAccessibleBuffer::uninitialized_array(
-            device.clone(),
-            NOTE_BUFFER_SIZE,
-            BufferUsage::all(),
-            false,
-        )
-        .expect("failed to create buffer")
+        CpuAccessibleBuffer::uninitialized_array(device.clone(), size, BufferUsage::all(), false)
+            .map_err(|e| println!("Failed to create note buffer of {} notes: {:?}", size, e))
+            .ok()
     }
 }
 
 impl BufferSet {
-    fn new(device: &Arc<Device>) -> Self {
-        Self {
-            vertex_buffers: vec![get_buffer(device), get_buffer(device)],
-            index: 0,
+    /// Allocates the buffers, halving their size until they fit in memory.
+    /// Fails if not even buffers of [`MIN_NOTE_BUFFER_SIZE`] notes fit.
+    fn new(device: &Arc<Device>, requested_size: u64) -> Result<Self, String> {
+        let mut size = requested_size;
+        loop {
+            let vertex_buffers: Option<Vec<_>> = (0..NOTE_BUFFER_COUNT)
+                .map(|_| get_buffer(device, size))
+                .collect();
+
+            match vertex_buffers {
+                Some(vertex_buffers) => {
+                    return Ok(Self {
+                        vertex_buffers,
+                        pitch_buffers: Vec::new(),
+                        index: 0,
+                    })
+                }
+                None if size > MIN_NOTE_BUFFER_SIZE => {
+                    size = (size / 2).max(MIN_NOTE_BUFFER_SIZE);
+                }
+                None => {
+                    return Err(format!(
+                        "not even {} notes per buffer fit in memory",
+                        MIN_NOTE_BUFFER_SIZE
+                    ))
+                }
+            }
+        }
+    }
+
+    /// The amount of notes each buffer holds
+    fn size(&self) -> u64 {
+        self.vertex_buffers[0].len()
+    }
+
+    /// Allocates the pitch offset buffers when the notes get drawn at their
+    /// tuned pitch, and frees them when they don't. Without them, the notes
+    /// are drawn at their keys.
+    fn set_tuned(&mut self, device: &Arc<Device>, tuned: bool) {
+        if !tuned {
+            self.pitch_buffers.clear();
+        } else if self.pitch_buffers.is_empty() {
+            let size = self.size();
+            self.pitch_buffers = (0..self.vertex_buffers.len())
+                .map(|_| get_buffer(device, size))
+                .collect::<Option<_>>()
+                .unwrap_or_default();
         }
     }
 
-    fn next(&mut self) -> &Arc<CpuAccessibleBuffer<[NoteVertex]>> {
+    fn next(
+        &mut self,
+    ) -> (
+        Arc<CpuAccessibleBuffer<[NoteVertex]>>,
+        Option<Arc<CpuAccessibleBuffer<[f32]>>>,
+    ) {
         self.index = (self.index + 1) % self.vertex_buffers.len();
-        &self.vertex_buffers[self.index]
+        (
+            self.vertex_buffers[self.index].clone(),
+            self.pitch_buffers.get(self.index).cloned(),
+        )
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
-pub enum NotePassStatus {
-    Finished { remaining: u32 },
-    HasMoreNotes,
+/// Submitted commands that haven't been waited for yet
+pub type SubmitFuture = FenceSignalFuture<Box<dyn GpuFuture>>;
+
+/// Submits a command buffer to run after `previous`, or right away. A failed
+/// submission, e.g. after the device was lost, only skips the drawing, the
+/// frame presentation notices it.
+pub fn submit(
+    queue: &Arc<Queue>,
+    previous: Option<SubmitFuture>,
+    command_buffer: PrimaryAutoCommandBuffer,
+) -> Option<SubmitFuture> {
+    let previous = match previous {
+        Some(previous) => previous.boxed(),
+        None => sync::now(queue.device().clone()).boxed(),
+    };
+
+    let future = match previous.then_execute(queue.clone(), command_buffer) {
+        Ok(future) => future.boxed(),
+        Err(e) => {
+            println!("Failed to execute commands: {:?}", e);
+            return None;
+        }
+    };
+
+    match future.then_signal_fence_and_flush() {
+        Ok(future) => Some(future),
+        Err(e) => {
+            println!("Failed to signal fence and flush: {:?}", e);
+            None
+        }
+    }
 }
 
-#[repr(C)]
-#[derive(Default, Debug, Copy, Clone, Zeroable, Pod)]
-pub struct KeyPosition {
-    left: f32,
-    right: f32,
-    _padding: [u8; 8],
+/// Waits for a submitted future, returns whether it finished successfully
+pub fn wait_for(future: Option<SubmitFuture>) -> bool {
+    match future.map(|future| future.wait(None)) {
+        Some(Ok(())) => true,
+        Some(Err(err)) => {
+            println!("err: {:?}", err);
+            false
+        }
+        None => false,
+    }
 }
 
-pub struct NoteRenderPass {
-    gfx_queue: Arc<Queue>,
-    buffer_set: BufferSet,
-    pipeline_clear: Arc<GraphicsPipeline>,
-    pipeline_draw_over: Arc<GraphicsPipeline>,
-    render_pass_clear: Arc<RenderPass>,
-    render_pass_draw_over: Arc<RenderPass>,
-    key_locations: Arc<CpuAccessibleBuffer<[[KeyPosition; 256]]>>,
-    depth_buffer: Arc<ImageView<AttachmentImage>>,
+fn create_texture(
+    queue: &Arc<Queue>,
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+) -> Arc<ImageView<ImmutableImage>> {
+    let (image, future) = ImmutableImage::from_iter(
+        pixels.into_iter(),
+        ImageDimensions::Dim2d {
+            width,
+            height,
+            array_layers: 1,
+        },
+        MipmapsCount::One,
+        // Image files are sRGB, sampling this format gives linear colors
+        Format::R8G8B8A8_SRGB,
+        queue.clone(),
+    )
+    .expect("failed to create texture");
+
+    future
+        .then_signal_fence_and_flush()
+        .and_then(|future| future.wait(None))
+        .expect("failed to upload texture");
+
+    ImageView::new_default(image).expect("failed to create texture view")
 }
 
-impl NoteRenderPass {
-    pub fn new(renderer: &GuiRenderer) -> NoteRenderPass {
-        let gfx_queue = renderer.queue.clone();
+fn load_texture(queue: &Arc<Queue>, path: &Path) -> Option<Arc<ImageView<ImmutableImage>>> {
+    match image::open(path) {
+        Ok(image) => {
+            let image = image.to_rgba8();
+            Some(create_texture(
+                queue,
+                image.width(),
+                image.height(),
+                image.into_raw(),
+            ))
+        }
+        Err(e) => {
+            println!("Failed to load note texture: {}", e);
+            None
+        }
+    }
+}
+
+/// How each note point gets expanded into a quad
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NotePipelineKind {
+    /// One point per note, expanded by `notes.geom`
+    GeometryShader,
+    /// One instance per note, drawn as a 4 vertex triangle strip by
+    /// `notes_instanced.vert`. Used on devices without geometry shaders.
+    Instanced,
+}
+
+fn build_pipeline(
+    gfx_queue: &Arc<Queue>,
+    kind: NotePipelineKind,
+    overlap: OverlapMode,
+    render_pass: &Arc<RenderPass>,
+) -> Arc<GraphicsPipeline> {
+    let device = gfx_queue.device().clone();
+    let fs = fs::load(device.clone()).expect("failed to create shader module");
+
+    let subpass = Subpass::from(render_pass.clone(), 0).unwrap();
+    let multisample_state = MultisampleState {
+        rasterization_samples: subpass.num_samples().unwrap_or(SampleCount::Sample1),
+        ..MultisampleState::new()
+    };
 
-        let render_pass_clear = vulkano::ordered_passes_renderpass!(gfx_queue.device().clone(),
+    // The notes are all drawn at the depths from `note_depth` in the shaders.
+    // With a less-than test the first drawn note wins ties, with less-or-equal
+    // the last one does, and the transparent mode blends them all instead.
+    let mut depth_stencil_state = DepthStencilState::simple_depth_test();
+    let mut color_blend_state = ColorBlendState::new(1);
+    match overlap {
+        OverlapMode::OldestOnTop => {
+            if let Some(depth) = depth_stencil_state.depth.as_mut() {
+                depth.compare_op = StateMode::Fixed(CompareOp::LessOrEqual);
+            }
+        }
+        OverlapMode::Transparent => {
+            depth_stencil_state = DepthStencilState::disabled();
+            // The fragment shader outputs premultiplied colors
+            color_blend_state = color_blend_state.blend(AttachmentBlend {
+                color_op: BlendOp::Add,
+                color_source: BlendFactor::One,
+                color_destination: BlendFactor::OneMinusSrcAlpha,
+                alpha_op: BlendOp::Add,
+                alpha_source: BlendFactor::One,
+                alpha_destination: BlendFactor::OneMinusSrcAlpha,
+            });
+        }
+        _ => {}
+    }
+
+    let pipeline = match kind {
+        NotePipelineKind::GeometryShader => {
+            let vs = vs::load(device.clone()).expect("failed to create shader module");
+            let gs = gs::load(device.clone()).expect("failed to create shader module");
+
+            GraphicsPipeline::start()
+                .input_assembly_state(
+                    InputAssemblyState::new().topology(PrimitiveTopology::PointList),
+                )
+                .vertex_input_state(BuffersDefinition::new().vertex::<NoteVertex>())
+                .vertex_shader(vs.entry_point("main").unwrap(), ())
+                .geometry_shader(gs.entry_point("main").unwrap(), ())
+                .fragment_shader(fs.entry_point("main").unwrap(), ())
+                .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+                .depth_stencil_state(depth_stencil_state)
+                .color_blend_state(color_blend_state)
+                .multisample_state(multisample_state)
+                .render_pass(subpass)
+                .build(device)
+        }
+        NotePipelineKind::Instanced => {
+            let vs = vs_instanced::load(device.clone()).expect("failed to create shader module");
+
+            GraphicsPipeline::start()
+                .input_assembly_state(
+                    InputAssemblyState::new().topology(PrimitiveTopology::TriangleStrip),
+                )
+                .vertex_input_state(BuffersDefinition::new().instance::<NoteVertex>())
+                .vertex_shader(vs.entry_point("main").unwrap(), ())
+                .fragment_shader(fs.entry_point("main").unwrap(), ())
+                .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
+                .depth_stencil_state(depth_stencil_state)
+                .color_blend_state(color_blend_state)
+                .multisample_state(multisample_state)
+                .render_pass(subpass)
+                .build(device)
+        }
+    };
+
+    pipeline.unwrap()
+}
+
+/// Creates the render pass that clears the images and the one that draws over
+/// them. With MSAA, the multisampled image is resolved into the final color.
+fn create_render_passes(
+    device: &Arc<Device>,
+    format: Format,
+    samples: u32,
+) -> (Arc<RenderPass>, Arc<RenderPass>) {
+    if samples == 1 {
+        let render_pass_clear = vulkano::ordered_passes_renderpass!(device.clone(),
             attachments: {
                 final_color: {
                     load: Clear,
                     store: Store,
-                    format: renderer.format,
+                    format: format,
                     samples: 1,
                 },
                 depth: {
@@ -127,16 +413,16 @@ impl NoteRenderPass {
         )
         .unwrap();
 
-        let render_pass_draw_over = vulkano::ordered_passes_renderpass!(gfx_queue.device().clone(),
+        let render_pass_draw_over = vulkano::ordered_passes_renderpass!(device.clone(),
             attachments: {
                 final_color: {
-                    load: DontCare,
+                    load: Load,
                     store: Store,
-                    format: renderer.format,
+                    format: format,
                     samples: 1,
                 },
                 depth: {
-                    load: DontCare,
+                    load: Load,
                     store: Store,
                     format: Format::D16_UNORM,
                     samples: 1,
@@ -152,16 +438,300 @@ impl NoteRenderPass {
         )
         .unwrap();
 
-        let depth_buffer = ImageView::new_default(
-            AttachmentImage::transient_input_attachment(
-                gfx_queue.device().clone(),
-                [1, 1],
-                Format::D16_UNORM,
-            )
-            .unwrap(),
+        (render_pass_clear, render_pass_draw_over)
+    } else {
+        let render_pass_clear = vulkano::ordered_passes_renderpass!(device.clone(),
+            attachments: {
+                msaa_color: {
+                    load: Clear,
+                    store: Store,
+                    format: format,
+                    samples: samples,
+                },
+                final_color: {
+                    load: DontCare,
+                    store: Store,
+                    format: format,
+                    samples: 1,
+                },
+                depth: {
+                    load: Clear,
+                    store: Store,
+                    format: Format::D16_UNORM,
+                    samples: samples,
+                }
+            },
+            passes: [
+                {
+                    color: [msaa_color],
+                    depth_stencil: {depth},
+                    input: [],
+                    resolve: [final_color]
+                }
+            ]
+        )
+        .unwrap();
+
+        let render_pass_draw_over = vulkano::ordered_passes_renderpass!(device.clone(),
+            attachments: {
+                msaa_color: {
+                    load: Load,
+                    store: Store,
+                    format: format,
+                    samples: samples,
+                },
+                final_color: {
+                    load: DontCare,
+                    store: Store,
+                    format: format,
+                    samples: 1,
+                },
+                depth: {
+                    load: Load,
+                    store: Store,
+                    format: Format::D16_UNORM,
+                    samples: samples,
+                }
+            },
+            passes: [
+                {
+                    color: [msaa_color],
+                    depth_stencil: {depth},
+                    input: [],
+                    resolve: [final_color]
+                }
+            ]
         )
         .unwrap();
 
+        (render_pass_clear, render_pass_draw_over)
+    }
+}
+
+/// The images the notes are rendered into, besides the scene image. They keep
+/// their contents between the passes of a frame, so none of them are transient.
+struct RenderTargets {
+    /// The size of the scene image
+    size: [u32; 2],
+    /// The antialiasing that was asked for
+    requested: AntiAliasing,
+    /// The antialiasing the images were created with, lower than the requested
+    /// one if that didn't fit
+    antialiasing: AntiAliasing,
+    depth: Arc<ImageView<AttachmentImage>>,
+    /// With MSAA, the multisampled image that gets resolved at the end of each pass
+    msaa_color: Option<Arc<ImageView<AttachmentImage>>>,
+    /// With supersampling, the images that are downsampled into the scene image,
+    /// from the largest to the smallest, each half the size of the previous one
+    supersampled: Vec<Arc<ImageView<AttachmentImage>>>,
+}
+
+fn create_view(
+    image: Result<Arc<AttachmentImage>, ImageCreationError>,
+) -> Result<Arc<ImageView<AttachmentImage>>, String> {
+    let image = image.map_err(|e| format!("{:?}", e))?;
+    ImageView::new_default(image).map_err(|e| format!("{:?}", e))
+}
+
+impl RenderTargets {
+    fn new(
+        device: &Arc<Device>,
+        format: Format,
+        size: [u32; 2],
+        antialiasing: &AntiAliasing,
+    ) -> Result<Self, String> {
+        let render_size = [
+            size[0] * antialiasing.supersampling,
+            size[1] * antialiasing.supersampling,
+        ];
+
+        let depth_usage = ImageUsage {
+            depth_stencil_attachment: true,
+            ..ImageUsage::none()
+        };
+        let (depth, msaa_color) = if antialiasing.msaa_samples == 1 {
+            let depth = AttachmentImage::with_usage(
+                device.clone(),
+                render_size,
+                Format::D16_UNORM,
+                depth_usage,
+            );
+
+            (create_view(depth)?, None)
+        } else {
+            let samples = SampleCount::try_from(antialiasing.msaa_samples)
+                .map_err(|_| format!("{} samples aren't supported", antialiasing.msaa_samples))?;
+            let depth = AttachmentImage::multisampled_with_usage(
+                device.clone(),
+                render_size,
+                samples,
+                Format::D16_UNORM,
+                depth_usage,
+            );
+            let color = AttachmentImage::multisampled_with_usage(
+                device.clone(),
+                render_size,
+                samples,
+                format,
+                ImageUsage {
+                    color_attachment: true,
+                    ..ImageUsage::none()
+                },
+            );
+
+            (create_view(depth)?, Some(create_view(color)?))
+        };
+
+        let usage = ImageUsage {
+            color_attachment: true,
+            transfer_source: true,
+            transfer_destination: true,
+            ..ImageUsage::none()
+        };
+        let mut supersampled = Vec::new();
+        let mut factor = antialiasing.supersampling;
+        while factor > 1 {
+            let image = AttachmentImage::with_usage(
+                device.clone(),
+                [size[0] * factor, size[1] * factor],
+                format,
+                usage,
+            );
+            supersampled.push(create_view(image)?);
+            factor /= 2;
+        }
+
+        Ok(RenderTargets {
+            size,
+            requested: antialiasing.clone(),
+            antialiasing: antialiasing.clone(),
+            depth,
+            msaa_color,
+            supersampled,
+        })
+    }
+
+    /// Creates the images with the requested antialiasing, lowering the
+    /// supersampling and then the MSAA samples until they fit in memory and
+    /// in the device's image size limits. `None` if nothing fits.
+    fn with_fallback(
+        device: &Arc<Device>,
+        format: Format,
+        size: [u32; 2],
+        requested: &AntiAliasing,
+    ) -> Option<Self> {
+        let mut antialiasing = requested.clone();
+        loop {
+            match Self::new(device, format, size, &antialiasing) {
+                Ok(mut targets) => {
+                    targets.requested = requested.clone();
+                    return Some(targets);
+                }
+                Err(e) => println!(
+                    "Failed to create {}x{} note images with {}x MSAA and {}x supersampling: {}",
+                    size[0], size[1], antialiasing.msaa_samples, antialiasing.supersampling, e
+                ),
+            }
+
+            if antialiasing.supersampling > 1 {
+                antialiasing.supersampling /= 2;
+            } else if antialiasing.msaa_samples > 1 {
+                antialiasing.msaa_samples /= 2;
+            } else {
+                return None;
+            }
+        }
+    }
+}
+
+/// Which images the notes are drawn into
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DrawTarget {
+    /// The scene shown in the window, its images are kept between frames
+    Frame,
+    /// A larger copy of the frame for a screenshot, drawn with its own images
+    /// so the ones of the frame are kept. They are freed with
+    /// [`NoteRenderPass::free_screenshot_targets`].
+    Screenshot,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NotePassStatus {
+    Finished { remaining: u32 },
+    HasMoreNotes,
+}
+
+#[repr(C)]
+#[derive(Default, Debug, Copy, Clone, Zeroable, Pod)]
+pub struct KeyPosition {
+    left: f32,
+    right: f32,
+    black: u32,
+    _padding: [u8; 4],
+}
+
+pub struct NoteRenderPass {
+    gfx_queue: Arc<Queue>,
+    format: Format,
+    /// Whether the shader has to encode the colors to sRGB for the target format
+    encode_srgb: bool,
+    antialiasing: AntiAliasing,
+    /// The streaming buffers, only allocated once notes get streamed. `None`
+    /// if they didn't fit in memory, in which case nothing is streamed.
+    buffer_set: Option<BufferSet>,
+    /// The size the buffers were last allocated for, even if that failed
+    allocated_size: Option<u64>,
+    /// A new requested size and when it was first asked for
+    pending_size: Option<(u64, Instant)>,
+    pipeline_kind: NotePipelineKind,
+    overlap: NoteOverlap,
+    pipeline_clear: Arc<GraphicsPipeline>,
+    pipeline_draw_over: Arc<GraphicsPipeline>,
+    render_pass_clear: Arc<RenderPass>,
+    render_pass_draw_over: Arc<RenderPass>,
+    key_locations: Arc<CpuAccessibleBuffer<[[KeyPosition; 256]]>>,
+    style_buffer: Arc<CpuAccessibleBuffer<NoteStyleUniform>>,
+    /// Bound instead of the pitch offsets of notes that have none, the
+    /// shaders treat all the notes past its end as having no offset
+    no_pitch_offsets: Arc<CpuAccessibleBuffer<[f32]>>,
+    sampler: Arc<Sampler>,
+    blank_texture: Arc<ImageView<ImmutableImage>>,
+    texture: Option<Arc<ImageView<ImmutableImage>>>,
+    texture_path: Option<PathBuf>,
+    targets: Option<RenderTargets>,
+    /// The scene size and antialiasing no images could be created for, so
+    /// that it isn't retried every frame
+    failed_targets: Option<([u32; 2], AntiAliasing)>,
+    screenshot_targets: Option<RenderTargets>,
+    /// The images the current draw goes to
+    draw_target: DrawTarget,
+}
+
+impl NoteRenderPass {
+    pub fn new(renderer: &GuiRenderer) -> NoteRenderPass {
+        let pipeline_kind = if renderer.device.enabled_features().geometry_shader {
+            NotePipelineKind::GeometryShader
+        } else {
+            NotePipelineKind::Instanced
+        };
+        println!("Using {:?} note pipeline", pipeline_kind);
+
+        Self::with_pipeline(renderer.queue.clone(), renderer.format, pipeline_kind)
+    }
+
+    /// Creates the pass for images of `format`, expanding the notes with the
+    /// given pipeline
+    fn with_pipeline(
+        gfx_queue: Arc<Queue>,
+        format: Format,
+        pipeline_kind: NotePipelineKind,
+    ) -> NoteRenderPass {
+        let encode_srgb = needs_srgb_encoding(format);
+
+        let antialiasing = AntiAliasing::default();
+        let (render_pass_clear, render_pass_draw_over) =
+            create_render_passes(gfx_queue.device(), format, antialiasing.msaa_samples);
+
         let key_locations = CpuAccessibleBuffer::from_iter(
             gfx_queue.device().clone(),
             BufferUsage::all(),
@@ -170,65 +740,311 @@ impl NoteRenderPass {
         )
         .unwrap();
 
-        let vs = vs::load(gfx_queue.device().clone()).expect("failed to create shader module");
-        let fs = fs::load(gfx_queue.device().clone()).expect("failed to create shader module");
-        let gs = gs::load(gfx_queue.device().clone()).expect("failed to create shader module");
-
-        let pipeline_clear = GraphicsPipeline::start()
-            .input_assembly_state(InputAssemblyState::new().topology(PrimitiveTopology::PointList))
-            .vertex_input_state(BuffersDefinition::new().vertex::<NoteVertex>())
-            .vertex_shader(vs.entry_point("main").unwrap(), ())
-            .geometry_shader(gs.entry_point("main").unwrap(), ())
-            .fragment_shader(fs.entry_point("main").unwrap(), ())
-            .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
-            .depth_stencil_state(DepthStencilState::simple_depth_test())
-            .render_pass(Subpass::from(render_pass_clear.clone(), 0).unwrap())
-            .build(gfx_queue.device().clone())
-            .unwrap();
+        let style_buffer = CpuAccessibleBuffer::from_data(
+            gfx_queue.device().clone(),
+            BufferUsage::all(),
+            false,
+            NoteStyle::default().as_uniform(false, 1.0, encode_srgb),
+        )
+        .unwrap();
 
-        let pipeline_draw_over = GraphicsPipeline::start()
-            .input_assembly_state(InputAssemblyState::new().topology(PrimitiveTopology::PointList))
-            .vertex_input_state(BuffersDefinition::new().vertex::<NoteVertex>())
-            .vertex_shader(vs.entry_point("main").unwrap(), ())
-            .geometry_shader(gs.entry_point("main").unwrap(), ())
-            .fragment_shader(fs.entry_point("main").unwrap(), ())
-            .viewport_state(ViewportState::viewport_dynamic_scissor_irrelevant())
-            .depth_stencil_state(DepthStencilState::simple_depth_test())
-            .render_pass(Subpass::from(render_pass_draw_over.clone(), 0).unwrap())
-            .build(gfx_queue.device().clone())
-            .unwrap();
+        let no_pitch_offsets = CpuAccessibleBuffer::from_iter(
+            gfx_queue.device().clone(),
+            BufferUsage::storage_buffer(),
+            false,
+            [0.0f32].into_iter(),
+        )
+        .unwrap();
+
+        let sampler = Sampler::new(
+            gfx_queue.device().clone(),
+            SamplerCreateInfo::simple_repeat_linear(),
+        )
+        .unwrap();
+
+        let blank_texture = create_texture(&gfx_queue, 1, 1, vec![255; 4]);
+
+        let overlap = NoteOverlap::default();
+        let pipeline_clear =
+            build_pipeline(&gfx_queue, pipeline_kind, overlap.mode, &render_pass_clear);
+        let pipeline_draw_over =
+            build_pipeline(&gfx_queue, pipeline_kind, overlap.mode, &render_pass_draw_over);
 
         NoteRenderPass {
             gfx_queue,
-            buffer_set: BufferSet::new(&renderer.device),
+            format,
+            encode_srgb,
+            antialiasing,
+            buffer_set: None,
+            allocated_size: None,
+            pending_size: None,
+            pipeline_kind,
+            overlap,
             pipeline_clear,
             pipeline_draw_over,
             render_pass_clear,
             render_pass_draw_over,
-            depth_buffer,
+            targets: None,
+            failed_targets: None,
+            screenshot_targets: None,
+            draw_target: DrawTarget::Frame,
             key_locations,
+            style_buffer,
+            no_pitch_offsets,
+            sampler,
+            blank_texture,
+            texture: None,
+            texture_path: None,
         }
     }
 
-    pub fn draw(
+    /// Allocates the streaming buffers the first time, and reallocates them
+    /// once the settings ask for a different size for long enough. Returns the
+    /// amount of notes each buffer holds.
+    pub fn update_buffer_size(&mut self, settings: &NoteBufferSettings) -> u64 {
+        let device = self.gfx_queue.device().clone();
+        let note_size = std::mem::size_of::<NoteVertex>() as u64;
+        let requested =
+            settings.notes_per_buffer(device.physical_device(), note_size, NOTE_BUFFER_COUNT);
+
+        match (self.allocated_size, self.pending_size) {
+            (Some(size), _) if size == requested => self.pending_size = None,
+            (Some(_), Some((size, since))) if size == requested => {
+                if since.elapsed() >= BUFFER_RESIZE_DELAY {
+                    self.allocate_buffers(requested);
+                }
+            }
+            (Some(_), _) => self.pending_size = Some((requested, Instant::now())),
+            (None, _) => self.allocate_buffers(requested),
+        }
+
+        self.buffer_size()
+    }
+
+    fn allocate_buffers(&mut self, size: u64) {
+        // Free the old buffers first, so they don't count against the new ones
+        self.buffer_set = None;
+        self.buffer_set = match BufferSet::new(self.gfx_queue.device(), size) {
+            Ok(buffer_set) => Some(buffer_set),
+            Err(e) => {
+                println!("Failed to create the note buffers, notes can't be streamed: {}", e);
+                None
+            }
+        };
+        self.allocated_size = Some(size);
+        self.pending_size = None;
+    }
+
+    /// The amount of notes each streaming buffer holds, 0 if they aren't allocated
+    pub fn buffer_size(&self) -> u64 {
+        self.buffer_set.as_ref().map(|set| set.size()).unwrap_or(0)
+    }
+
+    /// Uploads the notes of each column to device local memory. The notes
+    /// are written straight into staging buffers and copied in a single
+    /// submission. Each column comes with its amount of notes, and each note
+    /// with its pitch offset, which are only uploaded for the columns where
+    /// some aren't 0. Returns `None` if the device ran out of memory, in which
+    /// case the notes have to be streamed with [`NoteRenderPass::draw`] instead.
+    pub fn upload_columns<Notes: Iterator<Item = (NoteVertex, f32)>>(
+        &self,
+        columns: impl Iterator<Item = (usize, Notes)>,
+    ) -> Option<Vec<Option<ResidentColumn>>> {
+        let device = self.gfx_queue.device();
+        let usage = BufferUsage {
+            vertex_buffer: true,
+            transfer_destination: true,
+            ..BufferUsage::none()
+        };
+        let pitch_usage = BufferUsage {
+            storage_buffer: true,
+            transfer_destination: true,
+            ..BufferUsage::none()
+        };
+
+        let mut command_buffer_builder = AutoCommandBufferBuilder::primary(
+            device.clone(),
+            self.gfx_queue.family(),
+            CommandBufferUsage::OneTimeSubmit,
+        )
+        .unwrap();
+
+        let mut resident = Vec::new();
+        for (count, notes) in columns {
+            if count == 0 {
+                resident.push(None);
+                continue;
+            }
+
+            let staging = unsafe {
+                CpuAccessibleBuffer::<[NoteVertex]>::uninitialized_array(
+                    device.clone(),
+                    count as u64,
+                    BufferUsage::transfer_source(),
+                    false,
+                )
+            };
+            let buffer = DeviceLocalBuffer::<[NoteVertex]>::array(
+                device.clone(),
+                count as u64,
+                usage,
+                [self.gfx_queue.family()],
+            );
+            let (staging, buffer) = match (staging, buffer) {
+                (Ok(staging), Ok(buffer)) => (staging, buffer),
+                (Err(e), _) | (_, Err(e)) => {
+                    println!("Failed to upload notes to the GPU: {:?}", e);
+                    return None;
+                }
+            };
+
+            let mut pitch_offsets = Vec::new();
+            for (i, (write, (note, pitch_offset))) in
+                staging.write().unwrap().iter_mut().zip(notes).enumerate()
+            {
+                *write = note;
+                if pitch_offset != 0.0 || !pitch_offsets.is_empty() {
+                    pitch_offsets.resize(i, 0.0);
+                    pitch_offsets.push(pitch_offset);
+                }
+            }
+
+            command_buffer_builder
+                .copy_buffer(staging, buffer.clone())
+                .unwrap();
+
+            let pitch_offsets = if pitch_offsets.is_empty() {
+                None
+            } else {
+                pitch_offsets.resize(count, 0.0);
+                let staging = CpuAccessibleBuffer::from_iter(
+                    device.clone(),
+                    BufferUsage::transfer_source(),
+                    false,
+                    pitch_offsets,
+                );
+                let buffer = DeviceLocalBuffer::<[f32]>::array(
+                    device.clone(),
+                    count as u64,
+                    pitch_usage,
+                    [self.gfx_queue.family()],
+                );
+                match (staging, buffer) {
+                    (Ok(staging), Ok(buffer)) => {
+                        command_buffer_builder
+                            .copy_buffer(staging, buffer.clone())
+                            .unwrap();
+                        Some(buffer)
+                    }
+                    (Err(e), _) | (_, Err(e)) => {
+                        println!("Failed to upload pitch offsets to the GPU: {:?}", e);
+                        return None;
+                    }
+                }
+            };
+
+            resident.push(Some(ResidentColumn {
+                buffer,
+                pitch_offsets,
+            }));
+        }
+
+        let command_buffer = command_buffer_builder.build().unwrap();
+        if wait_for(submit(&self.gfx_queue, None, command_buffer)) {
+            Some(resident)
+        } else {
+            None
+        }
+    }
+
+    /// Updates the per frame state shared by both drawing paths. Returns
+    /// false if there are no images to draw into.
+    fn prepare(
         &mut self,
-        final_image: Arc<dyn ImageViewAbstract + 'static>,
+        img_dims: [u32; 2],
+        target: DrawTarget,
         key_view: &KeyboardView,
-        mut view_range: f32,
-        mut fill_buffer: impl FnMut(&Arc<CpuAccessibleBuffer<[NoteVertex]>>) -> NotePassStatus,
-    ) {
-        let img_dims = final_image.image().dimensions().width_height();
-        if self.depth_buffer.image().dimensions().width_height() != img_dims {
-            self.depth_buffer = ImageView::new_default(
-                AttachmentImage::transient_input_attachment(
-                    self.gfx_queue.device().clone(),
-                    img_dims,
-                    Format::D16_UNORM,
-                )
-                .unwrap(),
-            )
-            .unwrap();
+        style: &NoteStyle,
+        overlap: &NoteOverlap,
+        antialiasing: &AntiAliasing,
+    ) -> bool {
+        self.draw_target = target;
+        match target {
+            DrawTarget::Frame => {
+                let request = (img_dims, antialiasing.clone());
+                let outdated = match &self.targets {
+                    Some(targets) => {
+                        targets.size != img_dims || targets.requested != *antialiasing
+                    }
+                    None => self.failed_targets.as_ref() != Some(&request),
+                };
+                if outdated {
+                    // Free the old images before allocating the new ones
+                    self.targets = None;
+                    self.targets = RenderTargets::with_fallback(
+                        self.gfx_queue.device(),
+                        self.format,
+                        img_dims,
+                        antialiasing,
+                    );
+                    self.failed_targets = match self.targets {
+                        Some(_) => None,
+                        None => Some(request),
+                    };
+                }
+            }
+            DrawTarget::Screenshot => {
+                let outdated = match &self.screenshot_targets {
+                    Some(targets) => {
+                        targets.size != img_dims || targets.requested != *antialiasing
+                    }
+                    None => true,
+                };
+                if outdated {
+                    self.screenshot_targets = None;
+                    self.screenshot_targets = RenderTargets::with_fallback(
+                        self.gfx_queue.device(),
+                        self.format,
+                        img_dims,
+                        antialiasing,
+                    );
+                }
+            }
+        }
+
+        // The antialiasing the images could be created with
+        let antialiasing = match self.current_targets() {
+            Some(targets) => targets.antialiasing.clone(),
+            None => return false,
+        };
+
+        let samples_changed = antialiasing.msaa_samples != self.antialiasing.msaa_samples;
+        if samples_changed {
+            let (render_pass_clear, render_pass_draw_over) = create_render_passes(
+                self.gfx_queue.device(),
+                self.format,
+                antialiasing.msaa_samples,
+            );
+            self.render_pass_clear = render_pass_clear;
+            self.render_pass_draw_over = render_pass_draw_over;
+        }
+
+        if samples_changed || overlap.mode != self.overlap.mode {
+            self.pipeline_clear = build_pipeline(
+                &self.gfx_queue,
+                self.pipeline_kind,
+                overlap.mode,
+                &self.render_pass_clear,
+            );
+            self.pipeline_draw_over = build_pipeline(
+                &self.gfx_queue,
+                self.pipeline_kind,
+                overlap.mode,
+                &self.render_pass_draw_over,
+            );
         }
+        self.overlap = overlap.clone();
+        self.antialiasing = antialiasing;
 
         {
             let mut keys = self.key_locations.write().unwrap();
@@ -236,130 +1052,391 @@ impl NoteRenderPass {
                 *write = KeyPosition {
                     left: key.left,
                     right: key.right,
-                    _padding: [0; 8],
+                    black: key.black as u32,
+                    _padding: [0; 4],
                 };
             }
         }
 
-        let mut prev_future: Option<FenceSignalFuture<Box<dyn GpuFuture>>> = None;
+        if style.texture_path != self.texture_path {
+            self.texture_path = style.texture_path.clone();
+            self.texture = self
+                .texture_path
+                .as_deref()
+                .and_then(|path| load_texture(&self.gfx_queue, path));
+        }
 
-        let mut status = NotePassStatus::HasMoreNotes;
+        *self.style_buffer.write().unwrap() =
+            style.as_uniform(self.texture.is_some(), overlap.alpha(), self.encode_srgb);
 
-        let mut first_pass = true;
+        true
+    }
 
-        while status == NotePassStatus::HasMoreNotes {
-            let buffer = self.buffer_set.next();
+    fn current_targets(&self) -> Option<&RenderTargets> {
+        match self.draw_target {
+            DrawTarget::Frame => self.targets.as_ref(),
+            DrawTarget::Screenshot => self.screenshot_targets.as_ref(),
+        }
+    }
 
-            status = fill_buffer(buffer);
+    /// Frees the images of the last screenshot, they are only needed while
+    /// it is drawn
+    pub fn free_screenshot_targets(&mut self) {
+        self.screenshot_targets = None;
+    }
 
-            let items_to_render = match status {
-                NotePassStatus::Finished { remaining } => {
-                    assert!(remaining <= buffer.len() as u32);
-                    remaining
-                }
-                NotePassStatus::HasMoreNotes => buffer.len() as u32,
-            };
+    /// Binds the keys, style and texture along with the pitch offsets of the
+    /// notes that are drawn next
+    fn bind_descriptor_set(
+        &self,
+        command_buffer_builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        pipeline: &Arc<GraphicsPipeline>,
+        pitch_offsets: Arc<dyn BufferAccess>,
+    ) {
+        let texture = self
+            .texture
+            .clone()
+            .unwrap_or_else(|| self.blank_texture.clone());
 
-            let mut command_buffer_builder = AutoCommandBufferBuilder::primary(
-                self.gfx_queue.device().clone(),
-                self.gfx_queue.family(),
-                CommandBufferUsage::OneTimeSubmit,
-            )
-            .unwrap();
+        let pipeline_layout = pipeline.layout();
 
-            let (clears, pipeline, render_pass) = if first_pass {
-                first_pass = false;
-                (
-                    vec![[0.0, 0.0, 0.0, 0.0].into(), 1.0f32.into()],
-                    &self.pipeline_clear,
-                    &self.render_pass_clear,
-                )
-            } else {
-                (
-                    vec![ClearValue::None, ClearValue::None],
-                    &self.pipeline_draw_over,
-                    &self.render_pass_draw_over,
-                )
-            };
+        let desc_layout = pipeline_layout.set_layouts().get(0).unwrap();
+        let set = PersistentDescriptorSet::new(
+            desc_layout.clone(),
+            [
+                WriteDescriptorSet::buffer(0, self.key_locations.clone()),
+                WriteDescriptorSet::buffer(1, self.style_buffer.clone()),
+                WriteDescriptorSet::image_view_sampler(2, texture, self.sampler.clone()),
+                WriteDescriptorSet::buffer(3, pitch_offsets),
+            ],
+        )
+        .unwrap();
 
-            let framebuffer = Framebuffer::new(
-                render_pass.clone(),
-                FramebufferCreateInfo {
-                    attachments: vec![final_image.clone(), self.depth_buffer.clone()],
-                    ..Default::default()
-                },
-            )
-            .unwrap();
+        command_buffer_builder.bind_descriptor_sets(
+            PipelineBindPoint::Graphics,
+            pipeline_layout.clone(),
+            0,
+            set,
+        );
+    }
 
-            let pipeline_layout = pipeline.layout();
+    /// Starts a command buffer with the render pass begun and the pipeline,
+    /// push constants and descriptor set bound
+    fn begin_pass(
+        &self,
+        first_pass: bool,
+        final_image: &Arc<dyn ImageViewAbstract + 'static>,
+        push_constants: gs::ty::PushConstants,
+        pitch_offsets: Arc<dyn BufferAccess>,
+    ) -> AutoCommandBufferBuilder<PrimaryAutoCommandBuffer> {
+        let targets = self.current_targets().unwrap();
+
+        // With supersampling the notes are drawn into the largest image, and
+        // downsampled into the scene image at the end
+        let target: Arc<dyn ImageViewAbstract + 'static> = match targets.supersampled.first() {
+            Some(image) => image.clone(),
+            None => final_image.clone(),
+        };
+        let render_size = target.image().dimensions().width_height();
+
+        let mut command_buffer_builder = AutoCommandBufferBuilder::primary(
+            self.gfx_queue.device().clone(),
+            self.gfx_queue.family(),
+            CommandBufferUsage::OneTimeSubmit,
+        )
+        .unwrap();
 
-            let desc_layout = pipeline_layout.set_layouts().get(0).unwrap();
-            let set = PersistentDescriptorSet::new(
-                desc_layout.clone(),
-                [WriteDescriptorSet::buffer(0, self.key_locations.clone())],
+        let (mut clears, pipeline, render_pass) = if first_pass {
+            (
+                vec![[0.0, 0.0, 0.0, 0.0].into(), 1.0f32.into()],
+                &self.pipeline_clear,
+                &self.render_pass_clear,
+            )
+        } else {
+            (
+                vec![ClearValue::None, ClearValue::None],
+                &self.pipeline_draw_over,
+                &self.render_pass_draw_over,
             )
+        };
+
+        let mut attachments: Vec<Arc<dyn ImageViewAbstract>> = Vec::new();
+        if let Some(msaa_color) = &targets.msaa_color {
+            attachments.push(msaa_color.clone());
+            // The resolve target doesn't get cleared
+            clears.insert(1, ClearValue::None);
+        }
+        attachments.push(target);
+        attachments.push(targets.depth.clone());
+
+        let framebuffer = Framebuffer::new(
+            render_pass.clone(),
+            FramebufferCreateInfo {
+                attachments,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+
+        command_buffer_builder
+            .begin_render_pass(framebuffer, SubpassContents::Inline, clears)
             .unwrap();
 
-            command_buffer_builder
-                .begin_render_pass(framebuffer.clone(), SubpassContents::Inline, clears)
-                .unwrap();
+        command_buffer_builder
+            .bind_pipeline_graphics(pipeline.clone())
+            .set_viewport(
+                0,
+                [Viewport {
+                    origin: [0.0, 0.0],
+                    dimensions: [render_size[0] as f32, render_size[1] as f32],
+                    depth_range: 0.0..1.0,
+                }],
+            )
+            .push_constants(pipeline.layout().clone(), 0, push_constants);
+        self.bind_descriptor_set(&mut command_buffer_builder, pipeline, pitch_offsets);
 
-            let push_constants = gs::ty::PushConstants {
-                height_time: view_range,
-                win_width: img_dims[0] as f32,
-                win_height: img_dims[1] as f32,
-            };
+        command_buffer_builder
+    }
+
+    /// Downsamples the supersampled images into the scene image after the
+    /// note passes, halving the size with each blit so every pixel gets sampled
+    fn downsample(
+        &self,
+        final_image: &Arc<dyn ImageViewAbstract + 'static>,
+        notes: Option<SubmitFuture>,
+    ) -> Option<SubmitFuture> {
+        let targets = self.current_targets().unwrap();
+        if targets.supersampled.is_empty() || notes.is_none() {
+            return notes;
+        }
+
+        let images: Vec<Arc<dyn ImageAccess>> = targets
+            .supersampled
+            .iter()
+            .map(|view| view.image().clone() as Arc<dyn ImageAccess>)
+            .chain(std::iter::once(final_image.image()))
+            .collect();
+
+        let mut command_buffer_builder = AutoCommandBufferBuilder::primary(
+            self.gfx_queue.device().clone(),
+            self.gfx_queue.family(),
+            CommandBufferUsage::OneTimeSubmit,
+        )
+        .unwrap();
 
+        for pair in images.windows(2) {
+            let source = pair[0].dimensions().width_height();
+            let destination = pair[1].dimensions().width_height();
             command_buffer_builder
-                .bind_pipeline_graphics(pipeline.clone())
-                .set_viewport(
+                .blit_image(
+                    pair[0].clone(),
+                    [0, 0, 0],
+                    [source[0] as i32, source[1] as i32, 1],
                     0,
-                    [Viewport {
-                        origin: [0.0, 0.0],
-                        dimensions: [img_dims[0] as f32, img_dims[1] as f32],
-                        depth_range: 0.0..1.0,
-                    }],
-                )
-                .push_constants(pipeline_layout.clone().clone(), 0, push_constants)
-                .bind_descriptor_sets(
-                    PipelineBindPoint::Graphics,
-                    pipeline_layout.clone(),
                     0,
-                    set.clone(),
+                    pair[1].clone(),
+                    [0, 0, 0],
+                    [destination[0] as i32, destination[1] as i32, 1],
+                    0,
+                    0,
+                    1,
+                    Filter::Linear,
                 )
-                .bind_vertex_buffers(0, buffer.clone())
-                .draw(items_to_render, 1, 0, 0)
                 .unwrap();
+        }
+
+        let command_buffer = command_buffer_builder.build().unwrap();
+        submit(&self.gfx_queue, notes, command_buffer)
+    }
+
+    /// Draws a range of notes from the bound vertex buffer
+    fn draw_notes(
+        &self,
+        command_buffer_builder: &mut AutoCommandBufferBuilder<PrimaryAutoCommandBuffer>,
+        range: Range<u32>,
+    ) {
+        let count = range.end - range.start;
+        match self.pipeline_kind {
+            NotePipelineKind::GeometryShader => {
+                command_buffer_builder.draw(count, 1, range.start, 0).unwrap();
+            }
+            NotePipelineKind::Instanced => {
+                command_buffer_builder.draw(4, count, 0, range.start).unwrap();
+            }
+        }
+    }
+
+    fn push_constants(
+        &self,
+        img_dims: [u32; 2],
+        key_view: &KeyboardView,
+        view_range: f32,
+        view_start: f64,
+        key_shift: i32,
+        tuned_pitch: bool,
+    ) -> gs::ty::PushConstants {
+        let (view_start_high, view_start_low) = split_time(view_start);
+        gs::ty::PushConstants {
+            height_time: view_range,
+            win_width: img_dims[0] as f32,
+            win_height: img_dims[1] as f32,
+            orientation: key_view.orientation.as_u32(),
+            view_start_high,
+            view_start_low,
+            key_shift,
+            overlap_mode: self.overlap.mode.as_u32(),
+            black_over_white: self.overlap.black_over_white as u32,
+            tuned_pitch: tuned_pitch as u32,
+        }
+    }
+
+    /// Streams the notes through the CPU side buffers, in as many passes as
+    /// needed. The notes written by `fill_buffer` are already displaced. It
+    /// gets a buffer for the pitch offsets of the notes if they are drawn at
+    /// their tuned pitch and it could be allocated.
+    /// Returns the submitted last pass without waiting for it, so more drawing
+    /// can be chained after it.
+    pub fn draw(
+        &mut self,
+        final_image: Arc<dyn ImageViewAbstract + 'static>,
+        target: DrawTarget,
+        key_view: &KeyboardView,
+        view_range: f32,
+        style: &NoteStyle,
+        overlap: &NoteOverlap,
+        antialiasing: &AntiAliasing,
+        tuned_pitch: bool,
+        mut fill_buffer: impl FnMut(
+            &Arc<CpuAccessibleBuffer<[NoteVertex]>>,
+            Option<&Arc<CpuAccessibleBuffer<[f32]>>>,
+        ) -> NotePassStatus,
+    ) -> Option<SubmitFuture> {
+        let img_dims = final_image.image().dimensions().width_height();
+        if !self.prepare(img_dims, target, key_view, style, overlap, antialiasing) {
+            return None;
+        }
+
+        let push_constants =
+            self.push_constants(img_dims, key_view, view_range, 0.0, 0, tuned_pitch);
+
+        let mut prev_future: Option<SubmitFuture> = None;
+
+        let mut status = NotePassStatus::HasMoreNotes;
+
+        let mut first_pass = true;
+
+        let device = self.gfx_queue.device().clone();
+        match self.buffer_set.as_mut() {
+            Some(buffer_set) => buffer_set.set_tuned(&device, tuned_pitch),
+            None => return None,
+        }
+
+        while status == NotePassStatus::HasMoreNotes {
+            let (buffer, pitch_buffer) = self.buffer_set.as_mut().unwrap().next();
+
+            status = fill_buffer(&buffer, pitch_buffer.as_ref());
+
+            let items_to_render = match status {
+                NotePassStatus::Finished { remaining } => {
+                    assert!(remaining <= buffer.len() as u32);
+                    remaining
+                }
+                NotePassStatus::HasMoreNotes => buffer.len() as u32,
+            };
+
+            let pitch_offsets: Arc<dyn BufferAccess> = match pitch_buffer {
+                Some(pitch_buffer) => pitch_buffer,
+                None => self.no_pitch_offsets.clone(),
+            };
+            let mut command_buffer_builder =
+                self.begin_pass(first_pass, &final_image, push_constants, pitch_offsets);
+            first_pass = false;
+
+            command_buffer_builder.bind_vertex_buffers(0, buffer);
+            self.draw_notes(&mut command_buffer_builder, 0..items_to_render);
 
             command_buffer_builder.end_render_pass().unwrap();
             let command_buffer = command_buffer_builder.build().unwrap();
 
-            if let Some(prev_future) = prev_future.take() {
-                match prev_future.wait(None) {
-                    Ok(x) => x,
-                    Err(err) => println!("err: {:?}", err),
-                }
-            }
+            wait_for(prev_future.take());
 
-            let future = sync::now(self.gfx_queue.device().clone()).boxed();
-            let after_main_cb = future
-                .then_execute(self.gfx_queue.clone(), command_buffer)
-                .unwrap();
+            prev_future = submit(&self.gfx_queue, None, command_buffer);
+            if prev_future.is_none() {
+                return None;
+            }
+        }
 
-            let future = after_main_cb
-                .boxed()
-                .then_signal_fence_and_flush()
-                .expect("Failed to signal fence and flush");
+        self.downsample(&final_image, prev_future)
+    }
 
-            prev_future = Some(future);
+    /// Draws ranges of notes that were uploaded with [`NoteRenderPass::upload_columns`],
+    /// in a single pass. Like [`NoteRenderPass::draw`], the pass isn't waited for.
+    pub fn draw_resident(
+        &mut self,
+        final_image: Arc<dyn ImageViewAbstract + 'static>,
+        target: DrawTarget,
+        key_view: &KeyboardView,
+        view_range: f32,
+        view_start: f64,
+        key_shift: i32,
+        style: &NoteStyle,
+        overlap: &NoteOverlap,
+        antialiasing: &AntiAliasing,
+        tuned_pitch: bool,
+        draws: &[ResidentDraw],
+    ) -> Option<SubmitFuture> {
+        let img_dims = final_image.image().dimensions().width_height();
+        if !self.prepare(img_dims, target, key_view, style, overlap, antialiasing) {
+            return None;
         }
 
-        if let Some(prev_future) = prev_future {
-            match prev_future.wait(None) {
-                Ok(x) => x,
-                Err(err) => println!("err: {:?}", err),
+        let push_constants = self.push_constants(
+            img_dims,
+            key_view,
+            view_range,
+            view_start,
+            key_shift,
+            tuned_pitch,
+        );
+
+        let mut command_buffer_builder = self.begin_pass(
+            true,
+            &final_image,
+            push_constants,
+            self.no_pitch_offsets.clone(),
+        );
+
+        // The columns without pitch offsets share the placeholder bound with
+        // the pass, it only has to be bound again after a column that has some
+        let mut column_offsets_bound = false;
+        for draw in draws {
+            if draw.range.start == draw.range.end {
+                continue;
+            }
+
+            let pitch_offsets = draw.column.pitch_offsets.as_ref().filter(|_| tuned_pitch);
+            if pitch_offsets.is_some() || column_offsets_bound {
+                column_offsets_bound = pitch_offsets.is_some();
+                let pitch_offsets: Arc<dyn BufferAccess> = match pitch_offsets {
+                    Some(pitch_offsets) => pitch_offsets.clone(),
+                    None => self.no_pitch_offsets.clone(),
+                };
+                self.bind_descriptor_set(
+                    &mut command_buffer_builder,
+                    &self.pipeline_clear,
+                    pitch_offsets,
+                );
             }
+
+            command_buffer_builder.bind_vertex_buffers(0, draw.column.buffer.clone());
+            self.draw_notes(&mut command_buffer_builder, draw.range.clone());
         }
+
+        command_buffer_builder.end_render_pass().unwrap();
+        let command_buffer = command_buffer_builder.build().unwrap();
+
+        let notes = submit(&self.gfx_queue, None, command_buffer);
+        self.downsample(&final_image, notes)
     }
 }
 
@@ -370,6 +1447,13 @@ mod gs {
     }
 }
 
+mod vs_instanced {
+    vulkano_shaders::shader! {
+        ty: "vertex",
+        path: "shaders/notes_instanced.vert"
+    }
+}
+
 mod vs {
     vulkano_shaders::shader! {
         ty: "vertex",
@@ -377,13 +1461,22 @@ mod vs {
 #version 450
 layout(location = 0) in vec2 start_length;
 layout(location = 1) in uint key_color;
+layout(location = 2) in float start_low;
+layout(location = 3) in uint track_chan;
 
 layout(location = 0) out vec2 v_start_length;
 layout(location = 1) out uint v_key_color;
+layout(location = 2) out float v_start_low;
+layout(location = 3) out uint v_track_chan;
+// Where the note's pitch offset is, if it has one
+layout(location = 4) out uint v_note_index;
 
 void main() {
     v_start_length = start_length;
     v_key_color = key_color;
+    v_start_low = start_low;
+    v_track_chan = track_chan;
+    v_note_index = gl_VertexIndex;
 }"
     }
 }
@@ -391,6 +1484,10 @@ void main() {
 mod fs {
     vulkano_shaders::shader! {
         ty: "fragment",
-        path: "shaders/notes.frag"
+        path: "shaders/notes.frag",
+        include: ["shaders"]
     }
 }
+
+#[cfg(test)]
+mod tests;
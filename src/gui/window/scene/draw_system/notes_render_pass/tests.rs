@@ -0,0 +1,229 @@
+use std::sync::Arc;
+
+use image::RgbaImage;
+use vulkano::{
+    device::{
+        physical::{PhysicalDevice, PhysicalDeviceType},
+        Device, DeviceCreateInfo, Features, Queue, QueueCreateInfo,
+    },
+    format::Format,
+    image::{view::ImageView, AttachmentImage, ImageUsage},
+    instance::{Instance, InstanceCreateInfo},
+};
+
+use crate::{
+    gui::window::keyboard_layout::{KeyboardLayout, KeyboardParams},
+    renderer::screenshot::ImageReadback,
+};
+
+use super::{
+    super::{
+        antialiasing::{supported_msaa_samples, AntiAliasing},
+        buffer_budget::{NoteBufferSettings, MIN_NOTE_BUFFER_SIZE},
+        note_style::NoteStyle,
+        overlap::NoteOverlap,
+    },
+    wait_for, DrawTarget, NotePassStatus, NotePipelineKind, NoteRenderPass, NoteVertex,
+};
+
+const SIZE: [u32; 2] = [320, 180];
+const FORMAT: Format = Format::R8G8B8A8_UNORM;
+
+/// A queue on lavapipe, the software Vulkan driver, so the output doesn't
+/// depend on the GPU. `None` if it isn't installed.
+fn lavapipe_queue() -> Option<Arc<Queue>> {
+    let instance = Instance::new(InstanceCreateInfo::default()).ok()?;
+    let physical = PhysicalDevice::enumerate(&instance)
+        .find(|physical| physical.properties().device_type == PhysicalDeviceType::Cpu)?;
+    let queue_family = physical
+        .queue_families()
+        .find(|family| family.supports_graphics())?;
+
+    let (_, mut queues) = Device::new(
+        physical,
+        DeviceCreateInfo {
+            enabled_extensions: *physical.required_extensions(),
+            enabled_features: Features {
+                geometry_shader: physical.supported_features().geometry_shader,
+                ..Features::none()
+            },
+            queue_create_infos: vec![QueueCreateInfo::family(queue_family)],
+            _ne: Default::default(),
+        },
+    )
+    .ok()?;
+
+    queues.next()
+}
+
+/// Overlapping white and black key notes, some cut off by the edges of the view
+fn test_notes() -> Vec<NoteVertex> {
+    let colors = [0xFF0000, 0x00C0FF, 0x40FF40, 0xFFFF80];
+    (0..40)
+        .map(|i| {
+            let key = 48 + (i * 7) % 40;
+            let start = (i as f32 * 0.37) % 1.2 - 0.1;
+            let length = 0.05 + (i % 5) as f32 * 0.08;
+            NoteVertex::new(start, length, key as u8, colors[i % 4], i as u32 % 16)
+        })
+        .collect()
+}
+
+/// Renders the test notes in a single streamed pass and reads the image back
+fn render(
+    queue: &Arc<Queue>,
+    kind: NotePipelineKind,
+    notes: &[NoteVertex],
+    antialiasing: &AntiAliasing,
+) -> RgbaImage {
+    let mut pass = NoteRenderPass::with_pipeline(queue.clone(), FORMAT, kind);
+    pass.update_buffer_size(&NoteBufferSettings {
+        manual_size: Some(MIN_NOTE_BUFFER_SIZE),
+        ..Default::default()
+    });
+
+    let usage = ImageUsage {
+        color_attachment: true,
+        transfer_source: true,
+        transfer_destination: true,
+        ..ImageUsage::none()
+    };
+    let image = AttachmentImage::with_usage(queue.device().clone(), SIZE, FORMAT, usage).unwrap();
+    let view = ImageView::new_default(image.clone()).unwrap();
+
+    let layout = KeyboardLayout::new(&KeyboardParams::default(), None);
+    let key_view = layout.get_view_for_keys(40, 96);
+
+    let future = pass.draw(
+        view,
+        DrawTarget::Frame,
+        &key_view,
+        1.0,
+        &NoteStyle::default(),
+        &NoteOverlap::default(),
+        antialiasing,
+        false,
+        |buffer, _| {
+            buffer.write().unwrap()[..notes.len()].copy_from_slice(notes);
+            NotePassStatus::Finished {
+                remaining: notes.len() as u32,
+            }
+        },
+    );
+    assert!(wait_for(future), "the notes failed to render");
+
+    ImageReadback::read_now(queue, image).expect("failed to read the image back")
+}
+
+#[test]
+fn instanced_matches_geometry_shader() {
+    let queue = match lavapipe_queue() {
+        Some(queue) => queue,
+        None => {
+            println!("lavapipe isn't available, skipping");
+            return;
+        }
+    };
+    assert!(queue.device().enabled_features().geometry_shader);
+
+    let notes = test_notes();
+    let antialiasing = AntiAliasing::default();
+    let geometry = render(
+        &queue,
+        NotePipelineKind::GeometryShader,
+        &notes,
+        &antialiasing,
+    );
+    let instanced = render(&queue, NotePipelineKind::Instanced, &notes, &antialiasing);
+
+    assert!(
+        geometry.pixels().any(|pixel| pixel[3] != 0),
+        "no notes were drawn"
+    );
+    assert!(
+        geometry.as_raw() == instanced.as_raw(),
+        "the pipelines drew different pixels"
+    );
+}
+
+/// Notes apart from each other with edges between pixels, on white and black keys
+fn coverage_notes() -> Vec<NoteVertex> {
+    [48, 55, 61, 66, 72]
+        .iter()
+        .enumerate()
+        .map(|(i, &key)| {
+            let start = 0.1037 + i as f32 * 0.1313;
+            NoteVertex::new(start, 0.2713, key, 0xFFFFFF, 0)
+        })
+        .collect()
+}
+
+/// The notes are opaque and drawn over a transparent image, so the alpha of
+/// each pixel is how much of it they cover
+fn coverage(image: &RgbaImage, x: u32, y: u32) -> u8 {
+    image.get_pixel(x, y)[3]
+}
+
+/// Checks that only the pixels at the edges of the notes changed, and that
+/// some of them got partially covered
+fn assert_smoothed_edges(aliased: &RgbaImage, smoothed: &RgbaImage) {
+    let mut partial = 0;
+    for y in 1..SIZE[1] - 1 {
+        for x in 1..SIZE[0] - 1 {
+            let around: Vec<u8> = (y - 1..=y + 1)
+                .flat_map(|y| (x - 1..=x + 1).map(move |x| coverage(aliased, x, y)))
+                .collect();
+            let smoothed = coverage(smoothed, x, y);
+
+            if around.iter().all(|&alpha| alpha == 255) {
+                assert_eq!(
+                    smoothed, 255,
+                    "the inside of a note at {}, {} changed",
+                    x, y
+                );
+            } else if around.iter().all(|&alpha| alpha == 0) {
+                assert_eq!(smoothed, 0, "the background at {}, {} changed", x, y);
+            } else if smoothed != 0 && smoothed != 255 {
+                partial += 1;
+            }
+        }
+    }
+    assert!(partial > 0, "no edge pixels were partially covered");
+}
+
+#[test]
+fn antialiasing_covers_edges_partially() {
+    let queue = match lavapipe_queue() {
+        Some(queue) => queue,
+        None => {
+            println!("lavapipe isn't available, skipping");
+            return;
+        }
+    };
+
+    let kind = NotePipelineKind::Instanced;
+    let notes = coverage_notes();
+    let aliased = render(&queue, kind, &notes, &AntiAliasing::default());
+    assert!(
+        aliased
+            .pixels()
+            .all(|pixel| pixel[3] == 0 || pixel[3] == 255),
+        "edges were smoothed without antialiasing"
+    );
+
+    let mut settings = vec![AntiAliasing {
+        msaa_samples: 1,
+        supersampling: 2,
+    }];
+    if supported_msaa_samples(queue.device().physical_device()).contains(&4) {
+        settings.push(AntiAliasing {
+            msaa_samples: 4,
+            supersampling: 1,
+        });
+    }
+
+    for antialiasing in settings {
+        let smoothed = render(&queue, kind, &notes, &antialiasing);
+        assert_smoothed_edges(&aliased, &smoothed);
+    }
+}
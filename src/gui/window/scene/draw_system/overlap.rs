@@ -0,0 +1,89 @@
+/// Which note is visible where notes overlap
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverlapMode {
+    NewestOnTop,
+    OldestOnTop,
+    HighestTrackOnTop,
+    LowestTrackOnTop,
+    /// Overlapping notes are blended together
+    Transparent,
+}
+
+impl Default for OverlapMode {
+    fn default() -> Self {
+        OverlapMode::NewestOnTop
+    }
+}
+
+impl OverlapMode {
+    pub const ALL: [OverlapMode; 5] = [
+        OverlapMode::NewestOnTop,
+        OverlapMode::OldestOnTop,
+        OverlapMode::HighestTrackOnTop,
+        OverlapMode::LowestTrackOnTop,
+        OverlapMode::Transparent,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            OverlapMode::NewestOnTop => "Newest On Top",
+            OverlapMode::OldestOnTop => "Oldest On Top",
+            OverlapMode::HighestTrackOnTop => "Highest Track On Top",
+            OverlapMode::LowestTrackOnTop => "Lowest Track On Top",
+            OverlapMode::Transparent => "Transparent",
+        }
+    }
+
+    /// The value of the `overlap_mode` push constant in the note shaders
+    pub fn as_u32(&self) -> u32 {
+        match self {
+            OverlapMode::NewestOnTop => 0,
+            OverlapMode::OldestOnTop => 1,
+            OverlapMode::HighestTrackOnTop => 2,
+            OverlapMode::LowestTrackOnTop => 3,
+            OverlapMode::Transparent => 4,
+        }
+    }
+}
+
+/// How overlapping notes are drawn
+#[derive(Debug, Clone, PartialEq)]
+pub struct NoteOverlap {
+    pub mode: OverlapMode,
+    /// The opacity of the notes in the transparent mode
+    pub opacity: f32,
+    /// Whether notes on black keys are drawn over the notes on white keys,
+    /// or the other way around
+    pub black_over_white: bool,
+}
+
+impl Default for NoteOverlap {
+    fn default() -> Self {
+        NoteOverlap {
+            mode: OverlapMode::default(),
+            opacity: 0.5,
+            black_over_white: true,
+        }
+    }
+}
+
+impl NoteOverlap {
+    /// The alpha the notes are drawn with
+    pub fn alpha(&self) -> f32 {
+        match self.mode {
+            OverlapMode::Transparent => self.opacity,
+            _ => 1.0,
+        }
+    }
+
+    /// The order the black and white key notes are drawn in, as values of
+    /// "is black". Only matters in the transparent mode, where the notes drawn
+    /// last end up on top, the other modes use the depth.
+    pub fn key_draw_order(&self) -> [bool; 2] {
+        if self.mode == OverlapMode::Transparent && self.black_over_white {
+            [false, true]
+        } else {
+            [true, false]
+        }
+    }
+}
@@ -2,40 +2,91 @@
 #![feature(generic_associated_types)]
 #![feature(generators)]
 
+mod args;
 mod audio_playback;
 mod gui;
 mod midi;
 mod renderer;
 mod scenes;
+mod settings_file;
 
+use args::Args;
 use egui_winit_vulkano::Gui;
-use gui::{window::GuiWasabiWindow, GuiRenderer, GuiState};
-use renderer::Renderer;
-use vulkano::swapchain::PresentMode;
+use gui::{render_pass::GuiRenderPass, window::GuiWasabiWindow, GuiRenderer, GuiState};
+use renderer::{
+    gpu::print_vulkan_info,
+    pacing::FramePacer,
+    screenshot::{save_screenshot, ImageReadback},
+    swapchain::FrameError,
+    window_mode::{monitor_names, WindowGeometry},
+    Renderer,
+};
+use settings_file::SettingsFile;
+use vulkano::{swapchain::PresentMode, sync::GpuFuture};
 use winit::{
     event::{Event, WindowEvent},
     event_loop::{ControlFlow, EventLoop},
 };
 
+/// The rate the event loop runs at while the window is minimized and nothing is drawn
+const MINIMIZED_FPS: u32 = 10;
+
+/// The renderer state the GUI creates its GPU objects from
+fn gui_renderer<'a>(renderer: &Renderer, gui: &'a mut Gui) -> GuiRenderer<'a> {
+    GuiRenderer {
+        gui,
+        device: renderer.device(),
+        queue: renderer.queue(),
+        format: renderer.format(),
+        color_space: renderer.color_space(),
+        present_modes: renderer.present_modes(),
+        gpus: renderer.gpus(),
+        gpu: renderer.gpu().clone(),
+        monitors: monitor_names(renderer.window()),
+    }
+}
+
 pub fn main() {
+    let args = Args::parse();
+
+    if args.vulkan_info {
+        print_vulkan_info(&Renderer::create_instance());
+        return;
+    }
+
+    let settings = SettingsFile::load();
+
+    // The command line option overrides the GPU picked in the settings
+    let gpu = args.gpu.or_else(|| settings.get::<String>("gpu"));
+    let hdr = settings.get("hdr").unwrap_or(false);
+
     // Winit event loop
     let event_loop = EventLoop::new();
 
     // Create renderer for our scene & ui
-    let window_size = [1280, 720];
-    let mut renderer = Renderer::new(&event_loop, window_size, PresentMode::Fifo, "Wasabi");
+    let mut window_geometry = WindowGeometry::new([1280, 720]).load(&settings);
+    let mut renderer = Renderer::new(
+        &event_loop,
+        &window_geometry,
+        PresentMode::Fifo,
+        gpu.as_deref(),
+        hdr,
+        "Wasabi",
+    );
 
     // Vulkano & Winit & egui integration
-    let mut gui = Gui::new(renderer.surface(), renderer.queue(), false);
+    let mut gui_render_pass = GuiRenderPass::new(renderer.queue(), renderer.format());
+    let mut gui = Gui::new_with_subpass(
+        renderer.surface(),
+        renderer.queue(),
+        gui_render_pass.subpass(),
+    );
 
-    let mut gui_render_data = GuiRenderer {
-        gui: &mut gui,
-        device: renderer.device(),
-        queue: renderer.queue(),
-        format: renderer.format(),
-    };
+    let mut gui_state = GuiWasabiWindow::new(&mut gui_renderer(&renderer, &mut gui));
 
-    let mut gui_state = GuiWasabiWindow::new(&mut gui_render_data);
+    let mut present_modes = renderer.present_modes();
+    let mut frame_pacer = FramePacer::new();
+    let mut applied_fullscreen = None;
 
     event_loop.run(move |event, _, control_flow| {
         // Update Egui integration so the UI works!
@@ -47,18 +98,29 @@ pub fn main() {
                 match event {
                     WindowEvent::Resized(_) => {
                         renderer.resize();
+                        if renderer.window().fullscreen().is_none() {
+                            window_geometry.update_from_window(renderer.window());
+                        }
+                    }
+                    WindowEvent::Moved(_) => {
+                        if renderer.window().fullscreen().is_none() {
+                            window_geometry.update_from_window(renderer.window());
+                        }
                     }
                     WindowEvent::ScaleFactorChanged { .. } => {
                         renderer.resize();
                     }
                     WindowEvent::CloseRequested => {
+                        SettingsFile::update(|settings| window_geometry.save(settings));
                         *control_flow = ControlFlow::Exit;
                     }
                     _ => (),
                 }
             }
             Event::RedrawRequested(window_id) if window_id == window_id => {
-                renderer.render(|frame, future| {
+                let queue = renderer.queue();
+                let mut window_screenshot = None;
+                let result = renderer.render(|frame, future| {
                     // Generate egui layouts
                     gui.immediate_ui(|mut gui| {
                         let mut state = GuiState {
@@ -69,10 +131,61 @@ pub fn main() {
                     });
 
                     // Render the layouts
-                    gui.draw_on_image(future, frame.image.clone())
+                    let future = gui_render_pass.draw(&mut gui, future, frame.image.clone());
+
+                    // Copy the finished window image before it is presented
+                    if gui_state.take_window_screenshot_request() {
+                        if let Some((readback, command_buffer)) =
+                            ImageReadback::new(&queue, frame.image.image().clone())
+                        {
+                            window_screenshot = Some(readback);
+                            return future
+                                .then_execute(queue.clone(), command_buffer)
+                                .unwrap()
+                                .boxed();
+                        }
+                    }
+                    future
                 });
+
+                if let (Ok(()), Some(readback)) = (&result, window_screenshot) {
+                    // Presenting waits for the frame, so the copy has finished
+                    if let Some(image) = readback.read() {
+                        save_screenshot(&image, gui_state.song_name());
+                    }
+                }
+
+                // Playback keeps running on its own threads, only the objects
+                // created from the lost device are replaced. A failed rebuild
+                // is retried on a later frame.
+                if result == Err(FrameError::DeviceLost) && renderer.rebuild().is_ok() {
+                    gui_render_pass = GuiRenderPass::new(renderer.queue(), renderer.format());
+                    gui = Gui::new_with_subpass(
+                        renderer.surface(),
+                        renderer.queue(),
+                        gui_render_pass.subpass(),
+                    );
+                    gui_state.rebuild_renderer(&mut gui_renderer(&renderer, &mut gui));
+                    present_modes = renderer.present_modes();
+                    applied_fullscreen = None;
+                }
+
+                let frame_settings = gui_state.frame_settings();
+                renderer.set_present_mode(frame_settings.effective_present_mode(&present_modes));
+
+                let fullscreen = gui_state.fullscreen_target();
+                if fullscreen != applied_fullscreen {
+                    renderer.set_fullscreen(fullscreen.as_ref());
+                    applied_fullscreen = fullscreen;
+                }
             }
             Event::MainEventsCleared => {
+                let fps_cap = if renderer.is_minimized() {
+                    Some(MINIMIZED_FPS)
+                } else {
+                    gui_state.frame_settings().effective_fps_cap()
+                };
+                frame_pacer.wait(fps_cap);
                 renderer.window().request_redraw();
             }
             _ => (),
@@ -0,0 +1,344 @@
+use std::{collections::VecDeque, ops::RangeInclusive, time::Instant};
+
+use crossbeam_channel::Receiver;
+use midir::{Ignore, MidiInput, MidiInputConnection};
+
+use crate::audio_playback::SimpleTemporaryPlayer;
+
+use self::view::LiveCurrentNoteViews;
+
+use super::{
+    shared::{timer::TimeKeeper, tuning::TuningState},
+    MIDIColor, MIDIFile, MIDIFileBase, MIDIFileStats, MIDIViewRange,
+};
+
+pub mod view;
+
+const CLIENT_NAME: &str = "Wasabi";
+const VIRTUAL_PORT_NAME: &str = "Wasabi Live Input";
+
+/// A note that was received from the live input. Notes that are still held
+/// have no end time yet.
+pub struct LiveNote {
+    pub start: f64,
+    pub end: Option<f64>,
+    pub channel: u8,
+    pub velocity: u8,
+    pub pitch_offset: f32,
+}
+
+struct LiveNoteEvent {
+    received: Instant,
+    key: u8,
+    channel: u8,
+    velocity: u8,
+    pitch_offset: f32,
+    on: bool,
+}
+
+/// Turns the raw input messages into short messages for the synth and note
+/// events, following the state the messages depend on
+#[derive(Default)]
+struct LiveInputParser {
+    tuning: TuningState,
+    /// The status of the last channel message, for messages that leave it out
+    running_status: Option<u8>,
+}
+
+impl LiveInputParser {
+    /// Returns the packed short message to play, and the note event if it
+    /// starts or ends a note. `None` for messages that aren't played.
+    fn parse(&mut self, message: &[u8]) -> Option<(u32, Option<LiveNoteEvent>)> {
+        let head = *message.first()?;
+        if head == 0xF0 {
+            // SysEx isn't routed, but MTS messages retune the notes
+            self.running_status = None;
+            self.tuning.system_exclusive(message);
+            return None;
+        }
+
+        let (head, data) = match head {
+            0x80..=0xEF => {
+                self.running_status = Some(head);
+                (head, &message[1..])
+            }
+            // Real time messages don't cancel the running status, other
+            // system messages do
+            0xF8..=0xFF => return None,
+            0xF1..=0xF7 => {
+                self.running_status = None;
+                return None;
+            }
+            _ => (self.running_status?, message),
+        };
+        if data.len() > 2 {
+            return None;
+        }
+
+        let mut packed = head as u32;
+        for (i, byte) in data.iter().enumerate() {
+            packed |= (*byte as u32) << ((i + 1) * 8);
+        }
+
+        let channel = head & 0x0F;
+        match (head & 0xF0, data.get(0), data.get(1)) {
+            (0xB0, Some(&controller), Some(&value)) => {
+                self.tuning.control_change(channel, controller, value);
+            }
+            (0xE0, Some(&low), Some(&high)) => {
+                let value = ((high as i32) << 7 | low as i32) - 8192;
+                self.tuning.pitch_bend(channel, value);
+            }
+            _ => {}
+        }
+
+        let event = match (head & 0xF0, data.get(0), data.get(1)) {
+            (0x90, Some(&key), Some(&velocity)) if velocity > 0 => Some((key, velocity, true)),
+            (0x90, Some(&key), _) | (0x80, Some(&key), _) => Some((key, 0, false)),
+            _ => None,
+        };
+
+        let event = event.map(|(key, velocity, on)| LiveNoteEvent {
+            received: Instant::now(),
+            key,
+            channel,
+            velocity,
+            pitch_offset: self.tuning.pitch_offset(channel, key),
+            on,
+        });
+
+        Some((packed, event))
+    }
+}
+
+/// Data owned by the midir callback thread
+struct LiveInputData {
+    player: SimpleTemporaryPlayer,
+    sender: crossbeam_channel::Sender<LiveNoteEvent>,
+    parser: LiveInputParser,
+}
+
+fn handle_input_message(message: &[u8], data: &mut LiveInputData) {
+    if let Some((packed, event)) = data.parser.parse(message) {
+        data.player.push_event(packed);
+
+        if let Some(event) = event {
+            // The receiver only disappears when the file is dropped
+            data.sender.send(event).ok();
+        }
+    }
+}
+
+/// The received notes of each key, from the oldest to the newest
+struct LiveNoteColumns {
+    columns: Vec<VecDeque<LiveNote>>,
+    note_count: usize,
+    used_key_range: Option<RangeInclusive<usize>>,
+}
+
+impl LiveNoteColumns {
+    fn new() -> Self {
+        LiveNoteColumns {
+            columns: (0..256).map(|_| VecDeque::new()).collect(),
+            note_count: 0,
+            used_key_range: None,
+        }
+    }
+
+    /// Starts a note, or ends the oldest held note of the key on the same channel
+    fn apply(&mut self, event: &LiveNoteEvent, time: f64) {
+        let column = &mut self.columns[event.key as usize];
+
+        if event.on {
+            column.push_back(LiveNote {
+                start: time,
+                end: None,
+                channel: event.channel,
+                velocity: event.velocity,
+                pitch_offset: event.pitch_offset,
+            });
+            self.note_count += 1;
+
+            let key = event.key as usize;
+            self.used_key_range = match self.used_key_range.take() {
+                Some(range) => Some(key.min(*range.start())..=key.max(*range.end())),
+                None => Some(key..=key),
+            };
+        } else {
+            let note = column
+                .iter_mut()
+                .find(|note| note.end.is_none() && note.channel == event.channel);
+            if let Some(note) = note {
+                note.end = Some(time);
+            }
+        }
+    }
+
+    /// Drops the notes that ended before the cutoff
+    fn trim(&mut self, cutoff: f64) {
+        for column in self.columns.iter_mut() {
+            column.retain(|note| note.end.map_or(true, |end| end >= cutoff));
+        }
+    }
+}
+
+pub struct LiveMIDIFile {
+    _connection: MidiInputConnection<LiveInputData>,
+    receiver: Receiver<LiveNoteEvent>,
+    notes: LiveNoteColumns,
+    channel_colors: Vec<MIDIColor>,
+    view_range: MIDIViewRange,
+    timer: TimeKeeper,
+}
+
+impl LiveMIDIFile {
+    /// Lists the names of the MIDI input ports that can be opened with
+    /// [`LiveMIDIFile::open_port`]
+    pub fn list_ports() -> Vec<String> {
+        let input = match MidiInput::new(CLIENT_NAME) {
+            Ok(input) => input,
+            Err(_) => return Vec::new(),
+        };
+
+        input
+            .ports()
+            .iter()
+            .map(|port| input.port_name(port).unwrap_or_else(|_| "Unknown".to_string()))
+            .collect()
+    }
+
+    pub fn open_port(index: usize, player: SimpleTemporaryPlayer) -> Result<Self, String> {
+        let mut input = MidiInput::new(CLIENT_NAME).map_err(|e| e.to_string())?;
+        input.ignore(Ignore::TimeAndActiveSense);
+
+        let ports = input.ports();
+        let port = ports.get(index).ok_or("MIDI input port no longer exists")?;
+
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        let data = LiveInputData {
+            player,
+            sender,
+            parser: LiveInputParser::default(),
+        };
+
+        let connection = input
+            .connect(
+                port,
+                VIRTUAL_PORT_NAME,
+                |_, message, data| handle_input_message(message, data),
+                data,
+            )
+            .map_err(|e| e.to_string())?;
+
+        Ok(Self::new(connection, receiver))
+    }
+
+    /// Creates a virtual port that other applications can connect to
+    #[cfg(unix)]
+    pub fn open_virtual(player: SimpleTemporaryPlayer) -> Result<Self, String> {
+        use midir::os::unix::VirtualInput;
+
+        let mut input = MidiInput::new(CLIENT_NAME).map_err(|e| e.to_string())?;
+        input.ignore(Ignore::TimeAndActiveSense);
+
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        let data = LiveInputData {
+            player,
+            sender,
+            parser: LiveInputParser::default(),
+        };
+
+        let connection = input
+            .create_virtual(
+                VIRTUAL_PORT_NAME,
+                |_, message, data| handle_input_message(message, data),
+                data,
+            )
+            .map_err(|e| e.to_string())?;
+
+        Ok(Self::new(connection, receiver))
+    }
+
+    fn new(
+        connection: MidiInputConnection<LiveInputData>,
+        receiver: Receiver<LiveNoteEvent>,
+    ) -> Self {
+        let mut timer = TimeKeeper::new();
+        timer.play();
+
+        LiveMIDIFile {
+            _connection: connection,
+            receiver,
+            notes: LiveNoteColumns::new(),
+            channel_colors: MIDIColor::new_vec_for_tracks(1),
+            view_range: MIDIViewRange::new(0.0, 0.0),
+            timer,
+        }
+    }
+
+    /// Applies all the events received since the last frame
+    fn receive_events(&mut self) {
+        let now = self.timer.get_time().as_secs_f64();
+
+        for event in self.receiver.try_iter() {
+            let time = (now - event.received.elapsed().as_secs_f64()).max(0.0);
+            self.notes.apply(&event, time);
+        }
+    }
+}
+
+macro_rules! impl_file_base {
+    ($for_type:ty) => {
+        impl MIDIFileBase for $for_type {
+            fn midi_length(&self) -> Option<f64> {
+                None
+            }
+
+            fn parsed_up_to(&self) -> Option<f64> {
+                None
+            }
+
+            fn timer(&self) -> &TimeKeeper {
+                &self.timer
+            }
+
+            fn timer_mut(&mut self) -> &mut TimeKeeper {
+                &mut self.timer
+            }
+
+            fn allows_seeking_backward(&self) -> bool {
+                false
+            }
+
+            fn stats(&self) -> MIDIFileStats {
+                MIDIFileStats::new(self.notes.note_count)
+            }
+
+            fn used_key_range(&self) -> Option<RangeInclusive<usize>> {
+                self.notes.used_key_range.clone()
+            }
+        }
+    };
+}
+
+impl_file_base!(&mut LiveMIDIFile);
+impl_file_base!(LiveMIDIFile);
+
+impl MIDIFile for &mut LiveMIDIFile {
+    type ColumnsViews<'a> = LiveCurrentNoteViews<'a> where Self: 'a;
+
+    fn get_current_column_views<'a>(&'a mut self, range: &mut f64) -> Self::ColumnsViews<'a> {
+        self.receive_events();
+
+        // Notes rise up from the keyboard, so the view covers the recent past
+        let time = self.timer.get_time().as_secs_f64();
+        self.view_range = MIDIViewRange::new(time - *range, time);
+
+        self.notes.trim(self.view_range.start);
+
+        LiveCurrentNoteViews::new(&self.notes.columns, &self.channel_colors, self.view_range)
+    }
+}
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,175 @@
+use std::time::Instant;
+
+use crate::midi::{MIDIColor, MIDINoteColumnView, MIDINoteViews, MIDIViewRange};
+
+use super::{view::LiveCurrentNoteViews, LiveInputParser, LiveNoteColumns, LiveNoteEvent};
+
+fn note_event(key: u8, channel: u8, on: bool) -> LiveNoteEvent {
+    LiveNoteEvent {
+        received: Instant::now(),
+        key,
+        channel,
+        velocity: if on { 100 } else { 0 },
+        pitch_offset: 0.0,
+        on,
+    }
+}
+
+/// The key, channel, velocity and whether it starts a note of a parsed note event
+fn parse_note(parser: &mut LiveInputParser, message: &[u8]) -> Option<(u8, u8, u8, bool)> {
+    let (_, event) = parser.parse(message)?;
+    event.map(|event| (event.key, event.channel, event.velocity, event.on))
+}
+
+#[test]
+fn note_on_and_off() {
+    let mut parser = LiveInputParser::default();
+
+    let (packed, _) = parser.parse(&[0x93, 60, 100]).unwrap();
+    assert_eq!(packed, 0x643C93);
+
+    assert_eq!(
+        parse_note(&mut parser, &[0x90, 60, 100]),
+        Some((60, 0, 100, true))
+    );
+    assert_eq!(
+        parse_note(&mut parser, &[0x80, 60, 64]),
+        Some((60, 0, 0, false))
+    );
+    // A note on with velocity 0 ends the note
+    assert_eq!(
+        parse_note(&mut parser, &[0x91, 72, 0]),
+        Some((72, 1, 0, false))
+    );
+}
+
+#[test]
+fn other_messages_are_played_without_note_events() {
+    let mut parser = LiveInputParser::default();
+
+    let (packed, event) = parser.parse(&[0xC4, 12]).unwrap();
+    assert_eq!(packed, 0x0CC4);
+    assert!(event.is_none());
+
+    let (packed, event) = parser.parse(&[0xB0, 7, 100]).unwrap();
+    assert_eq!(packed, 0x6407B0);
+    assert!(event.is_none());
+
+    assert!(parser.parse(&[]).is_none());
+    assert!(parser.parse(&[0x90, 60, 100, 0]).is_none());
+}
+
+#[test]
+fn running_status() {
+    let mut parser = LiveInputParser::default();
+
+    // Data without any status before it is dropped
+    assert!(parser.parse(&[60, 100]).is_none());
+
+    assert_eq!(
+        parse_note(&mut parser, &[0x92, 60, 100]),
+        Some((60, 2, 100, true))
+    );
+    let (packed, _) = parser.parse(&[61, 90]).unwrap();
+    assert_eq!(packed, 0x5A3D92);
+    assert_eq!(parse_note(&mut parser, &[60, 0]), Some((60, 2, 0, false)));
+
+    // Real time messages keep the running status
+    assert!(parser.parse(&[0xF8]).is_none());
+    assert_eq!(parse_note(&mut parser, &[61, 0]), Some((61, 2, 0, false)));
+
+    // Other system messages cancel it
+    assert!(parser.parse(&[0xF2, 0, 0]).is_none());
+    assert!(parser.parse(&[62, 100]).is_none());
+
+    assert_eq!(
+        parse_note(&mut parser, &[0x85, 62, 0]),
+        Some((62, 5, 0, false))
+    );
+    assert!(parser
+        .parse(&[0xF0, 0x7E, 0x7F, 0x09, 0x01, 0xF7])
+        .is_none());
+    assert!(parser.parse(&[62, 0]).is_none());
+}
+
+#[test]
+fn notes_start_at_their_pitch_offset() {
+    let mut parser = LiveInputParser::default();
+
+    // A quarter of the way up on the default 2 semitone range
+    parser.parse(&[0xE3, 0x00, 0x50]).unwrap();
+    let (_, event) = parser.parse(&[0x93, 60, 100]).unwrap();
+    assert_eq!(event.unwrap().pitch_offset, 0.5);
+
+    let (_, event) = parser.parse(&[0x90, 60, 100]).unwrap();
+    assert_eq!(event.unwrap().pitch_offset, 0.0);
+}
+
+#[test]
+fn notes_pair_per_key_and_channel() {
+    let mut notes = LiveNoteColumns::new();
+
+    notes.apply(&note_event(60, 0, true), 1.0);
+    notes.apply(&note_event(60, 1, true), 2.0);
+    notes.apply(&note_event(60, 1, false), 3.0);
+    // No held note on that key and channel
+    notes.apply(&note_event(61, 0, false), 3.5);
+    notes.apply(&note_event(60, 0, true), 4.0);
+    // Ends the oldest held note on the channel
+    notes.apply(&note_event(60, 0, false), 5.0);
+
+    let column: Vec<_> = notes.columns[60]
+        .iter()
+        .map(|note| (note.channel, note.start, note.end))
+        .collect();
+    assert_eq!(
+        column,
+        [(0, 1.0, Some(5.0)), (1, 2.0, Some(3.0)), (0, 4.0, None)]
+    );
+    assert!(notes.columns[61].is_empty());
+
+    assert_eq!(notes.note_count, 3);
+    assert_eq!(notes.used_key_range, Some(60..=60));
+    notes.apply(&note_event(40, 0, true), 6.0);
+    assert_eq!(notes.used_key_range, Some(40..=60));
+}
+
+#[test]
+fn notes_out_of_view_are_trimmed() {
+    let mut notes = LiveNoteColumns::new();
+
+    notes.apply(&note_event(60, 0, true), 1.0);
+    notes.apply(&note_event(60, 0, false), 2.0);
+    notes.apply(&note_event(60, 0, true), 3.0);
+    notes.apply(&note_event(60, 0, false), 6.0);
+    notes.apply(&note_event(72, 0, true), 0.5);
+
+    notes.trim(4.0);
+
+    let starts: Vec<_> = notes.columns[60].iter().map(|note| note.start).collect();
+    assert_eq!(starts, [3.0]);
+    // Held notes stay however old they are
+    assert_eq!(notes.columns[72].len(), 1);
+    // The stats count every note received
+    assert_eq!(notes.note_count, 3);
+}
+
+#[test]
+fn notes_are_displaced_by_their_age() {
+    let mut notes = LiveNoteColumns::new();
+
+    notes.apply(&note_event(60, 0, true), 4.0);
+    notes.apply(&note_event(60, 0, false), 6.0);
+    notes.apply(&note_event(60, 1, true), 8.0);
+
+    let colors = MIDIColor::new_vec_for_tracks(1);
+    let views = LiveCurrentNoteViews::new(&notes.columns, &colors, MIDIViewRange::new(0.0, 10.0));
+    let column = views.get_column(60);
+
+    // The newest note first, the held one sits on the keyboard
+    let displaced: Vec<_> = column
+        .iterate_displaced_notes()
+        .map(|note| (note.start, note.len, note.track_chan))
+        .collect();
+    assert_eq!(displaced, [(0.0, 2.0, 1), (4.0, 2.0, 0)]);
+}
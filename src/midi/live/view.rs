@@ -0,0 +1,69 @@
+use std::collections::VecDeque;
+
+use crate::midi::{DisplacedMIDINote, MIDIColor, MIDINoteColumnView, MIDINoteViews, MIDIViewRange};
+
+use super::LiveNote;
+
+pub struct LiveCurrentNoteViews<'a> {
+    columns: &'a [VecDeque<LiveNote>],
+    channel_colors: &'a [MIDIColor],
+    view_range: MIDIViewRange,
+}
+
+impl<'a> LiveCurrentNoteViews<'a> {
+    pub fn new(
+        columns: &'a [VecDeque<LiveNote>],
+        channel_colors: &'a [MIDIColor],
+        view_range: MIDIViewRange,
+    ) -> Self {
+        LiveCurrentNoteViews {
+            columns,
+            channel_colors,
+            view_range,
+        }
+    }
+}
+
+pub struct LiveNoteColumnView<'a> {
+    column: &'a VecDeque<LiveNote>,
+    channel_colors: &'a [MIDIColor],
+    view_range: MIDIViewRange,
+}
+
+impl<'a> MIDINoteViews for LiveCurrentNoteViews<'a> {
+    type View<'b> = LiveNoteColumnView<'b> where Self: 'a + 'b;
+
+    fn get_column<'b>(&'b self, key: usize) -> Self::View<'b> {
+        LiveNoteColumnView {
+            column: &self.columns[key],
+            channel_colors: self.channel_colors,
+            view_range: self.view_range,
+        }
+    }
+
+    fn range<'b>(&'b self) -> MIDIViewRange {
+        self.view_range
+    }
+}
+
+impl<'a> MIDINoteColumnView for LiveNoteColumnView<'a> {
+    type Iter<'b> = impl 'b + ExactSizeIterator<Item = DisplacedMIDINote> where Self: 'b;
+
+    fn iterate_displaced_notes<'b>(&'b self) -> Self::Iter<'b> {
+        let now = self.view_range.end;
+
+        // Displace the notes by their age, so held notes sit on the keyboard
+        // and released notes float upwards
+        self.column.iter().rev().map(move |note| {
+            let end = note.end.unwrap_or(now);
+            DisplacedMIDINote {
+                start: (now - end) as f32,
+                len: (end - note.start) as f32,
+                color: self.channel_colors[note.channel as usize],
+                track_chan: note.channel as u32,
+                velocity: note.velocity,
+                pitch_offset: note.pitch_offset,
+            }
+        })
+    }
+}
@@ -1,9 +1,13 @@
+mod live;
 mod ram;
 mod shared;
+use std::ops::RangeInclusive;
+
 use enum_dispatch::enum_dispatch;
 use palette::convert::FromColorUnclamped;
 use rand::Rng;
 
+pub use live::LiveMIDIFile;
 pub use ram::{InRamMIDIFile, MIDIFileStats};
 
 use self::shared::timer::TimeKeeper;
@@ -98,6 +102,9 @@ pub trait MIDIFileBase {
 
     fn stats(&self) -> MIDIFileStats;
 
+    /// The lowest and highest keys that have notes, if there are any
+    fn used_key_range(&self) -> Option<RangeInclusive<usize>>;
+
     fn allows_seeking_backward(&self) -> bool;
 }
 
@@ -134,9 +141,16 @@ pub struct DisplacedMIDINote {
     pub start: f32,
     pub len: f32,
     pub color: MIDIColor,
+    /// The note's track and channel as `track * 16 + channel`
+    pub track_chan: u32,
+    pub velocity: u8,
+    /// How far the note plays from its key's pitch, in semitones, from pitch
+    /// bend and MTS tuning when it started
+    pub pitch_offset: f32,
 }
 
 #[enum_dispatch(MIDIFileBase)]
 pub enum MIDIFileUnion {
     InRam(ram::InRamMIDIFile),
+    Live(live::LiveMIDIFile),
 }
@@ -1,3 +1,8 @@
+use std::{
+    ops::RangeInclusive,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
 use self::view::{InRamCurrentNoteViews, InRamNoteViewData};
 
 use super::{shared::timer::TimeKeeper, MIDIFile, MIDIFileBase, MIDIViewRange};
@@ -27,14 +32,38 @@ impl MIDIFileStats {
     }
 }
 
+/// Gives every loaded file a unique id, so that data derived from a file (like
+/// the notes uploaded to the GPU) can tell when the file was replaced
+static NEXT_FILE_ID: AtomicU64 = AtomicU64::new(0);
+
 pub struct InRamMIDIFile {
+    id: u64,
     view_data: InRamNoteViewData,
     timer: TimeKeeper,
     length: f64,
     note_count: usize,
 }
 
-impl InRamMIDIFile {}
+impl InRamMIDIFile {
+    fn next_id() -> u64 {
+        NEXT_FILE_ID.fetch_add(1, Ordering::Relaxed)
+    }
+
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// Shifts the view range to the current time, like
+    /// [`MIDIFile::get_current_column_views`], and gives direct access to the
+    /// note data for renderers that keep their own copy of the notes.
+    pub fn current_view_data(&mut self, range: f64) -> &InRamNoteViewData {
+        let time = self.timer.get_time().as_secs_f64();
+        let new_range = MIDIViewRange::new(time, time + range);
+        self.view_data.shift_view_range(new_range);
+
+        &self.view_data
+    }
+}
 
 macro_rules! impl_file_base {
     ($for_type:ty) => {
@@ -62,6 +91,10 @@ macro_rules! impl_file_base {
             fn stats(&self) -> MIDIFileStats {
                 MIDIFileStats::new(self.note_count)
             }
+
+            fn used_key_range(&self) -> Option<RangeInclusive<usize>> {
+                self.view_data.used_key_range()
+            }
         }
     };
 }
@@ -2,27 +2,51 @@ pub struct InRamNoteBlock {
     pub start: f64,
     pub max_length: f32,
     pub notes: Vec<BasicMIDINote>,
+    /// The pitch offset of each note in semitones, `None` if none of the
+    /// notes are tuned so that untuned files don't use any extra memory
+    pitch_offsets: Option<Box<[f32]>>,
 }
 
 #[derive(Debug, Clone)]
 pub struct BasicMIDINote {
     pub len: f32,
-    pub track_chan: u32,
+    /// The track/channel in the low 24 bits and the velocity in the high 8,
+    /// so that storing the velocity doesn't make the notes bigger
+    track_chan_velocity: u32,
+}
+
+impl BasicMIDINote {
+    pub fn track_chan(&self) -> u32 {
+        self.track_chan_velocity & 0xFF_FFFF
+    }
+
+    pub fn velocity(&self) -> u8 {
+        (self.track_chan_velocity >> 24) as u8
+    }
 }
 
 impl InRamNoteBlock {
-    /// Creates a new block from an iterator of Track/Channel values.
+    /// Creates a new block from an iterator of Track/Channel, velocity and
+    /// pitch offset values.
     /// This assumes that the lengths will be added in the future.
     pub fn new_from_trackchans(
         time: f64,
-        track_chans_iter: impl ExactSizeIterator<Item = u32>,
+        track_chans_iter: impl ExactSizeIterator<Item = (u32, u8, f32)>,
     ) -> Self {
         let mut notes: Vec<BasicMIDINote> = Vec::with_capacity(track_chans_iter.len());
+        let mut pitch_offsets: Vec<f32> = Vec::new();
+
+        for (track_chan, velocity, pitch_offset) in track_chans_iter {
+            if pitch_offset != 0.0 && pitch_offsets.is_empty() {
+                pitch_offsets.resize(notes.len(), 0.0);
+            }
+            if pitch_offset != 0.0 || !pitch_offsets.is_empty() {
+                pitch_offsets.push(pitch_offset);
+            }
 
-        for track_chan in track_chans_iter {
             notes.push(BasicMIDINote {
                 len: 0.0,
-                track_chan,
+                track_chan_velocity: (track_chan & 0xFF_FFFF) | ((velocity as u32) << 24),
             });
         }
 
@@ -30,6 +54,19 @@ impl InRamNoteBlock {
             start: time,
             notes,
             max_length: 0.0,
+            pitch_offsets: if pitch_offsets.is_empty() {
+                None
+            } else {
+                Some(pitch_offsets.into_boxed_slice())
+            },
+        }
+    }
+
+    /// How far a note plays from its key's pitch, in semitones
+    pub fn pitch_offset(&self, note_index: usize) -> f32 {
+        match &self.pitch_offsets {
+            Some(offsets) => offsets[note_index],
+            None => 0.0,
         }
     }
 
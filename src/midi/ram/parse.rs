@@ -6,7 +6,7 @@ use crate::{
     audio_playback::SimpleTemporaryPlayer,
     midi::{
         ram::{audio_player::InRamAudioPlayer, column::InRamNoteColumn, view::InRamNoteViewData},
-        shared::{audio::CompressedAudio, timer::TimeKeeper},
+        shared::{audio::CompressedAudio, timer::TimeKeeper, tuning::TuningState},
     },
 };
 
@@ -19,7 +19,7 @@ struct UnendedNote {
 
 struct Key {
     column: Vec<InRamNoteBlock>,
-    block_builder: Vec<u32>,
+    block_builder: Vec<(u32, u8, f32)>,
     unended_notes: HashMap<u32, VecDeque<UnendedNote>>,
 }
 
@@ -32,10 +32,10 @@ impl Key {
         }
     }
 
-    fn add_note(&mut self, track_chan: u32) {
+    fn add_note(&mut self, track_chan: u32, velocity: u8, pitch_offset: f32) {
         let block_index = self.block_builder.len();
         let column_index = self.column.len();
-        self.block_builder.push(track_chan);
+        self.block_builder.push((track_chan, velocity, pitch_offset));
         let unended_queue = self
             .unended_notes
             .entry(track_chan)
@@ -108,6 +108,8 @@ impl InRamMIDIFile {
 
             let mut notes: usize = 0;
 
+            let mut tuning = TuningState::new();
+
             fn flush_keys(time: f64, keys: &mut Vec<Key>) {
                 for key in keys.iter_mut() {
                     key.flush(time);
@@ -125,13 +127,23 @@ impl InRamMIDIFile {
                     match event.as_event() {
                         Event::NoteOn(e) => {
                             let track_chan = track * 16 + e.channel as u32;
-                            keys[e.key as usize].add_note(track_chan);
+                            let pitch_offset = tuning.pitch_offset(e.channel, e.key);
+                            keys[e.key as usize].add_note(track_chan, e.velocity, pitch_offset);
                             notes += 1;
                         }
                         Event::NoteOff(e) => {
                             let track_chan = track * 16 + e.channel as u32;
                             keys[e.key as usize].end_note(track_chan, time);
                         }
+                        Event::PitchWheelChange(e) => {
+                            tuning.pitch_bend(e.channel, e.pitch as i32);
+                        }
+                        Event::ControlChange(e) => {
+                            tuning.control_change(e.channel, e.controller, e.value);
+                        }
+                        Event::SystemExclusiveMessage(e) => {
+                            tuning.system_exclusive(&e.data);
+                        }
                         _ => {}
                     }
                 }
@@ -177,6 +189,7 @@ impl InRamMIDIFile {
             .collect();
 
         InRamMIDIFile {
+            id: InRamMIDIFile::next_id(),
             view_data: InRamNoteViewData::new(columns, midi.track_count()),
             timer,
             length,
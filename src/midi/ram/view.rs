@@ -1,4 +1,4 @@
-use std::ops::Range;
+use std::ops::{Range, RangeInclusive};
 
 use gen_iter::GenIter;
 use rayon::iter::{
@@ -12,6 +12,7 @@ use super::column::InRamNoteColumn;
 pub struct InRamNoteViewData {
     columns: Vec<InRamNoteColumn>,
     column_view_data: Vec<InRamNoteColumnViewData>,
+    column_note_counts: Vec<usize>,
     default_track_colors: Vec<MIDIColor>,
     view_range: MIDIViewRange,
 }
@@ -32,9 +33,14 @@ impl InRamNoteViewData {
             .iter()
             .map(|_| InRamNoteColumnViewData::new())
             .collect();
+        let column_note_counts = columns
+            .iter()
+            .map(|column| column.blocks.iter().map(|block| block.notes.len()).sum())
+            .collect();
         InRamNoteViewData {
             columns,
             column_view_data,
+            column_note_counts,
             view_range: MIDIViewRange {
                 start: 0.0,
                 end: 0.0,
@@ -135,9 +141,102 @@ impl InRamNoteViewData {
             });
     }
 
+    pub fn used_key_range(&self) -> Option<RangeInclusive<usize>> {
+        let is_used = |column: &InRamNoteColumn| !column.blocks.is_empty();
+        let first = self.columns.iter().position(is_used)?;
+        let last = self.columns.iter().rposition(is_used)?;
+        Some(first..=last)
+    }
+
     fn allows_seeking_backward(&self) -> bool {
         false
     }
+
+    pub fn view_range(&self) -> MIDIViewRange {
+        self.view_range
+    }
+
+    pub fn column_note_count(&self, key: usize) -> usize {
+        self.column_note_counts[key]
+    }
+
+    /// All the notes of a column as `(absolute start, length, color, track_chan,
+    /// pitch offset)`, newest first, in the same order as
+    /// [`MIDINoteColumnView::iterate_displaced_notes`]
+    pub fn iter_column_notes<'a>(
+        &'a self,
+        key: usize,
+    ) -> impl 'a + Iterator<Item = (f64, f32, MIDIColor, u32, f32)> {
+        let colors = &self.default_track_colors;
+        self.columns[key].blocks.iter().rev().flat_map(move |block| {
+            block.notes.iter().enumerate().rev().map(move |(i, note)| {
+                (
+                    block.start,
+                    note.len,
+                    colors[note.track_chan() as usize],
+                    note.track_chan(),
+                    block.pitch_offset(i),
+                )
+            })
+        })
+    }
+
+    /// The newest note of a column that is playing at `time`, as `(start,
+    /// length, track_chan)`. Only the blocks in the view range are searched.
+    pub fn find_note(&self, key: usize, time: f64) -> Option<(f64, f32, u32)> {
+        let block_range = self.column_view_data[key].block_range.clone();
+        self.columns[key].blocks[block_range]
+            .iter()
+            .rev()
+            .filter(|block| block.start <= time && block.max_end() > time)
+            .flat_map(|block| block.notes.iter().rev().map(move |note| (block.start, note)))
+            .find(|(start, note)| time < start + note.len as f64)
+            .map(|(start, note)| (start, note.len, note.track_chan()))
+    }
+
+    /// The indexes of the column's notes inside the view range, in the order of
+    /// [`InRamNoteViewData::iter_column_notes`]
+    pub fn column_view_indexes(&self, key: usize) -> Range<usize> {
+        let count = self.column_note_counts[key];
+        let data = &self.column_view_data[key];
+        (count - data.notes_to_end)..(count - data.notes_to_start)
+    }
+
+    /// The notes of a column that started at or before the view start, newest
+    /// first and displaced by the view start. Only the blocks from the start
+    /// of the view range up to the view start are walked, the blocks that
+    /// start later in the view are skipped with a binary search.
+    pub fn iter_notes_at_view_start<'a>(
+        &'a self,
+        key: usize,
+    ) -> impl 'a + Iterator<Item = DisplacedMIDINote> {
+        let column = &self.columns[key];
+        let block_range = self.column_view_data[key].block_range.clone();
+        let view_start = self.view_range.start;
+        let started = column.blocks[block_range.clone()]
+            .partition_point(|block| block.start <= view_start);
+        let colors = &self.default_track_colors;
+
+        column.blocks[block_range.start..block_range.start + started]
+            .iter()
+            .rev()
+            .flat_map(move |block| {
+                let start = (block.start - view_start) as f32;
+                block
+                    .notes
+                    .iter()
+                    .enumerate()
+                    .rev()
+                    .map(move |(i, note)| DisplacedMIDINote {
+                        start,
+                        len: note.len,
+                        color: colors[note.track_chan() as usize],
+                        track_chan: note.track_chan(),
+                        velocity: note.velocity(),
+                        pitch_offset: block.pitch_offset(i),
+                    })
+            })
+    }
 }
 
 impl<'a> MIDINoteViews for InRamCurrentNoteViews<'a> {
@@ -173,11 +272,14 @@ impl<'a> MIDINoteColumnView for InRamNoteColumnView<'a> {
                 let block = &self.column.blocks[block_index];
                 let start = (block.start - self.view_range.start) as f32;
 
-                for note in block.notes.iter().rev() {
+                for (i, note) in block.notes.iter().enumerate().rev() {
                     yield DisplacedMIDINote {
                         start: start,
                         len: note.len,
-                        color: colors[note.track_chan as usize],
+                        color: colors[note.track_chan() as usize],
+                        track_chan: note.track_chan(),
+                        velocity: note.velocity(),
+                        pitch_offset: block.pitch_offset(i),
                     };
                 }
             }
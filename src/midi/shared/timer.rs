@@ -127,6 +127,18 @@ impl TimeKeeper {
         };
         self.notify_listeners(true);
     }
+
+    /// Seeks to `time` without starting the timer if it is paused
+    pub fn set_time(&mut self, time: Duration) {
+        self.current_state = match self.current_state {
+            TimerState::Paused { .. } => TimerState::Paused { time_offset: time },
+            TimerState::Running { .. } => TimerState::Running {
+                continue_time: Instant::now(),
+                time_offset: time,
+            },
+        };
+        self.notify_listeners(true);
+    }
 }
 
 pub struct TimeListener {
@@ -0,0 +1,189 @@
+/// The pitch bend range a channel starts with, in semitones
+const DEFAULT_BEND_RANGE: f32 = 2.0;
+
+/// The "no parameter selected" RPN
+const RPN_NULL: (u8, u8) = (127, 127);
+
+/// The pitch bend sensitivity RPN
+const RPN_BEND_RANGE: (u8, u8) = (0, 0);
+
+#[derive(Debug, Clone)]
+struct ChannelTuning {
+    /// The pitch wheel position, from -1 to 1
+    pitch_bend: f32,
+    /// How many semitones the pitch wheel bends at most
+    bend_range: f32,
+    /// The RPN selected with CC 101 and 100, data entry only applies to
+    /// the bend range
+    rpn: (u8, u8),
+    /// The offsets of each key in the octave from MTS scale/octave tuning,
+    /// in semitones
+    octave: [f32; 12],
+}
+
+impl Default for ChannelTuning {
+    fn default() -> Self {
+        ChannelTuning {
+            pitch_bend: 0.0,
+            bend_range: DEFAULT_BEND_RANGE,
+            rpn: RPN_NULL,
+            octave: [0.0; 12],
+        }
+    }
+}
+
+/// Follows the events that change the pitch notes play at: pitch bend, the
+/// pitch bend range RPN and the MIDI Tuning Standard SysEx messages. The
+/// offset of a note is taken when it starts, bends during the note are not
+/// followed. MTS tuning programs and banks are not told apart, a note tuning
+/// change applies to every channel.
+#[derive(Debug, Clone)]
+pub struct TuningState {
+    channels: [ChannelTuning; 16],
+    /// The offset of each key from MTS note tuning changes, in semitones
+    keys: [f32; 256],
+}
+
+impl Default for TuningState {
+    fn default() -> Self {
+        TuningState {
+            channels: Default::default(),
+            keys: [0.0; 256],
+        }
+    }
+}
+
+impl TuningState {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// How far a note on the key plays from the key's 12-EDO pitch, in semitones
+    pub fn pitch_offset(&self, channel: u8, key: u8) -> f32 {
+        let channel = &self.channels[channel as usize & 0x0F];
+        self.keys[key as usize]
+            + channel.octave[key as usize % 12]
+            + channel.pitch_bend * channel.bend_range
+    }
+
+    /// Applies a pitch wheel change, with `value` from -8192 to 8191
+    pub fn pitch_bend(&mut self, channel: u8, value: i32) {
+        let value = value.clamp(-8192, 8191) as f32;
+        self.channels[channel as usize & 0x0F].pitch_bend = value / 8192.0;
+    }
+
+    pub fn control_change(&mut self, channel: u8, controller: u8, value: u8) {
+        let channel = &mut self.channels[channel as usize & 0x0F];
+        match controller {
+            101 => channel.rpn.0 = value,
+            100 => channel.rpn.1 = value,
+            // Selecting an NRPN deselects the RPN
+            98 | 99 => channel.rpn = RPN_NULL,
+            6 if channel.rpn == RPN_BEND_RANGE => {
+                let cents = channel.bend_range.fract();
+                channel.bend_range = value as f32 + cents;
+            }
+            38 if channel.rpn == RPN_BEND_RANGE => {
+                let semitones = channel.bend_range.trunc();
+                channel.bend_range = semitones + value.min(99) as f32 / 100.0;
+            }
+            // Reset all controllers
+            121 => {
+                channel.pitch_bend = 0.0;
+                channel.rpn = RPN_NULL;
+            }
+            _ => {}
+        }
+    }
+
+    /// Applies a SysEx message if it's an MTS message. The data may or may
+    /// not include the leading 0xF0 and the trailing 0xF7.
+    pub fn system_exclusive(&mut self, data: &[u8]) {
+        let data = data.strip_prefix(&[0xF0]).unwrap_or(data);
+        let data = data.strip_suffix(&[0xF7]).unwrap_or(data);
+
+        // Universal real time or non real time, any device, MIDI tuning
+        let (kind, body) = match data {
+            [0x7E | 0x7F, _, 0x08, kind, body @ ..] => (*kind, body),
+            _ => return,
+        };
+
+        match (data[0], kind) {
+            // Bulk tuning dump: program, 16 name bytes, 128 frequencies
+            (0x7E, 0x01) => {
+                if let Some(frequencies) = body.get(17..17 + 128 * 3) {
+                    for (key, frequency) in frequencies.chunks_exact(3).enumerate() {
+                        self.tune_key(key as u8, frequency);
+                    }
+                }
+            }
+            // Single note tuning change: program, count, then key and frequency
+            (0x7F, 0x02) => self.tune_keys(body.get(1..)),
+            // The same, with a bank before the program
+            (0x7E, 0x07) => self.tune_keys(body.get(2..)),
+            // Scale/octave tuning, 1 byte form: -64 to 63 cents
+            (_, 0x08) => {
+                if let Some(values) = body.get(3..15) {
+                    let mut octave = [0.0; 12];
+                    for (offset, &value) in octave.iter_mut().zip(values) {
+                        *offset = (value as f32 - 64.0) / 100.0;
+                    }
+                    self.tune_octave(&body[..3], octave);
+                }
+            }
+            // Scale/octave tuning, 2 byte form: -100 to 100 cents
+            (_, 0x09) => {
+                if let Some(values) = body.get(3..27) {
+                    let mut octave = [0.0; 12];
+                    for (offset, value) in octave.iter_mut().zip(values.chunks_exact(2)) {
+                        let value = ((value[0] as u32) << 7) | value[1] as u32;
+                        *offset = (value as f32 - 8192.0) / 8192.0;
+                    }
+                    self.tune_octave(&body[..3], octave);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Applies `count, (key, frequency)*` note tuning data
+    fn tune_keys(&mut self, data: Option<&[u8]>) {
+        let (count, changes) = match data {
+            Some([count, changes @ ..]) => (*count as usize, changes),
+            _ => return,
+        };
+
+        for change in changes.chunks_exact(4).take(count) {
+            self.tune_key(change[0], &change[1..]);
+        }
+    }
+
+    /// Sets a key to an MTS frequency: a semitone, and a 14 bit fraction of
+    /// a semitone above it. `7F 7F 7F` leaves the key unchanged.
+    fn tune_key(&mut self, key: u8, frequency: &[u8]) {
+        if key > 127 || frequency == [0x7F, 0x7F, 0x7F] {
+            return;
+        }
+
+        let fraction = (((frequency[1] as u32) << 7) | frequency[2] as u32) as f32 / 16384.0;
+        let pitch = frequency[0] as f32 + fraction;
+        self.keys[key as usize] = pitch - key as f32;
+    }
+
+    /// Sets the octave tuning of the channels in the 3 byte channel mask
+    fn tune_octave(&mut self, mask: &[u8], octave: [f32; 12]) {
+        // The first byte holds channels 15 and 16, the last one channels 1 to 7
+        let mask = ((mask[0] as u32 & 0x03) << 14)
+            | ((mask[1] as u32 & 0x7F) << 7)
+            | (mask[2] as u32 & 0x7F);
+
+        for (i, channel) in self.channels.iter_mut().enumerate() {
+            if mask & (1 << i) != 0 {
+                channel.octave = octave;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
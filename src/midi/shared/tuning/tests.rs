@@ -0,0 +1,136 @@
+use super::TuningState;
+
+/// Wraps an MTS message body in the SysEx start and end bytes
+fn sysex(body: &[u8]) -> Vec<u8> {
+    let mut data = vec![0xF0];
+    data.extend_from_slice(body);
+    data.push(0xF7);
+    data
+}
+
+#[test]
+fn single_note_tuning_change() {
+    let mut tuning = TuningState::new();
+
+    // Key 69 to half a semitone above it, key 60 to a semitone below it
+    tuning.system_exclusive(&sysex(&[
+        0x7F, 0x7F, 0x08, 0x02, 0x00, 0x02, 69, 69, 0x40, 0x00, 60, 59, 0x00, 0x00,
+    ]));
+
+    assert_eq!(tuning.pitch_offset(0, 69), 0.5);
+    assert_eq!(tuning.pitch_offset(0, 60), -1.0);
+    assert_eq!(tuning.pitch_offset(0, 61), 0.0);
+    // Note tuning applies to every channel
+    assert_eq!(tuning.pitch_offset(9, 69), 0.5);
+}
+
+#[test]
+fn single_note_tuning_change_with_bank() {
+    let mut tuning = TuningState::new();
+
+    // Without the SysEx start and end bytes, and only the first of the two
+    // changes counted
+    tuning.system_exclusive(&[
+        0x7E, 0x7F, 0x08, 0x07, 0x00, 0x00, 0x01, 64, 66, 0x00, 0x00, 65, 60, 0x00, 0x00,
+    ]);
+
+    assert_eq!(tuning.pitch_offset(0, 64), 2.0);
+    assert_eq!(tuning.pitch_offset(0, 65), 0.0);
+}
+
+#[test]
+fn single_note_tuning_change_no_change() {
+    let mut tuning = TuningState::new();
+    tuning.system_exclusive(&sysex(&[
+        0x7F, 0x7F, 0x08, 0x02, 0x00, 0x01, 69, 70, 0x00, 0x00,
+    ]));
+
+    // 7F 7F 7F leaves the key as it was
+    tuning.system_exclusive(&sysex(&[
+        0x7F, 0x7F, 0x08, 0x02, 0x00, 0x01, 69, 0x7F, 0x7F, 0x7F,
+    ]));
+    assert_eq!(tuning.pitch_offset(0, 69), 1.0);
+
+    // Truncated changes are ignored
+    tuning.system_exclusive(&sysex(&[0x7F, 0x7F, 0x08, 0x02, 0x00, 0x01, 69, 72]));
+    assert_eq!(tuning.pitch_offset(0, 69), 1.0);
+}
+
+#[test]
+fn bulk_tuning_dump() {
+    let mut tuning = TuningState::new();
+
+    // Every key a quarter tone down, except key 0 which is left unchanged
+    let mut body = vec![0x7E, 0x7F, 0x08, 0x01, 0x00];
+    body.extend_from_slice(b"Quarter tone low");
+    body.extend_from_slice(&[0x7F, 0x7F, 0x7F]);
+    for key in 1..128u8 {
+        body.extend_from_slice(&[key - 1, 0x60, 0x00]);
+    }
+    // Checksum
+    body.push(0x00);
+    tuning.system_exclusive(&sysex(&body));
+
+    assert_eq!(tuning.pitch_offset(0, 0), 0.0);
+    for key in 1..128 {
+        assert_eq!(tuning.pitch_offset(3, key), -0.25, "key {}", key);
+    }
+}
+
+#[test]
+fn truncated_bulk_tuning_dump() {
+    let mut tuning = TuningState::new();
+
+    let mut body = vec![0x7E, 0x7F, 0x08, 0x01, 0x00];
+    body.extend_from_slice(b"Truncated       ");
+    for key in 0..64u8 {
+        body.extend_from_slice(&[key + 1, 0x00, 0x00]);
+    }
+    tuning.system_exclusive(&sysex(&body));
+
+    assert_eq!(tuning.pitch_offset(0, 0), 0.0);
+}
+
+#[test]
+fn scale_octave_tuning() {
+    let mut tuning = TuningState::new();
+
+    // 1 byte form, only for channel 1: C 50 cents up, D 64 cents down
+    let mut body = vec![0x7E, 0x7F, 0x08, 0x08, 0x00, 0x00, 0x01];
+    body.extend_from_slice(&[114, 64, 0, 64, 64, 64, 64, 64, 64, 64, 64, 64]);
+    tuning.system_exclusive(&sysex(&body));
+
+    assert_eq!(tuning.pitch_offset(0, 60), 0.5);
+    assert_eq!(tuning.pitch_offset(0, 72), 0.5);
+    assert_eq!(tuning.pitch_offset(0, 62), -0.64);
+    assert_eq!(tuning.pitch_offset(0, 61), 0.0);
+    assert_eq!(tuning.pitch_offset(1, 60), 0.0);
+
+    // 2 byte form, for channel 16: C# 50 cents down
+    let mut body = vec![0x7F, 0x7F, 0x08, 0x09, 0x02, 0x00, 0x00];
+    for key in 0..12 {
+        let value = if key == 1 { 0x20 } else { 0x40 };
+        body.extend_from_slice(&[value, 0x00]);
+    }
+    tuning.system_exclusive(&sysex(&body));
+
+    assert_eq!(tuning.pitch_offset(15, 61), -0.5);
+    assert_eq!(tuning.pitch_offset(15, 60), 0.0);
+    assert_eq!(tuning.pitch_offset(0, 60), 0.5);
+}
+
+#[test]
+fn other_sysex_is_ignored() {
+    let mut tuning = TuningState::new();
+
+    // GM system on, and a single note change for another sub-ID
+    tuning.system_exclusive(&sysex(&[0x7E, 0x7F, 0x09, 0x01]));
+    tuning.system_exclusive(&sysex(&[
+        0x7F, 0x7F, 0x04, 0x02, 0x00, 0x01, 69, 70, 0x00, 0x00,
+    ]));
+    tuning.system_exclusive(&[]);
+
+    for key in 0..=255 {
+        assert_eq!(tuning.pitch_offset(0, key), 0.0);
+    }
+}
@@ -1,15 +1,20 @@
+pub mod color_space;
+pub mod gpu;
+pub mod pacing;
+pub mod screenshot;
 pub mod swapchain;
+pub mod window_mode;
 
 use std::sync::Arc;
 
 use vulkano::{
     device::{
-        physical::PhysicalDevice, physical::PhysicalDeviceType, Device, DeviceCreateInfo, DeviceExtensions, Features, Queue,
+        physical::PhysicalDevice, Device, DeviceCreateInfo, DeviceExtensions, Features, Queue,
         QueueCreateInfo,
     },
     format::Format,
     instance::{Instance, InstanceCreateInfo, InstanceExtensions},
-    swapchain::{PresentMode, Surface},
+    swapchain::{ColorSpace, PresentMode, Surface},
     sync::GpuFuture,
     Version,
 };
@@ -19,97 +24,165 @@ use winit::{
     window::{Window, WindowBuilder},
 };
 
-use self::swapchain::{ManagedSwapchain, SwapchainFrame};
+use self::{
+    gpu::{can_present, list_gpus, select_physical_device, GpuInfo},
+    swapchain::{Backoff, FrameError, ManagedSwapchain, SwapchainFrame},
+    window_mode::{FullscreenTarget, WindowGeometry},
+};
 
 pub struct Renderer {
-    _instance: Arc<Instance>,
+    instance: Arc<Instance>,
     device: Arc<Device>,
+    gpu_info: GpuInfo,
     surface: Arc<Surface<Window>>,
     queue: Arc<Queue>,
-    swap_chain: ManagedSwapchain,
+    /// Only empty while the renderer is rebuilt after a device loss
+    swap_chain: Option<ManagedSwapchain>,
+    /// The present mode the swapchain is rebuilt with while it is empty
+    present_mode: PresentMode,
+    /// Spaces out the attempts to rebuild the renderer after a device loss
+    rebuild_retry: Backoff,
+    hdr: bool,
 }
 
 impl Renderer {
     pub fn new(
         event_loop: &EventLoop<()>,
-        window_size: [u32; 2],
+        geometry: &WindowGeometry,
         present_mode: PresentMode,
+        gpu: Option<&str>,
+        hdr: bool,
         name: &str,
     ) -> Self {
-        // Add instance extensions based on needs
-        let instance_extensions = InstanceExtensions {
-            ..vulkano_win::required_extensions()
-        };
+        let instance = Self::create_instance();
 
-        // Create instance
-        let instance = Instance::new(InstanceCreateInfo {
-            application_version: Version::V1_2,
-            enabled_extensions: instance_extensions,
-            ..Default::default()
-        })
-        .expect("Failed to create instance");
-
-        // Get most performant physical device (device with most memory)
-        /*let physical = PhysicalDevice::enumerate(&instance)
-            .fold(None, |acc, val| {
-                if acc.is_none() {
-                    Some(val)
-                } else if acc.unwrap().properties().max_compute_shared_memory_size
-                    >= val.properties().max_compute_shared_memory_size
-                {
-                    acc
-                } else {
-                    Some(val)
-                }
-            })
-            .expect("No physical device found");*/
-
-        let physical = PhysicalDevice::enumerate(&instance)
-            .fold(None, |acc, val| {
-                if acc.is_none() {
-                    Some(val)
-                } else if acc.unwrap().properties().device_type == PhysicalDeviceType::DiscreteGpu {
-                    acc
-                } else {
-                    Some(val)
-                }
-            })
-            .expect("No physical device found");
-
-        println!("Using device {}", physical.properties().device_name);
-
-        // Create rendering surface along with window
-        let surface = WindowBuilder::new()
-            .with_inner_size(winit::dpi::LogicalSize::new(window_size[0], window_size[1]))
+        // Create rendering surface along with window, the device is picked
+        // from the ones that can present to it
+        let surface = geometry
+            .apply(WindowBuilder::new())
             .with_title(name)
             .build_vk_surface(event_loop, instance.clone())
             .expect("Failed to create vulkan surface & window");
 
+        let physical = select_physical_device(&instance, &surface, gpu)
+            .expect("No device can draw to the window");
+        let gpu_info = GpuInfo::new(physical);
+        println!("Using device {}", gpu_info.describe());
+        if gpu_info.is_software() {
+            println!("No hardware GPU was picked, rendering will be slow");
+        }
+
         // Create device
-        let (device, queue) = Self::create_device(physical, surface.clone());
+        let (device, queue) = Self::create_device(physical, surface.clone())
+            .unwrap_or_else(|e| panic!("Failed to create the device: {}", e));
 
         // Create swap chain & frame(s) to which we'll render
         let swap_chain =
-            ManagedSwapchain::create(surface.clone(), physical, device.clone(), present_mode);
+            ManagedSwapchain::create(surface.clone(), physical, device.clone(), present_mode, hdr)
+                .unwrap_or_else(|e| panic!("Failed to create the swapchain: {}", e));
 
         Self {
-            _instance: instance,
+            instance,
             device,
+            gpu_info,
             surface,
             queue,
-            swap_chain,
+            swap_chain: Some(swap_chain),
+            present_mode,
+            rebuild_retry: Backoff::new(),
+            hdr,
         }
     }
 
+    /// Rebuilds everything created from the device after it was lost. The
+    /// instance, surface and window are kept. A failed attempt is retried on a
+    /// later call, waiting longer after every failure like the swapchain does.
+    pub fn rebuild(&mut self) -> Result<(), FrameError> {
+        // The old swapchain has to be destroyed before a new one can be
+        // created for the same surface
+        if let Some(swap_chain) = self.swap_chain.take() {
+            println!("The device was lost, rebuilding the renderer");
+            self.present_mode = swap_chain.present_mode();
+        }
+
+        if self.rebuild_retry.is_waiting() {
+            return Err(FrameError::Skipped);
+        }
+
+        match self.try_rebuild() {
+            Ok(()) => {
+                self.rebuild_retry.succeeded();
+                Ok(())
+            }
+            Err(e) => {
+                let delay = self.rebuild_retry.failed();
+                println!(
+                    "Failed to rebuild the renderer, retrying in {:?}: {}",
+                    delay, e
+                );
+                Err(FrameError::Skipped)
+            }
+        }
+    }
+
+    fn try_rebuild(&mut self) -> Result<(), String> {
+        let physical = PhysicalDevice::from_index(&self.instance, self.gpu_info.index)
+            .filter(|physical| can_present(*physical, &self.surface))
+            .or_else(|| select_physical_device(&self.instance, &self.surface, None))
+            .ok_or("no device can draw to the window")?;
+        self.gpu_info = GpuInfo::new(physical);
+        println!("Rebuilding the renderer on {}", self.gpu_info.describe());
+
+        let (device, queue) = Self::create_device(physical, self.surface.clone())?;
+        let swap_chain = ManagedSwapchain::create(
+            self.surface.clone(),
+            physical,
+            device.clone(),
+            self.present_mode,
+            self.hdr,
+        )?;
+
+        self.swap_chain = Some(swap_chain);
+        self.device = device;
+        self.queue = queue;
+        Ok(())
+    }
+
+    fn swap_chain(&self) -> &ManagedSwapchain {
+        self.swap_chain.as_ref().unwrap()
+    }
+
+    fn swap_chain_mut(&mut self) -> &mut ManagedSwapchain {
+        self.swap_chain.as_mut().unwrap()
+    }
+
+    pub fn create_instance() -> Arc<Instance> {
+        // Add instance extensions based on needs. The swapchain color space
+        // extension is needed for HDR output.
+        let supported = InstanceExtensions::supported_by_core()
+            .unwrap_or_else(|_| InstanceExtensions::none());
+        let instance_extensions = InstanceExtensions {
+            ext_swapchain_colorspace: supported.ext_swapchain_colorspace,
+            ..vulkano_win::required_extensions()
+        };
+
+        Instance::new(InstanceCreateInfo {
+            application_version: Version::V1_2,
+            enabled_extensions: instance_extensions,
+            ..Default::default()
+        })
+        .expect("Failed to create instance")
+    }
+
     /// Creates vulkan device with required queue families and required extensions
     fn create_device(
         physical: PhysicalDevice,
         surface: Arc<Surface<Window>>,
-    ) -> (Arc<Device>, Arc<Queue>) {
+    ) -> Result<(Arc<Device>, Arc<Queue>), String> {
         let queue_family = physical
             .queue_families()
             .find(|&q| q.supports_graphics() && q.supports_surface(&surface).unwrap_or(false))
-            .expect("couldn't find a graphical queue family");
+            .ok_or("couldn't find a graphical queue family")?;
 
         // Add device extensions based on needs
         let device_extensions = DeviceExtensions {
@@ -117,9 +190,10 @@ impl Renderer {
             ..DeviceExtensions::none()
         };
 
-        // Add device features
+        // Add device features. Geometry shaders are optional, the note renderer
+        // falls back to instanced quads without them.
         let features = Features {
-            geometry_shader: true,
+            geometry_shader: physical.supported_features().geometry_shader,
             ..Features::none()
         };
 
@@ -133,10 +207,10 @@ impl Renderer {
                     _ne: Default::default(),
                 },
             )
-            .expect("failed to create device")
+            .map_err(|e| format!("failed to create device: {:?}", e))?
         };
 
-        (device, queues.next().unwrap())
+        Ok((device, queues.next().unwrap()))
     }
 
     pub fn queue(&self) -> Arc<Queue> {
@@ -147,6 +221,16 @@ impl Renderer {
         self.device.clone()
     }
 
+    /// All the devices that could be picked
+    pub fn gpus(&self) -> Vec<GpuInfo> {
+        list_gpus(&self.instance, &self.surface)
+    }
+
+    /// The device that is in use
+    pub fn gpu(&self) -> &GpuInfo {
+        &self.gpu_info
+    }
+
     pub fn surface(&self) -> Arc<Surface<Window>> {
         self.surface.clone()
     }
@@ -156,30 +240,69 @@ impl Renderer {
     }
 
     pub fn format(&self) -> Format {
-        self.swap_chain.state().images_state.format
+        self.swap_chain().state().images_state.format
+    }
+
+    pub fn color_space(&self) -> ColorSpace {
+        self.swap_chain().state().images_state.color_space
     }
 
     pub fn resize(&mut self) {
-        self.swap_chain.resize();
+        if let Some(swap_chain) = self.swap_chain.as_mut() {
+            swap_chain.resize();
+        }
+    }
+
+    /// A minimized window has a zero size, there is nothing to render to
+    pub fn is_minimized(&self) -> bool {
+        let size = self.window().inner_size();
+        size.width == 0 || size.height == 0
+    }
+
+    /// Switches between windowed and fullscreen, the swapchain is recreated
+    /// on the next frame to match the new size
+    pub fn set_fullscreen(&mut self, target: Option<&FullscreenTarget>) {
+        let window = self.surface.window();
+        window.set_fullscreen(target.and_then(|target| target.to_winit(window)));
+        self.resize();
+    }
+
+    pub fn present_modes(&self) -> Vec<PresentMode> {
+        self.swap_chain().present_modes().to_vec()
     }
 
+    pub fn set_present_mode(&mut self, present_mode: PresentMode) {
+        if let Some(swap_chain) = self.swap_chain.as_mut() {
+            swap_chain.set_present_mode(present_mode);
+        }
+    }
+
+    /// Draws and presents a frame. Nothing is drawn while the window is
+    /// minimized or the swapchain can't be recreated, and a lost device has
+    /// to be handled with [`Renderer::rebuild`] until it succeeds.
     pub fn render(
         &mut self,
         draw: impl FnOnce(&SwapchainFrame, Box<dyn GpuFuture>) -> Box<dyn GpuFuture>,
-    ) {
-        // Get the previous frame before starting a new one
-        let previous_frame_future = self.swap_chain.take_previous_frame_end().unwrap();
+    ) -> Result<(), FrameError> {
+        if self.is_minimized() {
+            return Err(FrameError::Skipped);
+        }
+        if self.swap_chain.is_none() {
+            return Err(FrameError::DeviceLost);
+        }
+
+        let queue = self.queue.clone();
 
         // Start a new frame
-        let (frame, acquire_future) = self.swap_chain.acquire_frame();
+        let (mut frame, acquire_future) = self.swap_chain_mut().acquire_frame()?;
 
-        // Join the futures
-        let future = previous_frame_future.join(acquire_future);
+        // Join the futures, the previous frame has to be finished first
+        let future = frame.take_previous_frame_end().join(acquire_future);
 
         // Call the passed-in renderer
         let after_future = draw(&frame, Box::new(future));
 
         // Finish render
-        frame.present(&self.queue, after_future);
+        frame.present(&queue, after_future)
     }
 }
@@ -0,0 +1,47 @@
+use vulkano::{
+    format::{Format, NumericType},
+    swapchain::ColorSpace,
+};
+
+/// sRGB formats in order of preference, the hardware converts the linear
+/// shader output to sRGB when writing to them
+const SRGB_FORMATS: [Format; 3] = [
+    Format::B8G8R8A8_SRGB,
+    Format::R8G8B8A8_SRGB,
+    Format::A8B8G8R8_SRGB_PACK32,
+];
+
+/// Picks the surface format. With `hdr`, a linear extended sRGB (scRGB) float
+/// format is used if the surface has one. HDR10 isn't used, it would need the
+/// whole output including the GUI encoded with the PQ curve.
+pub fn choose_surface_format(formats: &[(Format, ColorSpace)], hdr: bool) -> (Format, ColorSpace) {
+    let hdr_format = (Format::R16G16B16A16_SFLOAT, ColorSpace::ExtendedSrgbLinear);
+    if hdr && formats.contains(&hdr_format) {
+        return hdr_format;
+    }
+
+    SRGB_FORMATS
+        .iter()
+        .map(|&format| (format, ColorSpace::SrgbNonLinear))
+        .find(|format| formats.contains(format))
+        .unwrap_or(formats[0])
+}
+
+/// Whether shaders writing to this format have to encode their linear colors
+/// to sRGB themselves, because the format stores the values as they are
+pub fn needs_srgb_encoding(format: Format) -> bool {
+    !matches!(
+        format.type_color(),
+        Some(NumericType::SRGB) | Some(NumericType::SFLOAT)
+    )
+}
+
+/// Converts an 8 bit sRGB channel to a linear float
+pub fn srgb_to_linear(value: u8) -> f32 {
+    let value = value as f32 / 255.0;
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
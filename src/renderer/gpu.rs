@@ -0,0 +1,260 @@
+use std::{fmt, str::FromStr, sync::Arc};
+
+use vulkano::{
+    device::physical::{PhysicalDevice, PhysicalDeviceType},
+    instance::Instance,
+    swapchain::Surface,
+};
+use winit::window::Window;
+
+/// Identifies a device between sessions. The vendor and device IDs stay the
+/// same when devices are added or removed, the index tells identical devices
+/// apart. Stored as `vendor:device:index` in hex.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GpuId {
+    pub vendor_id: u32,
+    pub device_id: u32,
+    pub index: usize,
+}
+
+impl GpuId {
+    pub fn new(physical: PhysicalDevice) -> Self {
+        GpuId {
+            vendor_id: physical.properties().vendor_id,
+            device_id: physical.properties().device_id,
+            index: physical.index(),
+        }
+    }
+
+    fn is_same_model(&self, physical: PhysicalDevice) -> bool {
+        let properties = physical.properties();
+        properties.vendor_id == self.vendor_id && properties.device_id == self.device_id
+    }
+}
+
+impl fmt::Display for GpuId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:04x}:{:04x}:{}",
+            self.vendor_id, self.device_id, self.index
+        )
+    }
+}
+
+impl FromStr for GpuId {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split(':');
+        let mut next = || parts.next().ok_or(());
+        let vendor_id = u32::from_str_radix(next()?, 16).map_err(|_| ())?;
+        let device_id = u32::from_str_radix(next()?, 16).map_err(|_| ())?;
+        let index = next()?.parse().map_err(|_| ())?;
+        if parts.next().is_some() {
+            return Err(());
+        }
+
+        Ok(GpuId {
+            vendor_id,
+            device_id,
+            index,
+        })
+    }
+}
+
+/// A short description of a physical device, for listing them in the GUI
+#[derive(Debug, Clone, PartialEq)]
+pub struct GpuInfo {
+    pub index: usize,
+    pub id: GpuId,
+    pub name: String,
+    pub device_type: PhysicalDeviceType,
+    pub driver: String,
+    /// The size of the largest device local memory heap in bytes
+    pub memory: u64,
+}
+
+impl GpuInfo {
+    pub fn new(physical: PhysicalDevice) -> Self {
+        let properties = physical.properties();
+        let driver = match (&properties.driver_name, &properties.driver_info) {
+            (Some(name), Some(info)) => format!("{} {}", name, info),
+            (Some(name), None) => name.clone(),
+            _ => format!("Driver version {}", properties.driver_version),
+        };
+
+        GpuInfo {
+            index: physical.index(),
+            id: GpuId::new(physical),
+            name: properties.device_name.clone(),
+            device_type: properties.device_type,
+            driver,
+            memory: device_local_memory(physical),
+        }
+    }
+
+    pub fn is_software(&self) -> bool {
+        self.device_type == PhysicalDeviceType::Cpu
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match self.device_type {
+            PhysicalDeviceType::DiscreteGpu => "Discrete",
+            PhysicalDeviceType::IntegratedGpu => "Integrated",
+            PhysicalDeviceType::VirtualGpu => "Virtual",
+            PhysicalDeviceType::Cpu => "Software",
+            PhysicalDeviceType::Other => "Other",
+        }
+    }
+
+    /// A one line description with the type, memory and driver
+    pub fn describe(&self) -> String {
+        format!(
+            "{}: {} ({}, {} MB, {})",
+            self.index,
+            self.name,
+            self.type_name(),
+            self.memory / 1024 / 1024,
+            self.driver
+        )
+    }
+}
+
+/// The devices that can draw to the surface
+pub fn list_gpus(instance: &Arc<Instance>, surface: &Arc<Surface<Window>>) -> Vec<GpuInfo> {
+    usable_devices(instance, surface)
+        .map(GpuInfo::new)
+        .collect()
+}
+
+/// Whether a queue family of the device supports both graphics and
+/// presenting to the surface
+pub fn can_present(physical: PhysicalDevice, surface: &Arc<Surface<Window>>) -> bool {
+    physical.queue_families().any(|family| {
+        family.supports_graphics() && family.supports_surface(surface).unwrap_or(false)
+    })
+}
+
+fn usable_devices<'a>(
+    instance: &'a Arc<Instance>,
+    surface: &'a Arc<Surface<Window>>,
+) -> impl 'a + Iterator<Item = PhysicalDevice<'a>> {
+    PhysicalDevice::enumerate(instance).filter(move |physical| can_present(*physical, surface))
+}
+
+fn device_local_memory(physical: PhysicalDevice) -> u64 {
+    physical
+        .memory_heaps()
+        .filter(|heap| heap.is_device_local())
+        .map(|heap| heap.size())
+        .max()
+        .unwrap_or(0)
+}
+
+/// Lower is better, software devices like lavapipe are only a fallback
+fn type_rank(device_type: PhysicalDeviceType) -> u32 {
+    match device_type {
+        PhysicalDeviceType::DiscreteGpu => 0,
+        PhysicalDeviceType::IntegratedGpu => 1,
+        PhysicalDeviceType::VirtualGpu => 2,
+        PhysicalDeviceType::Other => 3,
+        PhysicalDeviceType::Cpu => 4,
+    }
+}
+
+/// Picks the device matching the preference, which is a saved [`GpuId`], a
+/// device index or a part of its name. Only devices that can present to the
+/// surface are considered. Without a matching preference, the hardware device
+/// with the most memory is picked, falling back to a software device.
+pub fn select_physical_device<'a>(
+    instance: &'a Arc<Instance>,
+    surface: &'a Arc<Surface<Window>>,
+    preference: Option<&str>,
+) -> Option<PhysicalDevice<'a>> {
+    if let Some(preference) = preference {
+        let preferred = if let Ok(id) = preference.parse::<GpuId>() {
+            // The index may have changed if devices were added or removed,
+            // then any device of the same model is used
+            let models: Vec<_> = usable_devices(instance, surface)
+                .filter(|physical| id.is_same_model(*physical))
+                .collect();
+            models
+                .iter()
+                .find(|physical| physical.index() == id.index)
+                .or_else(|| models.first())
+                .copied()
+        } else {
+            let lowercase = preference.to_lowercase();
+            usable_devices(instance, surface).find(|physical| match preference.parse::<usize>() {
+                Ok(index) => physical.index() == index,
+                Err(_) => physical
+                    .properties()
+                    .device_name
+                    .to_lowercase()
+                    .contains(&lowercase),
+            })
+        };
+
+        match preferred {
+            Some(physical) => return Some(physical),
+            None => println!(
+                "GPU \"{}\" not found or can't draw to the window, picking one automatically",
+                preference
+            ),
+        }
+    }
+
+    usable_devices(instance, surface).min_by_key(|physical| {
+        (
+            type_rank(physical.properties().device_type),
+            std::cmp::Reverse(device_local_memory(*physical)),
+        )
+    })
+}
+
+/// Prints the instance's devices with their properties, memory and queues
+pub fn print_vulkan_info(instance: &Arc<Instance>) {
+    println!("Vulkan API version: {:?}", instance.api_version());
+
+    for physical in PhysicalDevice::enumerate(instance) {
+        let info = GpuInfo::new(physical);
+        let properties = physical.properties();
+
+        println!();
+        println!("Device {}", info.describe());
+        println!("  API version: {:?}", properties.api_version);
+        println!(
+            "  Vendor ID: {:#06x}, Device ID: {:#06x}",
+            properties.vendor_id, properties.device_id
+        );
+        println!(
+            "  Geometry shaders: {}",
+            physical.supported_features().geometry_shader
+        );
+        println!("  Max image size: {}", properties.max_image_dimension2_d);
+
+        for heap in physical.memory_heaps() {
+            println!(
+                "  Memory heap {}: {} MB{}",
+                heap.id(),
+                heap.size() / 1024 / 1024,
+                if heap.is_device_local() {
+                    ", device local"
+                } else {
+                    ""
+                }
+            );
+        }
+
+        for family in physical.queue_families() {
+            println!(
+                "  Queue family {}: {} queues, graphics: {}, compute: {}",
+                family.id(),
+                family.queues_count(),
+                family.supports_graphics(),
+                family.supports_compute()
+            );
+        }
+    }
+}
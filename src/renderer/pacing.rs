@@ -0,0 +1,107 @@
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use vulkano::swapchain::PresentMode;
+
+/// The last part of a frame wait is spun instead of slept, because sleeps can
+/// overshoot by the OS timer resolution
+const SPIN_TIME: Duration = Duration::from_millis(2);
+
+pub fn present_mode_name(mode: PresentMode) -> &'static str {
+    match mode {
+        PresentMode::Immediate => "Immediate",
+        PresentMode::Mailbox => "Mailbox",
+        PresentMode::Fifo => "Fifo (VSync)",
+        PresentMode::FifoRelaxed => "Fifo Relaxed",
+        _ => "Other",
+    }
+}
+
+/// How fast frames get presented
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrameSettings {
+    pub present_mode: PresentMode,
+    pub fps_cap: Option<u32>,
+    /// Renders as fast as possible, ignoring the present mode and the cap
+    pub benchmark: bool,
+}
+
+impl Default for FrameSettings {
+    fn default() -> Self {
+        FrameSettings {
+            present_mode: PresentMode::Fifo,
+            fps_cap: None,
+            benchmark: false,
+        }
+    }
+}
+
+impl FrameSettings {
+    /// The present mode to use out of the ones the surface supports
+    pub fn effective_present_mode(&self, supported: &[PresentMode]) -> PresentMode {
+        let preferred: &[PresentMode] = if self.benchmark {
+            &[PresentMode::Immediate, PresentMode::Mailbox]
+        } else {
+            std::slice::from_ref(&self.present_mode)
+        };
+
+        preferred
+            .iter()
+            .copied()
+            .find(|mode| supported.contains(mode))
+            .unwrap_or(PresentMode::Fifo)
+    }
+
+    pub fn effective_fps_cap(&self) -> Option<u32> {
+        if self.benchmark {
+            None
+        } else {
+            self.fps_cap
+        }
+    }
+}
+
+/// Waits between frames to hold a frame rate cap
+pub struct FramePacer {
+    next_frame: Instant,
+}
+
+impl FramePacer {
+    pub fn new() -> Self {
+        FramePacer {
+            next_frame: Instant::now(),
+        }
+    }
+
+    /// Waits until the next frame is due
+    pub fn wait(&mut self, fps_cap: Option<u32>) {
+        let fps_cap = match fps_cap {
+            Some(fps_cap) if fps_cap > 0 => fps_cap,
+            _ => {
+                self.next_frame = Instant::now();
+                return;
+            }
+        };
+        let frame_time = Duration::from_secs_f64(1.0 / fps_cap as f64);
+
+        let now = Instant::now();
+        if now < self.next_frame {
+            let remaining = self.next_frame - now;
+            if remaining > SPIN_TIME {
+                thread::sleep(remaining - SPIN_TIME);
+            }
+            while Instant::now() < self.next_frame {
+                std::hint::spin_loop();
+            }
+        } else if now - self.next_frame > frame_time {
+            // Fell behind by more than a frame, don't try to catch up
+            self.next_frame = now;
+        }
+
+        // Scheduled from the deadline instead of the current time, so the
+        // small errors don't add up
+        self.next_frame += frame_time;
+    }
+}
@@ -0,0 +1,296 @@
+use std::{
+    fs,
+    path::PathBuf,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use image::RgbaImage;
+use vulkano::{
+    buffer::{BufferUsage, CpuAccessibleBuffer},
+    command_buffer::{AutoCommandBufferBuilder, CommandBufferUsage, PrimaryAutoCommandBuffer},
+    device::Queue,
+    format::Format,
+    image::ImageAccess,
+    sync::{self, GpuFuture},
+};
+
+use crate::settings_file::config_dir;
+
+/// What a screenshot shows
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScreenshotKind {
+    /// The notes and optionally the keyboard, re-rendered at a higher
+    /// resolution if the scale is above 1
+    Scene,
+    /// The whole window, including the keyboard and the GUI
+    Window,
+}
+
+impl ScreenshotKind {
+    pub const ALL: [ScreenshotKind; 2] = [ScreenshotKind::Scene, ScreenshotKind::Window];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            ScreenshotKind::Scene => "Notes",
+            ScreenshotKind::Window => "Whole window",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScreenshotSettings {
+    pub kind: ScreenshotKind,
+    /// Scene screenshots are rendered this many times larger than the scene
+    pub scale: u32,
+    /// Draws the keyboard next to the notes in scene screenshots
+    pub include_keyboard: bool,
+}
+
+impl Default for ScreenshotSettings {
+    fn default() -> Self {
+        ScreenshotSettings {
+            kind: ScreenshotKind::Scene,
+            scale: 1,
+            include_keyboard: false,
+        }
+    }
+}
+
+/// A copy of an image into a host visible buffer, readable once the GPU work
+/// it was recorded after has finished
+pub struct ImageReadback {
+    buffer: Arc<CpuAccessibleBuffer<[u8]>>,
+    size: [u32; 2],
+    format: Format,
+}
+
+impl ImageReadback {
+    /// Creates the buffer and the command buffer copying the image into it.
+    /// `None` if the image can't be copied from, like swapchain images on
+    /// surfaces that don't support it.
+    pub fn new(
+        queue: &Arc<Queue>,
+        image: Arc<dyn ImageAccess>,
+    ) -> Option<(Self, PrimaryAutoCommandBuffer)> {
+        if !image.inner().image.usage().transfer_source {
+            println!("Can't take a screenshot, the image can't be copied from");
+            return None;
+        }
+
+        let format = image.format();
+        let size = image.dimensions().width_height();
+        let bytes_per_pixel = match format.block_size() {
+            Some(size) => size,
+            None => {
+                println!("Can't take a screenshot of the format {:?}", format);
+                return None;
+            }
+        };
+
+        let buffer = unsafe {
+            CpuAccessibleBuffer::uninitialized_array(
+                queue.device().clone(),
+                size[0] as u64 * size[1] as u64 * bytes_per_pixel,
+                BufferUsage::transfer_destination(),
+                true,
+            )
+        };
+        let buffer = match buffer {
+            Ok(buffer) => buffer,
+            Err(e) => {
+                println!("Failed to create the screenshot buffer: {:?}", e);
+                return None;
+            }
+        };
+
+        let mut command_buffer_builder = AutoCommandBufferBuilder::primary(
+            queue.device().clone(),
+            queue.family(),
+            CommandBufferUsage::OneTimeSubmit,
+        )
+        .unwrap();
+        if let Err(e) = command_buffer_builder.copy_image_to_buffer(image, buffer.clone()) {
+            println!("Failed to copy the screenshot: {:?}", e);
+            return None;
+        }
+        let command_buffer = command_buffer_builder.build().unwrap();
+
+        Some((
+            ImageReadback {
+                buffer,
+                size,
+                format,
+            },
+            command_buffer,
+        ))
+    }
+
+    /// Copies the image and waits for the copy
+    pub fn read_now(queue: &Arc<Queue>, image: Arc<dyn ImageAccess>) -> Option<RgbaImage> {
+        let (readback, command_buffer) = Self::new(queue, image)?;
+
+        if let Err(e) = sync::now(queue.device().clone())
+            .then_execute(queue.clone(), command_buffer)
+            .map_err(|e| format!("{:?}", e))
+            .and_then(|future| {
+                future
+                    .then_signal_fence_and_flush()
+                    .and_then(|future| future.wait(None))
+                    .map_err(|e| format!("{:?}", e))
+            })
+        {
+            println!("Failed to copy the screenshot: {}", e);
+            return None;
+        }
+
+        readback.read()
+    }
+
+    /// Converts the copied pixels to 8 bit sRGB
+    pub fn read(&self) -> Option<RgbaImage> {
+        let data = match self.buffer.read() {
+            Ok(data) => data,
+            Err(e) => {
+                println!("Failed to read the screenshot: {:?}", e);
+                return None;
+            }
+        };
+
+        let pixels: Vec<u8> = match self.format {
+            Format::R8G8B8A8_SRGB
+            | Format::R8G8B8A8_UNORM
+            | Format::A8B8G8R8_SRGB_PACK32
+            | Format::A8B8G8R8_UNORM_PACK32 => data.to_vec(),
+            Format::B8G8R8A8_SRGB | Format::B8G8R8A8_UNORM => data
+                .chunks_exact(4)
+                .flat_map(|bgra| [bgra[2], bgra[1], bgra[0], bgra[3]])
+                .collect(),
+            // Linear extended sRGB, values outside of the SDR range are clipped
+            Format::R16G16B16A16_SFLOAT => data
+                .chunks_exact(8)
+                .flat_map(|pixel| {
+                    let channel =
+                        |i: usize| f16_to_f32(u16::from_le_bytes([pixel[i * 2], pixel[i * 2 + 1]]));
+                    [
+                        linear_to_srgb(channel(0)),
+                        linear_to_srgb(channel(1)),
+                        linear_to_srgb(channel(2)),
+                        (channel(3).clamp(0.0, 1.0) * 255.0).round() as u8,
+                    ]
+                })
+                .collect(),
+            format => {
+                println!("Can't take a screenshot of the format {:?}", format);
+                return None;
+            }
+        };
+
+        RgbaImage::from_raw(self.size[0], self.size[1], pixels)
+    }
+}
+
+fn linear_to_srgb(value: f32) -> u8 {
+    let value = value.clamp(0.0, 1.0);
+    let srgb = if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    };
+    (srgb * 255.0).round() as u8
+}
+
+fn f16_to_f32(bits: u16) -> f32 {
+    let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((bits >> 10) & 0x1f) as i32;
+    let mantissa = (bits & 0x3ff) as f32;
+
+    sign * match exponent {
+        0 => mantissa * 2f32.powi(-24),
+        0x1f if mantissa == 0.0 => f32::INFINITY,
+        0x1f => f32::NAN,
+        _ => (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+    }
+}
+
+/// Blends the image over an opaque background. The scene is drawn with
+/// premultiplied alpha over a transparent clear color.
+pub fn flatten(image: &mut RgbaImage, background: [u8; 3]) {
+    for pixel in image.pixels_mut() {
+        let alpha = pixel[3] as u32;
+        for channel in 0..3 {
+            let blended = pixel[channel] as u32 + background[channel] as u32 * (255 - alpha) / 255;
+            pixel[channel] = blended.min(255) as u8;
+        }
+        pixel[3] = 255;
+    }
+}
+
+/// Formats seconds since the unix epoch as `YYYY-MM-DD_HH-MM-SS` in UTC
+fn format_timestamp(secs: u64) -> String {
+    let days = (secs / 86400) as i64;
+    let time = secs % 86400;
+
+    // Civil date from days since the epoch, from Howard Hinnant's algorithms
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let day_of_era = z.rem_euclid(146097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+    format!(
+        "{:04}-{:02}-{:02}_{:02}-{:02}-{:02}",
+        year,
+        month,
+        day,
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
+
+/// Saves the screenshot as a PNG in the screenshots directory of the
+/// [`config_dir`], named after the song and the current time. The directory
+/// next to the executable is often read only. Returns the path it was saved to.
+pub fn save_screenshot(image: &RgbaImage, song_name: &str) -> Option<PathBuf> {
+    let directory = config_dir().join("screenshots");
+    if let Err(e) = fs::create_dir_all(&directory) {
+        println!("Failed to create {}: {}", directory.display(), e);
+        return None;
+    }
+
+    let song_name: String = song_name
+        .chars()
+        .map(|c| {
+            if c.is_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or(0);
+    let path = directory.join(format!("{}_{}.png", song_name, format_timestamp(secs)));
+
+    match image.save(&path) {
+        Ok(()) => {
+            println!("Saved screenshot to {}", path.display());
+            Some(path)
+        }
+        Err(e) => {
+            println!("Failed to save screenshot to {}: {}", path.display(), e);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests;
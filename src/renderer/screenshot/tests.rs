@@ -0,0 +1,27 @@
+use super::format_timestamp;
+
+#[test]
+fn epoch() {
+    assert_eq!(format_timestamp(0), "1970-01-01_00-00-00");
+    assert_eq!(format_timestamp(59), "1970-01-01_00-00-59");
+    assert_eq!(format_timestamp(86399), "1970-01-01_23-59-59");
+    assert_eq!(format_timestamp(86400), "1970-01-02_00-00-00");
+}
+
+#[test]
+fn leap_days() {
+    assert_eq!(format_timestamp(951782400), "2000-02-29_00-00-00");
+    assert_eq!(format_timestamp(951868800), "2000-03-01_00-00-00");
+    assert_eq!(format_timestamp(1709208000), "2024-02-29_12-00-00");
+    // 2100 isn't a leap year
+    assert_eq!(format_timestamp(4107456000), "2100-02-28_00-00-00");
+    assert_eq!(format_timestamp(4107542400), "2100-03-01_00-00-00");
+}
+
+#[test]
+fn recent_dates() {
+    assert_eq!(format_timestamp(1234567890), "2009-02-13_23-31-30");
+    assert_eq!(format_timestamp(1700000000), "2023-11-14_22-13-20");
+    assert_eq!(format_timestamp(1798761599), "2026-12-31_23-59-59");
+    assert_eq!(format_timestamp(1798761600), "2027-01-01_00-00-00");
+}
@@ -1,21 +1,27 @@
-use std::sync::Arc;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
 use vulkano::{
     device::{physical::PhysicalDevice, Device, Queue},
     format::Format,
     image::{view::ImageView, ImageUsage, SwapchainImage},
     swapchain::{
-        AcquireError, PresentMode, Surface, Swapchain, SwapchainAcquireFuture, SwapchainCreateInfo,
+        AcquireError, ColorSpace, PresentMode, Surface, Swapchain, SwapchainAcquireFuture, SwapchainCreateInfo,
         SwapchainCreationError,
     },
     sync::{self, FlushError, GpuFuture},
 };
 use winit::window::Window;
 
+use super::color_space::choose_surface_format;
+
 #[derive(PartialEq, Eq, Clone, Copy, Debug)]
 pub struct ImagesState {
     pub count: usize,
     pub format: Format,
+    pub color_space: ColorSpace,
 }
 
 pub struct SwapchainState {
@@ -30,57 +36,127 @@ pub struct ManagedSwapchain {
     previous_frame_end: Option<Box<dyn GpuFuture>>,
     surface: Arc<Surface<Window>>,
     device: Arc<Device>,
+    present_modes: Vec<PresentMode>,
+    present_mode: PresentMode,
     recreate_on_next_frame: bool,
+    /// Spaces out the attempts to recreate the swapchain after a failure
+    retry: Backoff,
+    device_lost: bool,
+}
+
+/// Why no frame could be drawn
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FrameError {
+    /// Nothing can be drawn right now, e.g. the swapchain couldn't be
+    /// recreated yet. Drawing can be tried again on the next frame.
+    Skipped,
+    /// The device was lost, everything created from it has to be rebuilt
+    DeviceLost,
+}
+
+const MIN_RETRY_DELAY: Duration = Duration::from_millis(10);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(2);
+
+/// Spaces out the retries of something that failed, the delay doubles after
+/// every failure until it succeeds
+pub struct Backoff {
+    /// When the next attempt is made after a failure
+    next_retry: Option<Instant>,
+    delay: Duration,
+}
+
+impl Backoff {
+    pub fn new() -> Self {
+        Backoff {
+            next_retry: None,
+            delay: MIN_RETRY_DELAY,
+        }
+    }
+
+    /// Whether the next attempt has to wait longer
+    pub fn is_waiting(&self) -> bool {
+        self.next_retry
+            .map_or(false, |next_retry| Instant::now() < next_retry)
+    }
+
+    /// Schedules the next attempt, returns how long it is away
+    pub fn failed(&mut self) -> Duration {
+        let delay = self.delay;
+        self.next_retry = Some(Instant::now() + delay);
+        self.delay = (delay * 2).min(MAX_RETRY_DELAY);
+        delay
+    }
+
+    pub fn succeeded(&mut self) {
+        *self = Backoff::new();
+    }
 }
 
 impl ManagedSwapchain {
+    /// Creates a swapchain for the window's current size. Fails if the window
+    /// is minimized, as there is nothing to present to.
     pub fn create(
         surface: Arc<Surface<Window>>,
         physical: PhysicalDevice,
         device: Arc<Device>,
         present_mode: PresentMode,
-    ) -> Self {
+        hdr: bool,
+    ) -> Result<Self, String> {
         let surface_capabilities = physical
             .surface_capabilities(&surface, Default::default())
-            .unwrap();
-        let image_format = Some(
-            physical
+            .map_err(|e| format!("failed to get the surface capabilities: {:?}", e))?;
+        let (image_format, image_color_space) = choose_surface_format(
+            &physical
                 .surface_formats(&surface, Default::default())
-                .unwrap()[0]
-                .0,
+                .map_err(|e| format!("failed to get the surface formats: {:?}", e))?,
+            hdr,
         );
-        let image_extent = surface.window().inner_size().into();
+        let image_extent: [u32; 2] = surface.window().inner_size().into();
+        if image_extent.contains(&0) {
+            return Err("the window is minimized".to_string());
+        }
+        let present_modes = physical
+            .surface_present_modes(&surface)
+            .map_err(|e| format!("failed to get the present modes: {:?}", e))?
+            .collect::<Vec<_>>();
 
         let (swapchain, images) = Swapchain::new(
             device.clone(),
             surface.clone(),
             SwapchainCreateInfo {
                 min_image_count: surface_capabilities.min_image_count,
-                image_format,
+                image_format: Some(image_format),
+                image_color_space,
                 image_extent,
-                image_usage: ImageUsage::color_attachment(),
+                // Transfer source to take screenshots of the whole window
+                image_usage: ImageUsage {
+                    color_attachment: true,
+                    transfer_source: surface_capabilities.supported_usage_flags.transfer_source,
+                    ..ImageUsage::none()
+                },
                 composite_alpha: surface_capabilities
                     .supported_composite_alpha
                     .iter()
                     .next()
-                    .unwrap(),
+                    .ok_or("the surface supports no composite alpha mode")?,
                 present_mode,
                 ..Default::default()
             },
         )
-        .unwrap();
+        .map_err(|e| format!("failed to create the swapchain: {:?}", e))?;
 
         let images = images
             .into_iter()
             .map(|image| ImageView::new_default(image).unwrap())
             .collect::<Vec<_>>();
 
-        Self {
+        Ok(Self {
             state: SwapchainState {
                 size: image_extent,
                 images_state: ImagesState {
                     count: images.len(),
-                    format: image_format.unwrap(),
+                    format: image_format,
+                    color_space: image_color_space,
                 },
             },
             swap_chain: swapchain,
@@ -88,8 +164,12 @@ impl ManagedSwapchain {
             previous_frame_end: Some(sync::now(device.clone()).boxed()),
             surface,
             device,
+            present_modes,
+            present_mode,
             recreate_on_next_frame: false,
-        }
+            retry: Backoff::new(),
+            device_lost: false,
+        })
     }
 
     pub fn state(&self) -> &SwapchainState {
@@ -100,15 +180,50 @@ impl ManagedSwapchain {
         self.recreate_on_next_frame = true;
     }
 
-    pub fn recreate(&mut self) {
+    pub fn present_mode(&self) -> PresentMode {
+        self.present_mode
+    }
+
+    /// The present modes the surface supports
+    pub fn present_modes(&self) -> &[PresentMode] {
+        &self.present_modes
+    }
+
+    /// Switches the present mode, the swapchain gets recreated on the next frame
+    pub fn set_present_mode(&mut self, present_mode: PresentMode) {
+        if present_mode != self.present_mode && self.present_modes.contains(&present_mode) {
+            self.present_mode = present_mode;
+            self.recreate_on_next_frame = true;
+        }
+    }
+
+    /// Recreates the swapchain for the current window size. A failed attempt
+    /// is retried on a later frame, waiting longer after every failure.
+    fn recreate(&mut self) -> Result<(), FrameError> {
+        if self.retry.is_waiting() {
+            return Err(FrameError::Skipped);
+        }
+
         let dimensions: [u32; 2] = self.surface.window().inner_size().into();
         let (new_swapchain, new_images) = match self.swap_chain.recreate(SwapchainCreateInfo {
             image_extent: dimensions,
+            present_mode: self.present_mode,
             ..self.swap_chain.create_info()
         }) {
             Ok(r) => r,
-            Err(SwapchainCreationError::ImageExtentNotSupported { .. }) => return,
-            Err(e) => panic!("Failed to recreate swapchain: {:?}", e),
+            Err(SwapchainCreationError::DeviceLost) => {
+                self.set_device_lost();
+                return Err(FrameError::DeviceLost);
+            }
+            Err(e) => {
+                let delay = self.retry.failed();
+                println!(
+                    "Failed to recreate swapchain, retrying in {:?}: {:?}",
+                    delay, e
+                );
+                self.recreate_on_next_frame = true;
+                return Err(FrameError::Skipped);
+            }
         };
         self.swap_chain = new_swapchain;
         let new_images = new_images
@@ -116,46 +231,71 @@ impl ManagedSwapchain {
             .map(|image| ImageView::new_default(image).unwrap())
             .collect::<Vec<_>>();
 
-        self.image_views = new_images;
-
         self.state = SwapchainState {
             size: dimensions,
-            images_state: self.state.images_state,
+            images_state: ImagesState {
+                count: new_images.len(),
+                ..self.state.images_state
+            },
         };
+
+        self.image_views = new_images;
+        self.recreate_on_next_frame = false;
+        self.retry.succeeded();
+
+        Ok(())
     }
 
-    pub fn take_previous_frame_end(&mut self) -> Option<Box<dyn GpuFuture>> {
-        self.previous_frame_end.take()
+    /// Marks the device as lost. Nothing is drawn until the renderer is
+    /// rebuilt, which needs the swapchain and everything using it dropped.
+    fn set_device_lost(&mut self) {
+        self.device_lost = true;
+        self.previous_frame_end = None;
     }
 
-    pub fn acquire_frame(&mut self) -> (SwapchainFrame, SwapchainAcquireFuture<Window>) {
+    pub fn acquire_frame(
+        &mut self,
+    ) -> Result<(SwapchainFrame, SwapchainAcquireFuture<Window>), FrameError> {
+        if self.device_lost {
+            return Err(FrameError::DeviceLost);
+        }
+
         if self.recreate_on_next_frame {
-            self.recreate();
-            self.recreate_on_next_frame = false;
+            self.recreate()?;
         }
 
         let mut tries = 0;
         loop {
             tries += 1;
             if tries > 10 {
-                panic!("Failed to acquire next image after 10 tries");
+                println!("Failed to acquire next image after 10 tries");
+                self.recreate_on_next_frame = true;
+                return Err(FrameError::Skipped);
             }
 
             let next = vulkano::swapchain::acquire_next_image(self.swap_chain.clone(), None);
 
             let (image_num, suboptimal, acquire_future) = match next {
                 Ok(r) => r,
-                // TODO: Handle more errors, e.g. DeviceLost, by re-creating the entire graphics chain
                 Err(AcquireError::OutOfDate) => {
-                    self.recreate();
+                    self.recreate()?;
                     continue;
                 }
-                Err(e) => panic!("Failed to acquire next image: {:?}", e),
+                Err(AcquireError::DeviceLost) => {
+                    self.set_device_lost();
+                    return Err(FrameError::DeviceLost);
+                }
+                Err(e) => {
+                    println!("Failed to acquire next image: {:?}", e);
+                    self.recreate_on_next_frame = true;
+                    return Err(FrameError::Skipped);
+                }
             };
 
+            // The image can still be presented, the swapchain is recreated
+            // for the next one
             if suboptimal {
-                self.recreate();
-                continue;
+                self.recreate_on_next_frame = true;
             }
 
             let frame = SwapchainFrame {
@@ -165,7 +305,7 @@ impl ManagedSwapchain {
                 managed_swap_chain: self,
             };
 
-            return (frame, acquire_future);
+            return Ok((frame, acquire_future));
         }
     }
 }
@@ -180,7 +320,19 @@ pub struct SwapchainFrame<'a> {
 }
 
 impl<'a> SwapchainFrame<'a> {
-    pub fn present(mut self, queue: &Arc<Queue>, after_future: Box<dyn GpuFuture>) {
+    /// The future of the previous frame, the new frame's work has to start after it
+    pub fn take_previous_frame_end(&mut self) -> Box<dyn GpuFuture> {
+        let sc = &mut self.managed_swap_chain;
+        sc.previous_frame_end
+            .take()
+            .unwrap_or_else(|| sync::now(sc.device.clone()).boxed())
+    }
+
+    pub fn present(
+        mut self,
+        queue: &Arc<Queue>,
+        after_future: Box<dyn GpuFuture>,
+    ) -> Result<(), FrameError> {
         self.presented = true;
 
         let sc = &mut self.managed_swap_chain;
@@ -195,6 +347,10 @@ impl<'a> SwapchainFrame<'a> {
                 // https://github.com/vulkano-rs/vulkano/issues/627
                 match future.wait(None) {
                     Ok(x) => x,
+                    Err(FlushError::DeviceLost) => {
+                        sc.set_device_lost();
+                        return Err(FrameError::DeviceLost);
+                    }
                     Err(err) => println!("err: {:?}", err),
                 }
                 sc.previous_frame_end = Some(future.boxed());
@@ -203,11 +359,17 @@ impl<'a> SwapchainFrame<'a> {
                 sc.recreate_on_next_frame = true;
                 sc.previous_frame_end = Some(sync::now(sc.device.clone()).boxed());
             }
+            Err(FlushError::DeviceLost) => {
+                sc.set_device_lost();
+                return Err(FrameError::DeviceLost);
+            }
             Err(e) => {
                 println!("Failed to flush future: {:?}", e);
                 sc.previous_frame_end = Some(sync::now(sc.device.clone()).boxed());
             }
         }
+
+        Ok(())
     }
 
     pub fn swap_chain_state(&self) -> &SwapchainState {
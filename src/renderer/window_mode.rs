@@ -0,0 +1,172 @@
+use winit::{
+    dpi::{LogicalSize, PhysicalPosition},
+    window::{Fullscreen, Window, WindowBuilder},
+};
+
+use crate::settings_file::SettingsFile;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FullscreenMode {
+    /// A borderless window covering the monitor, switching to it is fast
+    Borderless,
+    /// Takes over the monitor's video mode, which can lower the latency
+    Exclusive,
+}
+
+impl FullscreenMode {
+    pub const ALL: [FullscreenMode; 2] = [FullscreenMode::Borderless, FullscreenMode::Exclusive];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            FullscreenMode::Borderless => "Borderless",
+            FullscreenMode::Exclusive => "Exclusive",
+        }
+    }
+}
+
+/// The fullscreen state the window should be in, `None` for windowed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FullscreenTarget {
+    pub mode: FullscreenMode,
+    /// The monitor name, the window's current monitor is used if it isn't found
+    pub monitor: Option<String>,
+}
+
+impl FullscreenTarget {
+    /// The winit fullscreen state for this target. Exclusive fullscreen uses
+    /// the monitor's largest video mode with the highest refresh rate.
+    pub fn to_winit(&self, window: &Window) -> Option<Fullscreen> {
+        let monitor = self
+            .monitor
+            .as_ref()
+            .and_then(|name| {
+                window
+                    .available_monitors()
+                    .find(|monitor| monitor.name().as_ref() == Some(name))
+            })
+            .or_else(|| window.current_monitor())?;
+
+        match self.mode {
+            FullscreenMode::Borderless => Some(Fullscreen::Borderless(Some(monitor))),
+            FullscreenMode::Exclusive => monitor
+                .video_modes()
+                .max_by_key(|mode| {
+                    let size = mode.size();
+                    (size.width * size.height, mode.refresh_rate())
+                })
+                .map(Fullscreen::Exclusive),
+        }
+    }
+}
+
+pub fn monitor_names(window: &Window) -> Vec<String> {
+    window
+        .available_monitors()
+        .filter_map(|monitor| monitor.name())
+        .collect()
+}
+
+/// The smallest window size that is restored, in logical pixels
+const MIN_WINDOW_SIZE: [u32; 2] = [320, 240];
+
+/// Windows moves minimized windows to this position
+const MINIMIZED_POSITION: i32 = -32000;
+
+/// Whether a window position is the one of a minimized window
+fn is_minimized_position([x, y]: [i32; 2]) -> bool {
+    x <= MINIMIZED_POSITION || y <= MINIMIZED_POSITION
+}
+
+/// The windowed size, position and maximized state, kept between sessions
+#[derive(Debug, Clone, PartialEq)]
+pub struct WindowGeometry {
+    /// The inner size in logical pixels
+    pub size: [u32; 2],
+    pub position: Option<[i32; 2]>,
+    pub maximized: bool,
+}
+
+impl WindowGeometry {
+    pub fn new(size: [u32; 2]) -> Self {
+        WindowGeometry {
+            size,
+            position: None,
+            maximized: false,
+        }
+    }
+
+    /// Loads the geometry from the settings, keeping `self` for missing values.
+    /// The size is kept to at least [`MIN_WINDOW_SIZE`], and the position of a
+    /// minimized window is ignored, in case one was saved.
+    pub fn load(mut self, settings: &SettingsFile) -> Self {
+        if let (Some(width), Some(height)) = (
+            settings.get::<u32>("window_width"),
+            settings.get::<u32>("window_height"),
+        ) {
+            self.size = [
+                width.max(MIN_WINDOW_SIZE[0]),
+                height.max(MIN_WINDOW_SIZE[1]),
+            ];
+        }
+        if let (Some(x), Some(y)) = (settings.get("window_x"), settings.get("window_y")) {
+            if !is_minimized_position([x, y]) {
+                self.position = Some([x, y]);
+            }
+        }
+        if let Some(maximized) = settings.get("window_maximized") {
+            self.maximized = maximized;
+        }
+        self
+    }
+
+    pub fn save(&self, settings: &mut SettingsFile) {
+        settings.set("window_width", self.size[0]);
+        settings.set("window_height", self.size[1]);
+        if let Some([x, y]) = self.position {
+            settings.set("window_x", x);
+            settings.set("window_y", y);
+        }
+        settings.set("window_maximized", self.maximized);
+    }
+
+    /// Reads the geometry of a window, this should only be done while it is
+    /// windowed. The size and position are kept while maximized, so that
+    /// unmaximizing after a restart goes back to them. Nothing is read while
+    /// the window is minimized, it has a zero size then and Windows moves it
+    /// far off screen.
+    pub fn update_from_window(&mut self, window: &Window) {
+        let inner_size = window.inner_size();
+        if inner_size.width == 0 || inner_size.height == 0 {
+            return;
+        }
+        let position = window
+            .outer_position()
+            .ok()
+            .map(|position| [position.x, position.y]);
+        if position.map_or(false, is_minimized_position) {
+            return;
+        }
+
+        self.maximized = window.is_maximized();
+        if self.maximized {
+            return;
+        }
+
+        let size = inner_size.to_logical::<u32>(window.scale_factor());
+        self.size = [size.width, size.height];
+        if position.is_some() {
+            self.position = position;
+        }
+    }
+
+    pub fn apply(&self, builder: WindowBuilder) -> WindowBuilder {
+        let builder = builder
+            .with_inner_size(LogicalSize::new(self.size[0], self.size[1]))
+            .with_min_inner_size(LogicalSize::new(MIN_WINDOW_SIZE[0], MIN_WINDOW_SIZE[1]))
+            .with_maximized(self.maximized);
+        match self.position {
+            Some([x, y]) => builder.with_position(PhysicalPosition::new(x, y)),
+            None => builder,
+        }
+    }
+}
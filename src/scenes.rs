@@ -2,7 +2,7 @@ use std::sync::Arc;
 
 use vulkano::{
     device::Device,
-    image::{view::ImageView, AttachmentImage},
+    image::{view::ImageView, AttachmentImage, ImageUsage},
 };
 
 use crate::{gui::GuiState, renderer::swapchain::ImagesState};
@@ -29,7 +29,13 @@ impl SceneSwapchain {
         }
     }
 
-    pub fn get_next_image(&mut self, state: &mut GuiState, size: [u32; 2]) -> &SceneImage {
+    /// The image to draw the scene to for the current frame. There is none
+    /// if the scene has no area or the images couldn't be created.
+    pub fn get_next_image(&mut self, state: &mut GuiState, size: [u32; 2]) -> Option<&SceneImage> {
+        if size[0] == 0 || size[1] == 0 {
+            return None;
+        }
+
         let image_state = state.frame.swap_chain_state().images_state;
 
         if Some(image_state) != self.image_state || self.scene_view_size != size {
@@ -40,15 +46,31 @@ impl SceneSwapchain {
 
             // Create new images
             for _ in 0..image_state.count {
-                let image = ImageView::new_default(
-                    AttachmentImage::sampled_input_attachment(
-                        self.device.clone(),
-                        size,
-                        image_state.format,
-                    )
-                    .expect("Failed to create scene image"),
-                )
-                .expect("Failed to create scene image view");
+                // Transfer destination so that supersampled notes can be
+                // downsampled into it, and source for screenshots
+                let usage = ImageUsage {
+                    sampled: true,
+                    input_attachment: true,
+                    color_attachment: true,
+                    transfer_source: true,
+                    transfer_destination: true,
+                    ..ImageUsage::none()
+                };
+                let image = match AttachmentImage::with_usage(
+                    self.device.clone(),
+                    size,
+                    image_state.format,
+                    usage,
+                ) {
+                    Ok(image) => ImageView::new_default(image)
+                        .expect("Failed to create scene image view"),
+                    Err(e) => {
+                        // Tried again on the next frame
+                        println!("Failed to create scene image: {:?}", e);
+                        self.image_state = None;
+                        return None;
+                    }
+                };
 
                 let id = state.gui.register_user_image_view(image.clone());
 
@@ -59,6 +81,6 @@ impl SceneSwapchain {
             self.scene_view_size = size;
         }
 
-        &self.scene_images[state.frame.image_num]
+        self.scene_images.get(state.frame.image_num)
     }
 }
@@ -0,0 +1,102 @@
+use std::{collections::BTreeMap, env, fs, path::PathBuf, str::FromStr};
+
+const SETTINGS_FILE_NAME: &str = "wasabi-settings.txt";
+const CONFIG_DIR_NAME: &str = "wasabi";
+
+/// The directory next to the executable, where older versions kept the settings
+fn app_dir() -> PathBuf {
+    env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(|dir| dir.to_path_buf()))
+        .unwrap_or_default()
+}
+
+/// The platform's per user configuration directory, `%APPDATA%\wasabi` on
+/// Windows, `~/Library/Application Support/wasabi` on macOS and
+/// `$XDG_CONFIG_HOME/wasabi` or `~/.config/wasabi` elsewhere. Falls back to
+/// [`app_dir`] if the environment doesn't say where it is.
+pub fn config_dir() -> PathBuf {
+    let env_dir = |name: &str| {
+        env::var_os(name)
+            .filter(|dir| !dir.is_empty())
+            .map(PathBuf::from)
+    };
+
+    let base = if cfg!(windows) {
+        env_dir("APPDATA")
+    } else if cfg!(target_os = "macos") {
+        env_dir("HOME").map(|home| home.join("Library").join("Application Support"))
+    } else {
+        env_dir("XDG_CONFIG_HOME").or_else(|| env_dir("HOME").map(|home| home.join(".config")))
+    };
+
+    match base {
+        Some(base) => base.join(CONFIG_DIR_NAME),
+        None => app_dir(),
+    }
+}
+
+/// Settings that are kept between sessions, stored as `key=value` lines in a
+/// file in the [`config_dir`]
+pub struct SettingsFile {
+    path: PathBuf,
+    values: BTreeMap<String, String>,
+}
+
+impl SettingsFile {
+    /// Loads the settings file, a missing or unreadable file gives empty settings
+    pub fn load() -> Self {
+        let path = config_dir().join(SETTINGS_FILE_NAME);
+
+        // Older versions kept the settings next to the executable, they are
+        // read from there until the settings are saved to the new place
+        let values = fs::read_to_string(&path)
+            .or_else(|_| fs::read_to_string(app_dir().join(SETTINGS_FILE_NAME)))
+            .map(|text| {
+                text.lines()
+                    .filter_map(|line| line.split_once('='))
+                    .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+                    .collect()
+            })
+            .unwrap_or_default();
+
+        SettingsFile { path, values }
+    }
+
+    pub fn get<T: FromStr>(&self, key: &str) -> Option<T> {
+        self.values.get(key).and_then(|value| value.parse().ok())
+    }
+
+    pub fn set(&mut self, key: &str, value: impl ToString) {
+        self.values.insert(key.to_string(), value.to_string());
+    }
+
+    pub fn remove(&mut self, key: &str) {
+        self.values.remove(key);
+    }
+
+    pub fn save(&self) {
+        let text: String = self
+            .values
+            .iter()
+            .map(|(key, value)| format!("{}={}\n", key, value))
+            .collect();
+
+        if let Some(dir) = self.path.parent() {
+            if let Err(e) = fs::create_dir_all(dir) {
+                println!("Failed to create {}: {}", dir.display(), e);
+            }
+        }
+
+        if let Err(e) = fs::write(&self.path, text) {
+            println!("Failed to save settings to {}: {}", self.path.display(), e);
+        }
+    }
+
+    /// Loads the file, changes it and saves it right away
+    pub fn update(change: impl FnOnce(&mut SettingsFile)) {
+        let mut settings = SettingsFile::load();
+        change(&mut settings);
+        settings.save();
+    }
+}